@@ -0,0 +1,369 @@
+//! Plays in the terminal, with no window needed: hot-seat, against the
+//! computer, or the computer against itself.
+//!
+//! cargo run --bin cli -- --ai b --depth 4
+//! cargo run --bin cli -- --ai b --engine "target/release/engine --weights weights.txt"
+//!
+//! Moves are typed in coordinate notation: a column a-d, a row 1-4 and a
+//! layer 1-4, so `b32` is the second column, third row, second layer.
+//! `resign` gives up, and `draw` or `takeback` asks the other player, who
+//! answers y or n; the computer gives moves back but never agrees a draw.
+
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    process,
+};
+use tic_tac_toc_toe::{
+    common::Location,
+    engine::ExternalEngine,
+    logic::{
+        computer_player::{self, search, CancelToken},
+        search::Limits,
+        GamePlayStatus, Offer, Player, TTTTState,
+    },
+    text_display::render_board,
+};
+
+const USAGE: &str = "usage: cli [--ai a|b|both|none] [--depth N] [--engine COMMAND]";
+
+const HELP: &str = "\
+Type a move like b32: column a-d, row 1-4, layer 1-4.
+`resign` gives up, `draw` offers a draw and `takeback` asks to take back
+your last move. `quit` leaves the game.";
+
+struct Options {
+    computer: Vec<Player>,
+    depth: u8,
+    /// An engine program to play the computer's moves instead of the
+    /// built-in search.
+    engine: Option<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        computer: vec![],
+        depth: 3,
+        engine: None,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--ai" => {
+                options.computer = match value.to_ascii_lowercase().as_str() {
+                    "a" => vec![Player::A],
+                    "b" => vec![Player::B],
+                    "both" => vec![Player::A, Player::B],
+                    "none" => vec![],
+                    _ => return Err(format!("bad value for --ai: {}", value)),
+                }
+            }
+            "--depth" => {
+                options.depth = value
+                    .parse()
+                    .map_err(|_| format!("bad value for --depth: {}", value))?
+            }
+            "--engine" => options.engine = Some(value),
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+    Ok(options)
+}
+
+fn read_line(input: &mut impl BufRead) -> Option<String> {
+    io::stdout().flush().ok()?;
+    let mut line = String::new();
+    if input.read_line(&mut line).ok()? == 0 {
+        println!();
+        return None;
+    }
+    Some(line)
+}
+
+/// A game between turns: the position, the moves that led to it, and any
+/// offer waiting for an answer.
+#[derive(Debug, Clone, PartialEq)]
+struct Game {
+    state: TTTTState,
+    moves: Vec<Location>,
+    /// Who offered what, while the other player hasn't said y or n.
+    offer: Option<(Player, Offer)>,
+}
+
+impl Game {
+    fn new() -> Self {
+        Self {
+            state: TTTTState::new(),
+            moves: vec![],
+            offer: None,
+        }
+    }
+
+    /// What to ask the person whose input comes next.
+    fn prompt(&self, player: Player) -> String {
+        match self.offer {
+            Some((by, offer)) => {
+                let asks = match offer {
+                    Offer::Draw => "offers a draw",
+                    Offer::Takeback => "asks to take back their last move",
+                };
+                format!(
+                    "{} {}. {}, do you accept? (y/n) ",
+                    by,
+                    asks,
+                    by.other_player()
+                )
+            }
+            None => format!("{} to move: ", player),
+        }
+    }
+
+    /// The board after a change, set apart from what came before.
+    fn board(&self) -> String {
+        format!("\n{}", render_board(&self.state.board))
+    }
+
+    /// Makes `offer` for `by`, which the computer answers straight away.
+    fn offer(&mut self, by: Player, offer: Offer, computer: &[Player]) -> String {
+        if offer == Offer::Takeback && self.moves.len() < 2 {
+            return "You have no move to take back\n".to_string();
+        }
+        self.offer = Some((by, offer));
+        if !computer.contains(&by.other_player()) {
+            // The other player answers with their next line.
+            String::new()
+        } else if computer_player::accepts(offer) {
+            self.accept();
+            self.board()
+        } else {
+            self.offer = None;
+            "Declined\n".to_string()
+        }
+    }
+
+    /// Agrees to the offer waiting for an answer.
+    fn accept(&mut self) {
+        let Some((by, offer)) = self.offer.take() else {
+            return;
+        };
+        match offer {
+            Offer::Draw => self.state.agree_draw(),
+            Offer::Takeback => self.state.take_back(by, &mut self.moves),
+        }
+        .expect("the offer was checked");
+    }
+}
+
+/// What `line`, typed by `player`, does to `game`, and what to print back.
+/// `None` means the player quit.
+fn handle_line(
+    game: &Game,
+    player: Player,
+    computer: &[Player],
+    line: &str,
+) -> (Option<Game>, String) {
+    let mut game = game.clone();
+    if game.offer.is_some() {
+        return match line.trim() {
+            "y" | "yes" => {
+                game.accept();
+                let board = game.board();
+                (Some(game), board)
+            }
+            "n" | "no" => {
+                game.offer = None;
+                (Some(game), "Declined\n".to_string())
+            }
+            _ => (Some(game), String::new()),
+        };
+    }
+    let output = match line.trim() {
+        "quit" | "exit" | "q" => return (None, String::new()),
+        "help" | "?" => format!("{}\n", HELP),
+        "resign" => {
+            game.state.resign(player).expect("the game isn't over");
+            String::new()
+        }
+        "draw" => game.offer(player, Offer::Draw, computer),
+        "takeback" => game.offer(player, Offer::Takeback, computer),
+        "" => String::new(),
+        text => match text.parse::<Location>() {
+            Ok(loc) if game.state.board.at(loc).is_some() => format!("{} is taken\n", loc),
+            Ok(loc) => {
+                game.state.play(loc).expect("only free spots are played");
+                game.moves.push(loc);
+                game.board()
+            }
+            Err(e) => format!("{}\n", e),
+        },
+    };
+    (Some(game), output)
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    let mut engine = options.engine.as_ref().map(|command| {
+        ExternalEngine::start(command, Limits::depth(options.depth)).unwrap_or_else(|e| {
+            eprintln!("couldn't start {}: {}", command, e);
+            process::exit(1);
+        })
+    });
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut game = Game::new();
+    print!("{}", render_board(&game.state.board));
+    while let GamePlayStatus::Playing(player) = game.state.status {
+        if game.offer.is_none() && options.computer.contains(&player) {
+            let state = &game.state;
+            let choice = match &mut engine {
+                Some(engine) => engine.best_move(state).unwrap_or_else(|e| {
+                    eprintln!("the engine failed: {}", e);
+                    process::exit(1);
+                }),
+                None => search(state, options.depth, &CancelToken::new()),
+            };
+            let loc = match choice {
+                Some(loc) if state.board.at(loc).is_none() => loc,
+                _ => {
+                    println!("{} gave up", player);
+                    process::exit(1);
+                }
+            };
+            println!("{} plays {}", player, loc);
+            game.state.play(loc).expect("only free spots are played");
+            game.moves.push(loc);
+            print!("{}", game.board());
+            continue;
+        }
+        print!("{}", game.prompt(player));
+        let next = read_line(&mut input).and_then(|line| {
+            let (next, output) = handle_line(&game, player, &options.computer, &line);
+            print!("{}", output);
+            next
+        });
+        match next {
+            Some(next) => game = next,
+            None => {
+                println!("Game abandoned");
+                process::exit(1);
+            }
+        }
+    }
+    println!("{}", game.state.status);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tic_tac_toc_toe::logic::{DrawReason, WinReason};
+
+    /// Types `lines` in turn, as whoever's asked, and returns the game and
+    /// what was printed after the last one.
+    fn type_lines(computer: &[Player], lines: &[&str]) -> (Game, String) {
+        let mut game = Game::new();
+        let mut output = String::new();
+        for line in lines {
+            let GamePlayStatus::Playing(player) = game.state.status else {
+                panic!("the game's over before {}", line);
+            };
+            let (next, printed) = handle_line(&game, player, computer, line);
+            game = next.expect("nobody quit");
+            output = printed;
+        }
+        (game, output)
+    }
+
+    #[test]
+    fn a_move_is_played_and_the_board_shown() {
+        let (game, output) = type_lines(&[], &["b32\n"]);
+        let loc = "b32".parse().unwrap();
+        assert_eq!(game.state.board.at(loc), Some(Player::A));
+        assert_eq!(game.moves, [loc]);
+        assert_eq!(output, format!("\n{}", render_board(&game.state.board)));
+    }
+
+    #[test]
+    fn taken_spots_and_typos_are_explained() {
+        let (game, output) = type_lines(&[], &["b32", "b32"]);
+        assert_eq!(output, "b32 is taken\n");
+        assert_eq!(game.moves.len(), 1);
+
+        let (_, output) = type_lines(&[], &["e32"]);
+        assert!(output.contains("isn't a spot"), "{}", output);
+    }
+
+    #[test]
+    fn resigning_ends_the_game() {
+        let (game, _) = type_lines(&[], &["a11", "resign"]);
+        assert_eq!(
+            game.state.status,
+            GamePlayStatus::Win {
+                player: Player::A,
+                reason: WinReason::Resignation
+            }
+        );
+    }
+
+    #[test]
+    fn the_other_player_answers_a_draw_offer() {
+        let (game, output) = type_lines(&[], &["draw"]);
+        assert_eq!(game.offer, Some((Player::A, Offer::Draw)));
+        assert_eq!(output, "");
+        assert_eq!(
+            game.prompt(Player::A),
+            "Player A offers a draw. Player B, do you accept? (y/n) "
+        );
+
+        let (game, _) = type_lines(&[], &["draw", "maybe", "y"]);
+        assert_eq!(
+            game.state.status,
+            GamePlayStatus::Draw {
+                reason: DrawReason::Agreement
+            }
+        );
+
+        let (game, output) = type_lines(&[], &["draw", "n"]);
+        assert_eq!(output, "Declined\n");
+        assert_eq!(game, Game::new());
+    }
+
+    #[test]
+    fn a_takeback_needs_a_move_to_take_back() {
+        let (game, output) = type_lines(&[], &["a11", "takeback"]);
+        assert_eq!(output, "You have no move to take back\n");
+        assert_eq!(game.offer, None);
+
+        let (game, _) = type_lines(&[], &["a11", "b11", "takeback", "y"]);
+        assert_eq!(game, Game::new());
+    }
+
+    #[test]
+    fn the_computer_gives_moves_back_but_never_agrees_a_draw() {
+        let computer = [Player::B];
+        let (game, output) = type_lines(&computer, &["draw"]);
+        assert_eq!(output, "Declined\n");
+        assert_eq!(game, Game::new());
+
+        let mut game = Game::new();
+        for loc in ["a11", "b11"] {
+            game.state.play(loc.parse().unwrap()).unwrap();
+            game.moves.push(loc.parse().unwrap());
+        }
+        let (game, _) = handle_line(&game, Player::A, &computer, "takeback");
+        assert_eq!(game, Some(Game::new()));
+    }
+
+    #[test]
+    fn quitting_leaves_the_game() {
+        let (game, _) = handle_line(&Game::new(), Player::A, &[], "quit\n");
+        assert_eq!(game, None);
+    }
+}
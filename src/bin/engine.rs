@@ -0,0 +1,133 @@
+//! The computer player as a standalone engine, spoken to over stdin and
+//! stdout with the protocol in `tic_tac_toc_toe::engine`.
+//!
+//! cargo run --release --bin engine -- --weights weights.txt
+
+use std::{
+    env,
+    io::{self, BufRead},
+    process,
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+use tic_tac_toc_toe::{
+    engine::{Command, Response},
+    logic::{
+        computer_player::{CancelToken, Eval, Ponderer, SearchHandle, SharedEval},
+        network::Network,
+        search::Limits,
+        GamePlayStatus, TTTTState,
+    },
+};
+
+const USAGE: &str = "usage: engine [--weights FILE]";
+
+struct Search {
+    stop: CancelToken,
+    /// Hands back the ponderer it starts once it's moved.
+    thread: JoinHandle<Option<Ponderer>>,
+}
+
+impl Search {
+    /// Ends the search early; it still reports its move. Returns what it's
+    /// pondering since, if anything.
+    fn stop(self) -> Option<Ponderer> {
+        self.stop.cancel();
+        self.thread.join().ok().flatten()
+    }
+}
+
+/// Searches `state`, carrying on from `ponder` if it guessed this position.
+/// Once the move's reported, it ponders on the opponent's reply.
+fn go(eval: &SharedEval, state: &TTTTState, limits: Limits, ponder: Option<Ponderer>) -> Search {
+    let search = match ponder {
+        Some(ponder) => ponder.resolve_with(eval.clone(), state, limits),
+        None => SearchHandle::spawn_with(eval.clone(), state, limits),
+    };
+    let stop = search.stopper();
+    let (eval, mut state) = (eval.clone(), state.clone());
+    let thread = thread::spawn(move || {
+        let info = search.wait_info();
+        if let Some(info) = &info {
+            println!(
+                "{}",
+                Response::Info {
+                    depth: info.depth,
+                    score: info.score,
+                    nodes: info.nodes,
+                    pv: info.pv.clone(),
+                }
+            );
+        }
+        let best = info.map(|info| info.best);
+        println!("{}", Response::BestMove(best));
+        state.play(best?).ok()?;
+        matches!(state.status, GamePlayStatus::Playing(_))
+            .then(|| Ponderer::start_with(eval, &state, limits))
+    });
+    Search { stop, thread }
+}
+
+fn main() {
+    let mut name = "tttt-engine".to_string();
+    let mut eval: SharedEval = Arc::new(Eval);
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        match (flag.as_str(), args.next()) {
+            ("--weights", Some(weights)) => {
+                let network = Network::load(&weights).unwrap_or_else(|e| {
+                    eprintln!("couldn't load {}: {}", weights, e);
+                    process::exit(1);
+                });
+                name = format!("tttt-engine network ({})", weights);
+                eval = Arc::new(network);
+            }
+            _ => {
+                eprintln!("bad option {}\n{}", flag, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    let mut state = TTTTState::new();
+    let mut search: Option<Search> = None;
+    let mut ponder: Option<Ponderer> = None;
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match line.parse() {
+            Ok(command) => command,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        // Only `isready` may be answered while a search is running; anything
+        // else that would change what's being searched stops it first.
+        if !matches!(command, Command::IsReady) {
+            if let Some(search) = search.take() {
+                ponder = search.stop();
+            }
+        }
+        match command {
+            Command::Hello => {
+                println!("{}", Response::Id(name.clone()));
+                println!("{}", Response::HelloOk);
+            }
+            Command::IsReady => println!("{}", Response::ReadyOk),
+            Command::NewGame => {
+                state = TTTTState::new();
+                ponder = None;
+            }
+            Command::Position(position) => state = position,
+            Command::Go(limits) => search = Some(go(&eval, &state, limits, ponder.take())),
+            Command::Stop => (),
+            Command::Quit => return,
+        }
+    }
+    if let Some(search) = search {
+        search.stop();
+    }
+}
@@ -0,0 +1,150 @@
+//! Hosts network games for players to join, for example with
+//! `cargo run -- --connect 192.168.1.20:7878`, or from a browser over a
+//! WebSocket on the next port up. Clients on the local network can find it
+//! with `cargo run -- --lan`, and bots on this machine can play over HTTP.
+//! Metrics and admin actions are served on localhost too.
+//! Players and games are kept in an SQLite database, `tttt.db` unless
+//! `--database` says otherwise, so a restarted server carries on where it
+//! left off; `--database :memory:` keeps nothing. `--clock 180+2` plays
+//! every game against the clock: three minutes each plus two seconds a move,
+//! or `180+2b` for a Bronstein delay.
+//!
+//! cargo run --bin server -- --port 7878 --ws-port 7879 --discovery-port 7880 --bot-api-port 7881 --admin-port 7882
+
+use std::{
+    env,
+    net::{TcpListener, UdpSocket},
+    process, thread,
+    time::Duration,
+};
+use tic_tac_toc_toe::{
+    logic::clock::TimeControl,
+    net::{
+        admin::ADMIN_PORT,
+        bot_api::BOT_API_PORT,
+        discovery::DISCOVERY_PORT,
+        server::{Server, ServerConfig, PORT, WEBSOCKET_PORT},
+        store::Store,
+    },
+};
+
+const USAGE: &str = "usage: server [--host ADDRESS] [--port N] [--ws-port N] [--discovery-port N] [--bot-api-port N] [--admin-port N] \
+[--match-timeout SECONDS] [--computer-depth N] [--reconnect-grace SECONDS] [--database PATH] [--clock BASE[+BONUS[b]]]";
+
+const DATABASE: &str = "tttt.db";
+
+fn listen(host: &str, port: u16) -> TcpListener {
+    TcpListener::bind((host, port)).unwrap_or_else(|e| {
+        eprintln!("couldn't listen on {}:{}: {}", host, port, e);
+        process::exit(1);
+    })
+}
+
+fn main() {
+    let mut host = "0.0.0.0".to_string();
+    let mut port = PORT;
+    let mut ws_port = WEBSOCKET_PORT;
+    let mut discovery_port = DISCOVERY_PORT;
+    let mut bot_api_port = BOT_API_PORT;
+    let mut admin_port = ADMIN_PORT;
+    let mut config = ServerConfig::default();
+    let mut database = DATABASE.to_string();
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        match (flag.as_str(), args.next()) {
+            ("--host", Some(value)) => host = value,
+            ("--port", Some(value)) if value.parse::<u16>().is_ok() => {
+                port = value.parse().unwrap()
+            }
+            ("--ws-port", Some(value)) if value.parse::<u16>().is_ok() => {
+                ws_port = value.parse().unwrap()
+            }
+            ("--discovery-port", Some(value)) if value.parse::<u16>().is_ok() => {
+                discovery_port = value.parse().unwrap()
+            }
+            ("--bot-api-port", Some(value)) if value.parse::<u16>().is_ok() => {
+                bot_api_port = value.parse().unwrap()
+            }
+            ("--admin-port", Some(value)) if value.parse::<u16>().is_ok() => {
+                admin_port = value.parse().unwrap()
+            }
+            ("--match-timeout", Some(value)) if value.parse::<f64>().is_ok() => {
+                config.match_timeout = Duration::from_secs_f64(value.parse().unwrap())
+            }
+            ("--reconnect-grace", Some(value)) if value.parse::<f64>().is_ok() => {
+                config.reconnect_grace = Duration::from_secs_f64(value.parse().unwrap())
+            }
+            ("--database", Some(value)) => database = value,
+            ("--clock", Some(value)) if value.parse::<TimeControl>().is_ok() => {
+                config.clock = Some(value.parse().unwrap())
+            }
+            ("--computer-depth", Some(value)) if value.parse::<u8>().is_ok() => {
+                config.computer_depth = value.parse().unwrap()
+            }
+            _ => {
+                eprintln!("bad option {}\n{}", flag, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    let server = Store::open(&database)
+        .and_then(|store| Server::with_store(config, store))
+        .unwrap_or_else(|e| {
+            eprintln!("couldn't open the database {}: {}", database, e);
+            process::exit(1);
+        });
+    let tcp = listen(&host, port);
+    let websocket = listen(&host, ws_port);
+    // Bots and admins are trusted, so their endpoints are never served
+    // beyond this machine.
+    let bot_api = listen("127.0.0.1", bot_api_port);
+    let admin = listen("127.0.0.1", admin_port);
+    println!("listening on {} (TCP)", tcp.local_addr().unwrap());
+    println!(
+        "listening on {} (WebSocket)",
+        websocket.local_addr().unwrap()
+    );
+    // Discovery is a convenience, so the server carries on without it.
+    match UdpSocket::bind((host.as_str(), discovery_port)) {
+        Ok(socket) => {
+            println!(
+                "answering LAN discovery on {}",
+                socket.local_addr().unwrap()
+            );
+            let (lan_server, port) = (server.clone(), tcp.local_addr().unwrap().port());
+            thread::spawn(move || lan_server.serve_discovery(socket, port));
+        }
+        Err(e) => eprintln!("not answering LAN discovery: {}", e),
+    }
+    println!(
+        "serving the bot API on http://{}",
+        bot_api.local_addr().unwrap()
+    );
+    let bot_server = server.clone();
+    thread::spawn(move || {
+        if let Err(e) = bot_server.serve_bot_api(bot_api) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    });
+    println!("serving admin on http://{}", admin.local_addr().unwrap());
+    let admin_server = server.clone();
+    thread::spawn(move || {
+        if let Err(e) = admin_server.serve_admin(admin) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    });
+    let ws_server = server.clone();
+    thread::spawn(move || {
+        if let Err(e) = ws_server.serve_websocket(websocket) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    });
+    if let Err(e) = server.serve_tcp(tcp) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
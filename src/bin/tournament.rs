@@ -0,0 +1,125 @@
+//! Plays two players against each other; by default the network evaluator
+//! against the hand-written one.
+//!
+//! cargo run --release --bin tournament -- --weights weights.txt
+//! cargo run --release --bin tournament -- --first "engine=python3 my_bot.py" --second heuristic
+//!
+//! A player is `heuristic`, `network=FILE`, or `engine=COMMAND` for any
+//! program speaking the engine protocol.
+
+use rand::{rngs::StdRng, SeedableRng};
+use std::{env, process, str::FromStr};
+use tic_tac_toc_toe::{
+    engine::ExternalEngine,
+    logic::{
+        computer_player::Eval,
+        network::Network,
+        search::{Limits, CELLS},
+        tournament::{play_match, random_openings, Agent, SearchAgent},
+    },
+};
+
+const USAGE: &str = "usage: tournament (--weights FILE | --first PLAYER --second PLAYER) \
+[--depth N] [--openings N] [--opening-length N] [--seed N]
+a PLAYER is heuristic, network=FILE or engine=COMMAND";
+
+fn parse<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("bad value for {}: {}", flag, value))
+}
+
+/// Builds the player described by `spec`.
+fn agent(spec: &str, limits: Limits) -> Result<Box<dyn Agent>, String> {
+    match spec.split_once('=') {
+        None if spec == "heuristic" => Ok(Box::new(SearchAgent {
+            name: "heuristic".to_string(),
+            eval: Eval,
+            limits,
+        })),
+        Some(("network", weights)) => {
+            let network =
+                Network::load(weights).map_err(|e| format!("couldn't load {}: {}", weights, e))?;
+            Ok(Box::new(SearchAgent {
+                name: format!("network ({})", weights),
+                eval: network,
+                limits,
+            }))
+        }
+        Some(("engine", command)) => {
+            let engine = ExternalEngine::start(command, limits)
+                .map_err(|e| format!("couldn't start {}: {}", command, e))?;
+            Ok(Box::new(engine))
+        }
+        _ => Err(format!("unknown player {}", spec)),
+    }
+}
+
+fn main() {
+    let mut first = None;
+    let mut second = None;
+    let mut depth = 2;
+    let mut openings = 20;
+    let mut opening_length = 2;
+    let mut seed = 0;
+
+    let mut args = env::args().skip(1);
+    let parsed: Result<(), String> = (|| {
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} needs a value", flag))?;
+            match flag.as_str() {
+                "--weights" => {
+                    first = Some(format!("network={}", value));
+                    second.get_or_insert_with(|| "heuristic".to_string());
+                }
+                "--first" => first = Some(value),
+                "--second" => second = Some(value),
+                "--depth" => depth = parse(&flag, &value)?,
+                "--openings" => openings = parse(&flag, &value)?,
+                "--opening-length" => {
+                    opening_length = parse(&flag, &value)?;
+                    if opening_length >= CELLS {
+                        return Err(format!(
+                            "--opening-length must leave moves to play, so less than {}",
+                            CELLS
+                        ));
+                    }
+                }
+                "--seed" => seed = parse(&flag, &value)?,
+                _ => return Err(format!("unknown option {}", flag)),
+            }
+        }
+        Ok(())
+    })();
+    let (first, second) = match (parsed, first, second) {
+        (Ok(()), Some(first), Some(second)) => (first, second),
+        (Err(e), _, _) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let [mut first, mut second] = [first, second].map(|spec| {
+        agent(&spec, Limits::depth(depth)).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        })
+    });
+
+    let openings = random_openings(openings, opening_length, &mut StdRng::seed_from_u64(seed));
+    let result = play_match(&mut *first, &mut *second, &openings);
+    println!(
+        "{} vs {}: {} won, {} lost, {} drawn, score {:.1}%",
+        first.name(),
+        second.name(),
+        result.first_wins,
+        result.second_wins,
+        result.draws,
+        result.first_score() * 100.0
+    );
+}
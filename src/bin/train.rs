@@ -0,0 +1,193 @@
+//! Trains the network evaluator from self-play, on the CPU.
+//!
+//! cargo run --release --bin train -- --games 5000 --out weights.txt
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{env, process, str::FromStr};
+use tic_tac_toc_toe::logic::{
+    computer_player::{CancelToken, Eval},
+    network::{active_inputs, Network, MAX_HIDDEN},
+    search::{analyse, location, Limits, Position},
+    tournament::{play_match, random_openings, SearchAgent},
+    GamePlayStatus, Player, TTTTState,
+};
+
+struct Options {
+    games: usize,
+    hidden: usize,
+    depth: u8,
+    epsilon: f64,
+    learning_rate: f32,
+    discount: f32,
+    seed: u64,
+    out: String,
+    resume: Option<String>,
+    report_every: usize,
+    match_openings: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            games: 2000,
+            hidden: 32,
+            depth: 1,
+            epsilon: 0.1,
+            learning_rate: 0.01,
+            discount: 0.97,
+            seed: 0,
+            out: "weights.txt".to_string(),
+            resume: None,
+            report_every: 500,
+            match_openings: 5,
+        }
+    }
+}
+
+const USAGE: &str = "usage: train [--games N] [--hidden N] [--depth N] [--epsilon P] \
+[--rate R] [--discount D] [--seed N] [--out FILE] [--resume FILE] [--report-every N] \
+[--match-openings N]";
+
+fn parse<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("bad value for {}: {}", flag, value))
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--games" => options.games = parse(&flag, &value)?,
+            "--hidden" => options.hidden = parse(&flag, &value)?,
+            "--depth" => options.depth = parse(&flag, &value)?,
+            "--epsilon" => options.epsilon = parse(&flag, &value)?,
+            "--rate" => options.learning_rate = parse(&flag, &value)?,
+            "--discount" => options.discount = parse(&flag, &value)?,
+            "--seed" => options.seed = parse(&flag, &value)?,
+            "--out" => options.out = value,
+            "--resume" => options.resume = Some(value),
+            "--report-every" => options.report_every = parse(&flag, &value)?,
+            "--match-openings" => options.match_openings = parse(&flag, &value)?,
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+    if options.games == 0 {
+        return Err("--games needs at least 1 game".to_string());
+    }
+    if !(1..=MAX_HIDDEN).contains(&options.hidden) {
+        return Err(format!(
+            "--hidden must be 1 to {}, not {}",
+            MAX_HIDDEN, options.hidden
+        ));
+    }
+    if options.depth == 0 {
+        return Err("--depth needs at least 1 move".to_string());
+    }
+    if !(0.0..=1.0).contains(&options.epsilon) {
+        return Err(format!(
+            "--epsilon is a probability, 0 to 1, not {}",
+            options.epsilon
+        ));
+    }
+    if options.report_every == 0 {
+        return Err("--report-every needs at least 1 game".to_string());
+    }
+    Ok(options)
+}
+
+/// Plays one game of the network against itself and learns from the result.
+/// Returns the mean squared error over the game's positions.
+fn self_play(network: &mut Network, options: &Options, rng: &mut StdRng) -> f32 {
+    let mut state = TTTTState::new();
+    let mut seen: Vec<(Vec<usize>, Player)> = vec![];
+    while let GamePlayStatus::Playing(player) = state.status {
+        let position = Position::from_state(&state).unwrap();
+        seen.push((active_inputs(&position), player));
+        let loc = if rng.gen_bool(options.epsilon) {
+            let empty: Vec<usize> = position.empty_cells().collect();
+            location(empty[rng.gen_range(0..empty.len())])
+        } else {
+            analyse(
+                &*network,
+                &state,
+                Limits::depth(options.depth),
+                &CancelToken::new(),
+            )
+            .unwrap()
+            .best
+        };
+        state.play(loc).expect("the search only picks free spots");
+    }
+
+    let plies = seen.len();
+    let mut error = 0.0;
+    for (i, (inputs, player)) in seen.iter().enumerate() {
+        let outcome = match state.status {
+            GamePlayStatus::Win { player: winner, .. } if winner == *player => 1.0,
+            GamePlayStatus::Win { .. } => -1.0,
+            _ => 0.0,
+        };
+        let target = outcome * options.discount.powi((plies - 1 - i) as i32);
+        error += network.train(inputs, target, options.learning_rate);
+    }
+    error / plies as f32
+}
+
+fn report(network: &Network, options: &Options, rng: &mut StdRng) {
+    let openings = random_openings(options.match_openings, 2, rng);
+    let result = play_match(
+        &mut SearchAgent {
+            name: "network".to_string(),
+            eval: network.clone(),
+            limits: Limits::depth(2),
+        },
+        &mut SearchAgent {
+            name: "heuristic".to_string(),
+            eval: Eval,
+            limits: Limits::depth(2),
+        },
+        &openings,
+    );
+    println!(
+        "  vs heuristic: {} won, {} lost, {} drawn ({:.0}%)",
+        result.first_wins,
+        result.second_wins,
+        result.draws,
+        result.first_score() * 100.0
+    );
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(2);
+    });
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut network = match &options.resume {
+        Some(path) => Network::load(path).unwrap_or_else(|e| {
+            eprintln!("couldn't load {}: {}", path, e);
+            process::exit(1);
+        }),
+        None => Network::random(options.hidden, &mut rng),
+    };
+
+    let mut error = 0.0;
+    for game in 1..=options.games {
+        error += self_play(&mut network, &options, &mut rng);
+        if game % options.report_every == 0 || game == options.games {
+            let games = (game - 1) % options.report_every + 1;
+            println!("game {}: mean error {:.4}", game, error / games as f32);
+            error = 0.0;
+            report(&network, &options, &mut rng);
+            if let Err(e) = network.save(&options.out) {
+                eprintln!("couldn't save {}: {}", options.out, e);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,494 @@
+//! A full-screen terminal version of the game, played with the same keys as
+//! the 3D one: IJKL and UO move the cursor, Return places a piece. R
+//! resigns, E offers a draw and Z asks to take back a move, which the other
+//! player answers with Y or N. Against the computer, the offers are yours
+//! to make: it gives moves back, but never agrees a draw.
+//!
+//! cargo run --bin tui -- --ai b --depth 4
+//! cargo run --bin tui -- --ai b --engine "python3 my_bot.py"
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, List, Paragraph},
+    DefaultTerminal, Frame,
+};
+use std::{env, io, process, time::Duration};
+use tic_tac_toc_toe::{
+    common::Location,
+    engine::{EngineWorker, ExternalEngine},
+    logic::{
+        computer_player::{self, SearchHandle},
+        search::Limits,
+        GamePlayStatus, Offer, Player, TTTTState,
+    },
+    text_display::symbol,
+};
+
+const USAGE: &str = "usage: tui [--ai a|b|both|none] [--depth N] [--engine COMMAND]";
+
+const KEYS: &str = "IJKL/UO move  Return place  R resign  E draw  Z take back  N new game  Q quit";
+
+enum Thinking {
+    Search(SearchHandle),
+    /// Waiting for the engine's answer about this position.
+    Engine(TTTTState),
+}
+
+struct App {
+    state: TTTTState,
+    cursor: Location,
+    history: Vec<Location>,
+    computer: Vec<Player>,
+    depth: u8,
+    engine: Option<EngineWorker>,
+    thinking: Option<Thinking>,
+    /// Why the engine gave no move in this position, so it isn't asked again.
+    engine_failure: Option<(TTTTState, String)>,
+    /// An offer waiting for an answer, and who made it.
+    offer: Option<(Player, Offer)>,
+    quit: bool,
+}
+
+impl App {
+    fn new(computer: Vec<Player>, depth: u8, engine: Option<EngineWorker>) -> Self {
+        Self {
+            state: TTTTState::new(),
+            cursor: Location::new(1, 2, 3),
+            history: vec![],
+            computer,
+            depth,
+            engine,
+            thinking: None,
+            engine_failure: None,
+            offer: None,
+            quit: false,
+        }
+    }
+
+    fn new_game(&mut self) {
+        // Dropping the search cancels it, so its move can't land on the new board.
+        self.thinking = None;
+        self.state = TTTTState::new();
+        self.history.clear();
+        self.offer = None;
+    }
+
+    fn humans_turn(&self) -> bool {
+        match self.state.status {
+            GamePlayStatus::Playing(player) => !self.computer.contains(&player),
+            _ => false,
+        }
+    }
+
+    fn place(&mut self, loc: Location) {
+        if self.state.play(loc).is_ok() {
+            self.history.push(loc);
+            self.offer = None;
+        }
+    }
+
+    /// Why the engine gave no move, if that's what's holding up the game.
+    fn engine_failure(&self) -> Option<&str> {
+        match &self.engine_failure {
+            Some((state, why)) if *state == self.state => Some(why),
+            _ => None,
+        }
+    }
+
+    /// Who resigns or offers a draw from the keyboard: the player to move,
+    /// or the human if that's the computer.
+    fn asker(&self) -> Option<Player> {
+        let GamePlayStatus::Playing(to_move) = self.state.status else {
+            return None;
+        };
+        [to_move, to_move.other_player()]
+            .into_iter()
+            .find(|player| !self.computer.contains(player))
+    }
+
+    /// Makes `offer` for `by`. The computer answers straight away; a person
+    /// answers with Y or N.
+    fn offer(&mut self, by: Player, offer: Offer) {
+        if self.offer.is_some() {
+            return;
+        }
+        if offer == Offer::Takeback
+            && self
+                .state
+                .clone()
+                .take_back(by, &mut self.history.clone())
+                .is_err()
+        {
+            return;
+        }
+        self.offer = Some((by, offer));
+        if self.computer.contains(&by.other_player()) {
+            self.answer(computer_player::accepts(offer));
+        }
+    }
+
+    fn answer(&mut self, accept: bool) {
+        let Some((by, offer)) = self.offer.take() else {
+            return;
+        };
+        if !accept {
+            return;
+        }
+        let accepted = match offer {
+            Offer::Draw => self.state.agree_draw(),
+            Offer::Takeback => self.state.take_back(by, &mut self.history),
+        };
+        if accepted.is_ok() {
+            // A search of the old position is no use now.
+            self.thinking = None;
+        }
+    }
+
+    fn handle_key(&mut self, code: KeyCode) {
+        let cursor = &mut self.cursor;
+        match code {
+            KeyCode::Char('i') => cursor.y = (cursor.y + 1) % 4,
+            KeyCode::Char('k') => cursor.y = (4 + cursor.y - 1) % 4,
+            KeyCode::Char('j') => cursor.x = (cursor.x + 1) % 4,
+            KeyCode::Char('l') => cursor.x = (4 + cursor.x - 1) % 4,
+            KeyCode::Char('u') => cursor.z = (cursor.z + 1) % 4,
+            KeyCode::Char('o') => cursor.z = (4 + cursor.z - 1) % 4,
+            KeyCode::Enter if self.humans_turn() => self.place(self.cursor),
+            KeyCode::Char('y') => self.answer(true),
+            KeyCode::Char('n') if self.offer.is_some() => self.answer(false),
+            KeyCode::Char('r') => {
+                if let Some(player) = self.asker() {
+                    self.state.resign(player).ok();
+                    self.thinking = None;
+                }
+            }
+            KeyCode::Char('e') => {
+                if let Some(player) = self.asker() {
+                    self.offer(player, Offer::Draw);
+                }
+            }
+            // Between people, it's whoever just moved who wants it back.
+            KeyCode::Char('z') => match (self.state.status, self.computer.is_empty()) {
+                (GamePlayStatus::Playing(to_move), true) => {
+                    self.offer(to_move.other_player(), Offer::Takeback)
+                }
+                _ => {
+                    if let Some(player) = self.asker() {
+                        self.offer(player, Offer::Takeback);
+                    }
+                }
+            },
+            KeyCode::Char('n') => self.new_game(),
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            _ => (),
+        }
+    }
+
+    /// Starts the computer thinking when it's its turn, and plays its move
+    /// once it has one. An engine that gives none isn't asked again until
+    /// the position changes.
+    fn tick(&mut self) {
+        if let GamePlayStatus::Playing(player) = self.state.status {
+            if self.computer.contains(&player)
+                && self.thinking.is_none()
+                && self.engine_failure().is_none()
+            {
+                self.thinking = Some(match &self.engine {
+                    Some(engine) => {
+                        engine.ask(&self.state);
+                        Thinking::Engine(self.state.clone())
+                    }
+                    None => Thinking::Search(SearchHandle::spawn(&self.state, self.depth)),
+                });
+            }
+        }
+        let best = match &mut self.thinking {
+            Some(Thinking::Search(search)) => search.try_take(&self.state),
+            Some(Thinking::Engine(asked)) => {
+                let engine = self.engine.as_ref().expect("only engines are asked");
+                // Answers about positions since left behind are dropped.
+                match engine.answer() {
+                    Some((state, best)) if state == *asked && state == self.state => match best {
+                        Ok(Some(loc)) => Some(loc),
+                        failed => {
+                            let why = match failed {
+                                Err(e) => format!("the engine gave no move: {}", e),
+                                _ => "the engine has no move".to_string(),
+                            };
+                            self.engine_failure = Some((state, why));
+                            self.thinking = None;
+                            None
+                        }
+                    },
+                    _ => None,
+                }
+            }
+            None => None,
+        };
+        if let Some(loc) = best {
+            self.thinking = None;
+            self.place(loc);
+        }
+    }
+}
+
+fn player_style(player: Player) -> Style {
+    match player {
+        Player::A => Style::new().fg(Color::White).add_modifier(Modifier::BOLD),
+        Player::B => Style::new().fg(Color::Black).bg(Color::Gray),
+    }
+}
+
+fn status_line(app: &App) -> Line<'static> {
+    let style = match app.state.status {
+        GamePlayStatus::Playing(player) | GamePlayStatus::Win { player, .. } => {
+            player_style(player)
+        }
+        GamePlayStatus::Draw { .. } => Style::new().fg(Color::Yellow),
+    };
+    let mut spans = vec![Span::styled(app.state.status.to_string(), style)];
+    if app.thinking.is_some() {
+        spans.push(Span::raw(" is thinking..."));
+    }
+    if let Some(why) = app.engine_failure() {
+        spans.push(Span::styled(
+            format!("   {}", why),
+            Style::new().fg(Color::Red),
+        ));
+    }
+    if let Some((by, offer)) = app.offer {
+        let asks = match offer {
+            Offer::Draw => "offers a draw",
+            Offer::Takeback => "asks to take back a move",
+        };
+        spans.push(Span::styled(
+            format!("   {} {}: Y accepts, N declines", by, asks),
+            Style::new().fg(Color::Yellow),
+        ));
+    }
+    spans.push(Span::raw("   "));
+    spans.push(Span::styled(KEYS, Style::new().fg(Color::DarkGray)));
+    Line::from(spans)
+}
+
+fn board_lines(app: &App) -> Vec<Line<'static>> {
+    let gap = || Span::raw("   ");
+    let mut header = vec![];
+    let mut columns = vec![];
+    for z in 0..4 {
+        if z > 0 {
+            header.push(gap());
+            columns.push(gap());
+        }
+        let title = format!(" layer {}", z + 1);
+        let style = if z == app.cursor.z {
+            Style::new().add_modifier(Modifier::BOLD)
+        } else {
+            Style::new()
+        };
+        header.push(Span::styled(format!("{:<9}", title), style));
+        columns.push(Span::raw("  a b c d"));
+    }
+    let mut lines = vec![Line::from(header), Line::from(columns)];
+
+    let last = app.history.last().copied();
+    for y in (0..4).rev() {
+        let mut spans = vec![];
+        for z in 0..4 {
+            if z > 0 {
+                spans.push(gap());
+            }
+            spans.push(Span::raw(format!("{} ", y + 1)));
+            for x in 0..4 {
+                let loc = Location::new(x, y, z);
+                let spot = app.state.board.at(loc);
+                let mut style = match spot {
+                    _ if Some(loc) == last => Style::new().fg(Color::Black).bg(Color::Yellow),
+                    Some(player) => player_style(player),
+                    None => Style::new().fg(Color::DarkGray),
+                };
+                if loc == app.cursor {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+                spans.push(Span::styled(symbol(spot).to_string(), style));
+                if x < 3 {
+                    spans.push(Span::raw(" "));
+                }
+            }
+        }
+        lines.push(Line::from(spans));
+    }
+    lines.push(Line::raw(""));
+    lines.push(Line::raw(format!("cursor {}", app.cursor)));
+    lines
+}
+
+fn draw(frame: &mut Frame, app: &App) {
+    let [main, status] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+    let [board, moves] =
+        Layout::horizontal([Constraint::Length(49), Constraint::Min(16)]).areas(main);
+
+    frame.render_widget(
+        Paragraph::new(board_lines(app)).block(Block::bordered().title(" Board ")),
+        board,
+    );
+
+    let history = app.history.iter().enumerate().map(|(i, loc)| {
+        let player = if i % 2 == 0 { Player::A } else { Player::B };
+        Line::from(vec![
+            Span::raw(format!("{:>2}. ", i + 1)),
+            Span::styled(player.to_string(), player_style(player)),
+            Span::raw(format!(" {}", loc)),
+        ])
+    });
+    let skip = app
+        .history
+        .len()
+        .saturating_sub(moves.height.saturating_sub(2) as usize);
+    frame.render_widget(
+        List::new(history.skip(skip)).block(Block::bordered().title(" Moves ")),
+        moves,
+    );
+
+    frame.render_widget(Paragraph::new(status_line(app)), status);
+}
+
+fn run(terminal: &mut DefaultTerminal, mut app: App) -> io::Result<()> {
+    while !app.quit {
+        app.tick();
+        terminal.draw(|frame| draw(frame, &app))?;
+        if event::poll(Duration::from_millis(50))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    app.handle_key(key.code);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let mut computer = vec![];
+    let mut depth = 3;
+    let mut engine = None;
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().unwrap_or_default();
+        match (flag.as_str(), value.to_ascii_lowercase().as_str()) {
+            ("--ai", "a") => computer = vec![Player::A],
+            ("--ai", "b") => computer = vec![Player::B],
+            ("--ai", "both") => computer = vec![Player::A, Player::B],
+            ("--ai", "none") => computer = vec![],
+            // The search needs to look at least a move ahead to pick one.
+            ("--depth", d) if d.parse::<u8>().is_ok_and(|d| d > 0) => depth = d.parse().unwrap(),
+            ("--engine", _) => engine = Some(value),
+            _ => {
+                eprintln!("bad option {} {}\n{}", flag, value, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    let engine = engine.map(|command| {
+        let engine = ExternalEngine::start(&command, Limits::depth(depth)).unwrap_or_else(|e| {
+            eprintln!("couldn't start {}: {}", command, e);
+            process::exit(1);
+        });
+        EngineWorker::spawn(engine)
+    });
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, App::new(computer, depth, engine));
+    ratatui::restore();
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tic_tac_toc_toe::logic::{DrawReason, WinReason};
+
+    fn press(app: &mut App, keys: &str) {
+        for key in keys.chars() {
+            app.handle_key(match key {
+                '\n' => KeyCode::Enter,
+                c => KeyCode::Char(c),
+            });
+        }
+    }
+
+    #[test]
+    fn return_places_a_piece_under_the_cursor() {
+        let mut app = App::new(vec![], 1, None);
+        press(&mut app, "ij\n");
+        let placed = Location::new(2, 3, 3);
+        assert_eq!(app.state.board.at(placed), Some(Player::A));
+        assert_eq!(app.history, [placed]);
+        assert_eq!(app.state.status, GamePlayStatus::Playing(Player::B));
+
+        // Not again on the same spot.
+        press(&mut app, "\n");
+        assert_eq!(app.history, [placed]);
+    }
+
+    #[test]
+    fn the_player_who_just_moved_can_take_it_back_if_the_other_agrees() {
+        let mut app = App::new(vec![], 1, None);
+        press(&mut app, "\nz");
+        assert_eq!(app.offer, Some((Player::A, Offer::Takeback)));
+        press(&mut app, "n");
+        assert_eq!(app.offer, None);
+        assert_eq!(app.history.len(), 1);
+
+        press(&mut app, "zy");
+        assert!(app.history.is_empty());
+        assert_eq!(app.state, TTTTState::new());
+    }
+
+    #[test]
+    fn r_resigns_for_the_player_to_move() {
+        let mut app = App::new(vec![], 1, None);
+        press(&mut app, "\nr");
+        assert_eq!(
+            app.state.status,
+            GamePlayStatus::Win {
+                player: Player::A,
+                reason: WinReason::Resignation
+            }
+        );
+    }
+
+    #[test]
+    fn a_draw_is_agreed_with_y() {
+        let mut app = App::new(vec![], 1, None);
+        press(&mut app, "e");
+        assert_eq!(app.offer, Some((Player::A, Offer::Draw)));
+        press(&mut app, "y");
+        assert_eq!(
+            app.state.status,
+            GamePlayStatus::Draw {
+                reason: DrawReason::Agreement
+            }
+        );
+    }
+
+    #[test]
+    fn the_computer_gives_moves_back_but_never_agrees_a_draw() {
+        let mut app = App::new(vec![Player::B], 1, None);
+        press(&mut app, "\ne");
+        assert_eq!(app.offer, None);
+        assert_eq!(app.state.status, GamePlayStatus::Playing(Player::B));
+
+        press(&mut app, "z");
+        assert_eq!(app.offer, None);
+        assert_eq!(app.state, TTTTState::new());
+    }
+}
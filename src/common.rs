@@ -1,4 +1,13 @@
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+/// How many spots there are along each side of the board.
+pub const SIZE: usize = 4;
+
+/// A spot on the board. Deserializing one checks it's on the board, since
+/// they come from clients over the network.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "Coordinates")]
 pub struct Location {
     pub x: usize,
     pub y: usize,
@@ -9,6 +18,37 @@ impl Location {
     pub fn new(x: usize, y: usize, z: usize) -> Self {
         Self { x, y, z }
     }
+
+    pub fn is_on_board(&self) -> bool {
+        self.x < SIZE && self.y < SIZE && self.z < SIZE
+    }
+}
+
+/// A `Location` as it arrives, before it's been checked.
+#[derive(Deserialize)]
+struct Coordinates {
+    x: usize,
+    y: usize,
+    z: usize,
+}
+
+impl TryFrom<Coordinates> for Location {
+    type Error = String;
+
+    fn try_from(c: Coordinates) -> Result<Self, Self::Error> {
+        let loc = Location::new(c.x, c.y, c.z);
+        if loc.is_on_board() {
+            Ok(loc)
+        } else {
+            Err(format!(
+                "x, y and z must be 0 to {}, not {}, {} and {}",
+                SIZE - 1,
+                c.x,
+                c.y,
+                c.z
+            ))
+        }
+    }
 }
 
 impl From<(usize, usize, usize)> for Location {
@@ -16,3 +56,85 @@ impl From<(usize, usize, usize)> for Location {
         Self::new(l.0, l.1, l.2)
     }
 }
+
+/// Coordinate notation: the column as a letter `a`-`d` for x, then the row
+/// `1`-`4` for y, then the layer `1`-`4` for z. `b32` is x 1, y 2, z 1.
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            (b'a' + self.x as u8) as char,
+            self.y + 1,
+            self.z + 1
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLocationError(pub String);
+
+impl Display for ParseLocationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "`{}` isn't a spot; use a column a-d, a row 1-4 and a layer 1-4, like b32",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseLocationError {}
+
+impl FromStr for Location {
+    type Err = ParseLocationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chars: Vec<char> = s.trim().to_ascii_lowercase().chars().collect();
+        match chars[..] {
+            [x @ 'a'..='d', y @ '1'..='4', z @ '1'..='4'] => Ok(Self::new(
+                x as usize - 'a' as usize,
+                y as usize - '1' as usize,
+                z as usize - '1' as usize,
+            )),
+            _ => Err(ParseLocationError(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notation_round_trips_for_every_spot() {
+        for z in 0..4 {
+            for y in 0..4 {
+                for x in 0..4 {
+                    let loc = Location::new(x, y, z);
+                    assert_eq!(loc.to_string().parse(), Ok(loc));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn notation_is_column_row_layer() {
+        assert_eq!("b32".parse(), Ok(Location::new(1, 2, 1)));
+        assert_eq!(" D14 ".parse(), Ok(Location::new(3, 0, 3)));
+    }
+
+    #[test]
+    fn spots_off_the_board_are_rejected() {
+        for bad in ["", "e11", "a51", "a15", "a1", "a111", "11a"] {
+            assert!(bad.parse::<Location>().is_err(), "{}", bad);
+        }
+        let json = |s: &str| serde_json::from_str::<Location>(s);
+        assert_eq!(
+            json(r#"{"x":3,"y":0,"z":2}"#).unwrap(),
+            Location::new(3, 0, 2)
+        );
+        assert!(json(r#"{"x":4,"y":0,"z":0}"#).is_err());
+        assert!(json(r#"{"x":0,"y":0,"z":9}"#).is_err());
+    }
+}
@@ -0,0 +1,494 @@
+//! A line-based protocol for talking to engines in other processes, modelled
+//! on UCI. The controller writes commands to the engine's stdin and reads
+//! responses from its stdout, one per line:
+//!
+//! ```text
+//! > tttt
+//! < id name tttt-engine
+//! < tttok
+//! > isready
+//! < readyok
+//! > position startpos moves a11 d44
+//! > go depth 5
+//! < info depth 5 score 12 nodes 3301 pv b22 c33
+//! < bestmove b22
+//! > quit
+//! ```
+//!
+//! Positions are given in the notation from `logic::notation`, or as
+//! `startpos`, optionally followed by `moves` and the moves played since.
+//! `go` takes a `depth`, a `movetime` in milliseconds, or both; `stop` ends
+//! a search early and the engine still answers with `bestmove`.
+
+use crate::{
+    common::Location,
+    logic::{clock::ParseTimeControlError, search::Limits, tournament::Agent, TTTTState},
+};
+use std::{
+    fmt::Display,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    process::{Child, ChildStdin, Command as Process, Stdio},
+    str::FromStr,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+/// How deep `go` searches when it isn't told.
+pub const DEFAULT_DEPTH: u8 = 4;
+
+/// How long to wait for an engine to answer anything but `go`.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a depth-limited search may take for each ply before the engine
+/// is taken to have hung: far longer than any sensible search needs.
+const PLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Hello,
+    IsReady,
+    NewGame,
+    Position(TTTTState),
+    Go(Limits),
+    Stop,
+    Quit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    Id(String),
+    HelloOk,
+    ReadyOk,
+    Info {
+        depth: u8,
+        score: i32,
+        nodes: u64,
+        pv: Vec<Location>,
+    },
+    BestMove(Option<Location>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMessageError(pub String);
+
+impl Display for ParseMessageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ParseMessageError {}
+
+impl From<ParseTimeControlError> for ParseMessageError {
+    fn from(e: ParseTimeControlError) -> Self {
+        Self(e.to_string())
+    }
+}
+
+fn parse_word<T: FromStr>(what: &str, word: Option<&str>) -> Result<T, ParseMessageError> {
+    let word = word.ok_or_else(|| ParseMessageError(format!("missing {}", what)))?;
+    word.parse()
+        .map_err(|_| ParseMessageError(format!("bad {}: {}", what, word)))
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::Hello => write!(f, "tttt"),
+            Command::IsReady => write!(f, "isready"),
+            Command::NewGame => write!(f, "newgame"),
+            Command::Position(state) => write!(f, "position {}", state.to_notation()),
+            Command::Go(limits) => {
+                write!(f, "go depth {}", limits.depth)?;
+                if let Some(movetime) = limits.movetime {
+                    write!(f, " movetime {}", movetime.as_millis())?;
+                }
+                Ok(())
+            }
+            Command::Stop => write!(f, "stop"),
+            Command::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = ParseMessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command = match words.next() {
+            Some("tttt") => Command::Hello,
+            Some("isready") => Command::IsReady,
+            Some("newgame") => Command::NewGame,
+            Some("position") => {
+                let mut state = match words.next() {
+                    Some("startpos") => TTTTState::new(),
+                    Some(layers) => {
+                        let side = words.next().unwrap_or_default();
+                        TTTTState::from_notation(&format!("{} {}", layers, side))
+                            .map_err(|e| ParseMessageError(e.to_string()))?
+                    }
+                    None => return Err(ParseMessageError("missing position".to_string())),
+                };
+                match words.next() {
+                    Some("moves") => (),
+                    Some(word) => return Err(ParseMessageError(format!("unexpected {}", word))),
+                    None => return Ok(Command::Position(state)),
+                }
+                for word in words.by_ref() {
+                    let loc: Location = parse_word("move", Some(word))?;
+                    state
+                        .play(loc)
+                        .map_err(|e| ParseMessageError(format!("can't play {}: {}", loc, e)))?;
+                }
+                Command::Position(state)
+            }
+            Some("go") => {
+                let mut limits = Limits::depth(DEFAULT_DEPTH);
+                let mut depth = None;
+                while let Some(word) = words.next() {
+                    match word {
+                        "depth" => depth = Some(parse_word("depth", words.next())?),
+                        "movetime" => {
+                            let ms = parse_word("movetime", words.next())?;
+                            limits = Limits::movetime(Duration::from_millis(ms));
+                        }
+                        _ => return Err(ParseMessageError(format!("unknown go option {}", word))),
+                    }
+                }
+                if let Some(depth) = depth {
+                    limits.depth = depth;
+                }
+                Command::Go(limits)
+            }
+            Some("stop") => Command::Stop,
+            Some("quit") => Command::Quit,
+            Some(word) => return Err(ParseMessageError(format!("unknown command {}", word))),
+            None => return Err(ParseMessageError("empty command".to_string())),
+        };
+        match words.next() {
+            Some(word) => Err(ParseMessageError(format!("unexpected {}", word))),
+            None => Ok(command),
+        }
+    }
+}
+
+impl Display for Response {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Response::Id(name) => write!(f, "id name {}", name),
+            Response::HelloOk => write!(f, "tttok"),
+            Response::ReadyOk => write!(f, "readyok"),
+            Response::Info {
+                depth,
+                score,
+                nodes,
+                pv,
+            } => {
+                write!(f, "info depth {} score {} nodes {}", depth, score, nodes)?;
+                if !pv.is_empty() {
+                    let pv: Vec<String> = pv.iter().map(Location::to_string).collect();
+                    write!(f, " pv {}", pv.join(" "))?;
+                }
+                Ok(())
+            }
+            Response::BestMove(Some(loc)) => write!(f, "bestmove {}", loc),
+            Response::BestMove(None) => write!(f, "bestmove none"),
+        }
+    }
+}
+
+impl FromStr for Response {
+    type Err = ParseMessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        match words.next() {
+            Some("id") => match words.next() {
+                Some("name") => Ok(Response::Id(words.collect::<Vec<_>>().join(" "))),
+                _ => Err(ParseMessageError(format!("unknown id line {}", s))),
+            },
+            Some("tttok") => Ok(Response::HelloOk),
+            Some("readyok") => Ok(Response::ReadyOk),
+            Some("info") => {
+                let (mut depth, mut score, mut nodes, mut pv) = (0, 0, 0, vec![]);
+                while let Some(word) = words.next() {
+                    match word {
+                        "depth" => depth = parse_word("depth", words.next())?,
+                        "score" => score = parse_word("score", words.next())?,
+                        "nodes" => nodes = parse_word("nodes", words.next())?,
+                        "pv" => {
+                            pv = words
+                                .by_ref()
+                                .map(|w| parse_word("move", Some(w)))
+                                .collect::<Result<_, _>>()?
+                        }
+                        _ => return Err(ParseMessageError(format!("unknown info {}", word))),
+                    }
+                }
+                Ok(Response::Info {
+                    depth,
+                    score,
+                    nodes,
+                    pv,
+                })
+            }
+            Some("bestmove") => match words.next() {
+                Some("none") => Ok(Response::BestMove(None)),
+                word => Ok(Response::BestMove(Some(parse_word("move", word)?))),
+            },
+            _ => Err(ParseMessageError(format!("unknown response {}", s))),
+        }
+    }
+}
+
+/// An engine running in another process, spoken to over its stdin and
+/// stdout. Dropping it tells the engine to quit.
+pub struct ExternalEngine {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    responses: Receiver<Response>,
+    pub limits: Limits,
+}
+
+impl ExternalEngine {
+    /// Starts `command`, split on whitespace into a program and its
+    /// arguments, and waits for it to introduce itself.
+    pub fn start(command: &str, limits: Limits) -> io::Result<Self> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "empty engine command"))?;
+        let mut child = Process::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        // Lines the controller doesn't understand are skipped, as in UCI.
+        let (sender, responses) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if let Ok(response) = line.parse() {
+                    if sender.send(response).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut engine = Self {
+            name: program.to_string(),
+            child,
+            stdin,
+            responses,
+            limits,
+        };
+        engine.send(&Command::Hello)?;
+        loop {
+            match engine.receive(HANDSHAKE_TIMEOUT)? {
+                Response::Id(name) => engine.name = name,
+                Response::HelloOk => break,
+                _ => (),
+            }
+        }
+        Ok(engine)
+    }
+
+    pub fn send(&mut self, command: &Command) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    fn receive(&self, timeout: Duration) -> io::Result<Response> {
+        self.responses.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => {
+                io::Error::new(ErrorKind::TimedOut, "the engine stopped answering")
+            }
+            RecvTimeoutError::Disconnected => {
+                io::Error::new(ErrorKind::UnexpectedEof, "the engine exited")
+            }
+        })
+    }
+
+    /// Waits until the engine has handled everything sent so far.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.send(&Command::IsReady)?;
+        while self.receive(HANDSHAKE_TIMEOUT)? != Response::ReadyOk {}
+        Ok(())
+    }
+
+    /// Asks for a move in `state` within `self.limits`.
+    pub fn best_move(&mut self, state: &TTTTState) -> io::Result<Option<Location>> {
+        self.send(&Command::Position(state.clone()))?;
+        self.send(&Command::Go(self.limits))?;
+        // A depth-limited search has no deadline of its own, so only give
+        // up on it once it's clearly gone quiet.
+        let timeout = match self.limits.movetime {
+            Some(movetime) => movetime + HANDSHAKE_TIMEOUT,
+            None => PLY_TIMEOUT * self.limits.depth.max(1).into(),
+        };
+        loop {
+            match self.receive(timeout) {
+                Ok(Response::BestMove(best)) => return Ok(best),
+                Ok(_) => (),
+                Err(e) if e.kind() == ErrorKind::TimedOut => {
+                    // Its answer mustn't be taken for the next position's, so
+                    // stop the search and wait until it's been given.
+                    self.send(&Command::Stop)?;
+                    self.sync()?;
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Agent for ExternalEngine {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// An engine that crashes or stops answering gives up.
+    fn choose(&mut self, state: &TTTTState) -> Option<Location> {
+        self.best_move(state).ok().flatten()
+    }
+}
+
+impl Drop for ExternalEngine {
+    fn drop(&mut self) {
+        self.send(&Command::Quit).ok();
+        for _ in 0..20 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+/// What an engine said about a position: its move, or why it gave none.
+pub type Answer = (TTTTState, Result<Option<Location>, String>);
+
+/// An external engine on its own thread, so waiting for it doesn't hold up
+/// whoever asked. Each answer comes back with the position it was asked
+/// about.
+pub struct EngineWorker {
+    positions: Sender<TTTTState>,
+    answers: Receiver<Answer>,
+}
+
+impl EngineWorker {
+    pub fn spawn(engine: ExternalEngine) -> Self {
+        Self::on_thread(move || Ok(engine))
+    }
+
+    /// Starts `command` on the worker thread as well. If it won't start,
+    /// every position gets that as its answer.
+    pub fn start(command: String, limits: Limits) -> Self {
+        Self::on_thread(move || {
+            ExternalEngine::start(&command, limits)
+                .map_err(|e| format!("couldn't start {}: {}", command, e))
+        })
+    }
+
+    fn on_thread(engine: impl FnOnce() -> Result<ExternalEngine, String> + Send + 'static) -> Self {
+        let (positions, requests) = mpsc::channel::<TTTTState>();
+        let (sender, answers) = mpsc::channel();
+        thread::spawn(move || {
+            let mut engine = engine();
+            for state in requests {
+                let best = match &mut engine {
+                    Ok(engine) => engine.best_move(&state).map_err(|e| e.to_string()),
+                    Err(e) => Err(e.clone()),
+                };
+                if sender.send((state, best)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { positions, answers }
+    }
+
+    pub fn ask(&self, state: &TTTTState) {
+        // The thread only ends once this side has gone.
+        self.positions.send(state.clone()).ok();
+    }
+
+    /// The next answer, if one's come.
+    pub fn answer(&self) -> Option<Answer> {
+        self.answers.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_round_trip_through_text() {
+        let mut state = TTTTState::new();
+        state.play("b32".parse().unwrap()).unwrap();
+        for command in [
+            Command::Hello,
+            Command::IsReady,
+            Command::NewGame,
+            Command::Position(state),
+            Command::Go(Limits::depth(6)),
+            Command::Go(Limits::movetime(Duration::from_millis(250))),
+            Command::Stop,
+            Command::Quit,
+        ] {
+            assert_eq!(command.to_string().parse(), Ok(command));
+        }
+    }
+
+    #[test]
+    fn positions_can_be_given_as_moves_from_the_start() {
+        let mut state = TTTTState::new();
+        state.play("a11".parse().unwrap()).unwrap();
+        state.play("d44".parse().unwrap()).unwrap();
+        assert_eq!(
+            "position startpos moves a11 d44".parse(),
+            Ok(Command::Position(state))
+        );
+        assert!("position startpos moves a11 a11"
+            .parse::<Command>()
+            .is_err());
+    }
+
+    #[test]
+    fn go_defaults_to_a_fixed_depth() {
+        assert_eq!("go".parse(), Ok(Command::Go(Limits::depth(DEFAULT_DEPTH))));
+        assert_eq!("go depth 2".parse(), Ok(Command::Go(Limits::depth(2))));
+        assert!("go depth".parse::<Command>().is_err());
+        assert!("go fast".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn responses_round_trip_through_text() {
+        for response in [
+            Response::Id("tttt engine".to_string()),
+            Response::HelloOk,
+            Response::ReadyOk,
+            Response::Info {
+                depth: 3,
+                score: -40,
+                nodes: 1234,
+                pv: vec!["b22".parse().unwrap(), "c33".parse().unwrap()],
+            },
+            Response::BestMove(Some("a14".parse().unwrap())),
+            Response::BestMove(None),
+        ] {
+            assert_eq!(response.to_string().parse(), Ok(response));
+        }
+    }
+}
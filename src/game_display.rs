@@ -1,29 +1,117 @@
-use crate::{common::*, logic::*};
-use bevy::{core_pipeline::clear_color::ClearColorConfig, prelude::*};
-use bevy_mod_picking::{
-    DefaultPickingPlugins, PickableBundle, PickingCameraBundle, PickingEvent, Selection,
-};
+use bevy::{core_pipeline::clear_color::ClearColorConfig, prelude::*, ui::FocusPolicy};
+use bevy_mod_picking::{DefaultPickingPlugins, Hover, PickableMesh, PickingCameraBundle};
 use core::f32::consts::PI;
+use std::time::{Duration, Instant};
+use tic_tac_toc_toe::{
+    common::*,
+    logic::{clock::TimeControl, *},
+};
+
+mod camera;
+mod chat;
+mod computer;
+mod lan;
+mod menu;
+mod navigation;
+mod network;
+mod offers;
+mod overlays;
+mod placing;
+
+pub use lan::LanBrowser;
+pub use menu::Settings;
+pub use network::NetworkGame;
 
-pub struct GameDisplayPlugin;
+/// Where the app is: picking a game from the main menu, playing one,
+/// looking at how it ended, or paused over the top of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppState {
+    MainMenu,
+    Playing,
+    GameOver,
+    Paused,
+}
+
+/// Draws the game and runs the menus around it, starting from `start`: the
+/// main menu, unless a game was given on the command line.
+pub struct GameDisplayPlugin {
+    pub start: AppState,
+}
 
 impl Plugin for GameDisplayPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(AmbientLight {
-            color: Color::WHITE,
-            brightness: 1.0,
-        })
-        .add_plugins(DefaultPickingPlugins)
-        .add_startup_system(setup)
-        .add_startup_system(create_frame)
-        .add_startup_system(make_selector)
-        .add_system(replace_board)
-        .add_system(pulse_selector)
-        .add_system(handle_input)
-        .add_system(handle_camera_movement)
-        .add_system(update_player_indicator)
-        .add_system(move_selector_on_click)
-        .add_system(deselect_nodes);
+        app.add_state(self.start)
+            .insert_resource(AmbientLight {
+                color: Color::WHITE,
+                brightness: 1.0,
+            })
+            .add_plugins(DefaultPickingPlugins)
+            .init_resource::<chat::ChatBox>()
+            .init_resource::<Settings>()
+            .init_resource::<BoardAssets>()
+            .init_resource::<overlays::OverlayAssets>()
+            .init_resource::<placing::GhostMaterials>()
+            .add_event::<placing::Place>()
+            .add_startup_system(setup)
+            .add_startup_system(create_frame)
+            .add_startup_system(placing::spawn_ghost)
+            .add_startup_system(lan::spawn_list)
+            .add_startup_system(chat::spawn_panel)
+            .add_startup_system(menu::spawn_problem)
+            .add_system_set(
+                SystemSet::on_enter(AppState::MainMenu)
+                    .with_system(menu::spawn_main_menu)
+                    .with_system(despawn_selectors),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::MainMenu).with_system(lan::join_lan_game),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::MainMenu).with_system(menu::despawn_menus))
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(make_selector))
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(navigation::move_selector.before(chat::Typing))
+                    .with_system(handle_input.before(chat::Typing))
+                    .with_system(placing::click_to_place)
+                    .with_system(placing::preview_piece)
+                    .with_system(offers::make_offers.before(chat::Typing))
+                    .with_system(computer::play_computer)
+                    .with_system(flag_fall)
+                    .with_system(menu::end_game),
+            )
+            .add_system_set(SystemSet::on_pause(AppState::Playing).with_system(placing::hide_ghost))
+            .add_system_set(SystemSet::on_exit(AppState::Playing).with_system(placing::hide_ghost))
+            .add_system_set(
+                SystemSet::on_enter(AppState::Paused)
+                    .with_system(menu::spawn_pause_menu)
+                    .with_system(menu::stop_clock),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Paused)
+                    .with_system(menu::despawn_menus)
+                    .with_system(menu::restart_clock),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::GameOver)
+                    .with_system(menu::spawn_game_over_menu)
+                    .with_system(despawn_selectors),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::GameOver).with_system(menu::despawn_menus))
+            .add_system(menu::pause.before(chat::Typing))
+            .add_system(menu::press_buttons)
+            .add_system(menu::update_buttons)
+            .add_system(menu::show_problem)
+            .add_system(lan::update_list)
+            .add_system(network::receive_updates)
+            .add_system(update_board)
+            .add_system(overlays::update_overlays)
+            .add_system(pulse_selector)
+            .add_system(camera::steer_camera.before(camera::move_camera))
+            .add_system(camera::move_camera)
+            .add_system(chat::type_message.label(chat::Typing))
+            .add_system(chat::update_panel)
+            .add_system(update_player_indicator)
+            .add_system(placing::place_pieces);
     }
 }
 
@@ -52,55 +140,24 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         PlayerIndicator,
     ));
 
+    let view = camera::View {
+        distance: 8.0,
+        ..camera::View::from_translation(Vec3::new(-2.0, 2.5, 5.0))
+    };
     commands.spawn((
         Camera3dBundle {
-            transform: center_looking(Transform::from_xyz(-2.0, 2.5, 5.0)),
+            transform: view.transform(),
             ..default()
         },
-        MainCamera,
+        camera::OrbitCamera::new(view),
         PickingCameraBundle::default(),
     ));
 }
 
-fn center_looking(pos: Transform) -> Transform {
-    pos.looking_at(Vec3::ZERO, Vec3::Y)
-}
-
-fn move_selector_on_click(
-    mut events: EventReader<PickingEvent>,
-    nodes: Query<(Entity, &ClickableNode)>,
-    mut selectors: Query<&mut Selector>,
-) {
-    for event in events.iter() {
-        match event {
-            PickingEvent::Clicked(e) => {
-                for (entity, node) in &nodes {
-                    if *e == entity {
-                        for mut seelctor in selectors.iter_mut() {
-                            seelctor.x = node.0.x;
-                            seelctor.y = node.0.y;
-                            seelctor.z = node.0.z;
-                        }
-                    }
-                }
-            }
-            _ => {}
-        }
-    }
-}
-
-fn deselect_nodes(mut nodes: Query<&mut Selection>) {
-    for mut node in nodes.iter_mut() {
-        node.set_selected(false);
-    }
-}
-
-#[derive(Component)]
-struct MainCamera;
-
 fn update_player_indicator(
     mut indicators: Query<&mut Text, With<PlayerIndicator>>,
     state: Res<MyGame>,
+    network: Option<NonSend<NetworkGame>>,
 ) {
     fn player_color(player: Player) -> Color {
         match player {
@@ -108,10 +165,25 @@ fn update_player_indicator(
             Player::B => Color::BLACK,
         }
     }
-    let (text, color) = match state.status {
-        GamePlayStatus::Playing(player) => (format!("{}", player), player_color(player)),
-        GamePlayStatus::Draw => ("Draw".to_string(), Color::YELLOW),
-        GamePlayStatus::Win(player) => (format!("{} won", player), player_color(player)),
+    let mut text = state.status.to_string();
+    if let Some(clock) = clock_line(&state) {
+        text = format!("{}\n{}", text, clock);
+    }
+    let me = network
+        .as_ref()
+        .and_then(|network| network.seat)
+        .map(|(_, me)| me);
+    if let Some(prompt) = offers::prompt(&state, me) {
+        text = format!("{}\n{}", text, prompt);
+    }
+    if let Some(network) = network {
+        text = format!("{}\n{}", text, network::describe(&network));
+    }
+    let color = match state.status {
+        GamePlayStatus::Playing(player) | GamePlayStatus::Win { player, .. } => {
+            player_color(player)
+        }
+        GamePlayStatus::Draw { .. } => Color::YELLOW,
     };
     for mut indicator in indicators.iter_mut() {
         indicator.sections.iter_mut().for_each(|t| {
@@ -125,165 +197,124 @@ fn pulse_selector(time: Res<Time>, mut selectors: Query<(&mut Transform, &Select
     let scale = (4.0 * time.elapsed().as_secs_f32()).cos() * 0.2 + 1.0;
     for (mut transfrom, selector) in selectors.iter_mut() {
         transfrom.scale = Vec3::splat(scale);
-        transfrom.translation = Transform::from_xyz(
-            selector.x as f32 - 1.5,
-            selector.y as f32 - 1.5,
-            selector.z as f32 - 1.5,
-        )
-        .translation;
+        transfrom.translation = position(Location::new(selector.x, selector.y, selector.z));
     }
 }
 
-fn make_selector(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
+fn despawn_selectors(mut commands: Commands, selectors: Query<Entity, With<Selector>>) {
+    for selector in &selectors {
+        commands.entity(selector).despawn_recursive();
+    }
+}
+
+fn make_selector(mut commands: Commands, assets: Res<BoardAssets>) {
     commands
         .spawn(PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Icosphere {
-                radius: 0.35,
-                ..default()
-            })),
-            material: materials.add(Color::rgba(0.0, 1.0, 0.0, 0.5).into()),
+            mesh: assets.piece.clone(),
+            material: assets.selector.clone(),
             transform: Transform::from_xyz(1.0 - 1.5, 2.0 - 1.5, 3.0 - 1.5),
             ..default()
         })
         .insert(Selector { x: 1, y: 2, z: 3 });
 }
 
-fn replace_board(
+/// Brings the pieces on screen in line with the board when it changes:
+/// pieces no longer on the board go, and new ones are added, sharing
+/// `BoardAssets`' mesh and materials.
+fn update_board(
     mut commands: Commands,
     game: Res<MyGame>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    prev_markers: Query<Entity, With<Marker>>,
+    assets: Res<BoardAssets>,
+    markers: Query<(Entity, &Marker)>,
 ) {
-    for marker in &prev_markers {
-        commands.entity(marker).despawn();
+    if !game.is_changed() {
+        return;
     }
-    for (z, plane) in game.board.spots.iter().enumerate() {
-        for (y, row) in plane.iter().enumerate() {
-            for (x, pos) in row.iter().enumerate() {
-                if let Some(player) = pos {
-                    commands
-                        .spawn(PbrBundle {
-                            mesh: meshes.add(Mesh::from(shape::Icosphere {
-                                radius: 0.35,
-                                ..default()
-                            })),
-                            material: materials.add(
-                                (if *player == Player::A {
-                                    Color::rgba(1.0, 1.0, 1.0, 0.75)
-                                } else {
-                                    Color::rgba(0.0, 0.0, 0.0, 0.75)
-                                })
-                                .into(),
-                            ),
-                            transform: Transform::from_xyz(
-                                x as f32 - 1.5,
-                                y as f32 - 1.5,
-                                z as f32 - 1.5,
-                            ),
-                            ..default()
-                        })
-                        .insert(Marker);
-                }
-            }
+    let mut shown = vec![];
+    for (entity, marker) in &markers {
+        if game.board.at(marker.at) == Some(marker.player) {
+            shown.push(marker.at);
+        } else {
+            commands.entity(entity).despawn();
         }
     }
+    for loc in all_locations() {
+        let Some(player) = game.board.at(loc) else {
+            continue;
+        };
+        if shown.contains(&loc) {
+            continue;
+        }
+        commands.spawn((
+            PbrBundle {
+                mesh: assets.piece.clone(),
+                material: assets.player(player),
+                transform: Transform::from_translation(position(loc)),
+                ..default()
+            },
+            Marker { at: loc, player },
+        ));
+    }
 }
 
+/// `Return` places a piece where the selector is.
 fn handle_input(
     input: Res<Input<KeyCode>>,
-    mut selectors: Query<&mut Selector>,
-    mut board: ResMut<MyGame>,
-    mut commands: Commands,
-    selector_entity: Query<Entity, With<Selector>>,
+    selectors: Query<&Selector>,
+    chat: Res<chat::ChatBox>,
+    mut places: EventWriter<placing::Place>,
 ) {
-    if input.just_pressed(KeyCode::I) {
-        for mut selector in selectors.iter_mut() {
-            selector.y = (selector.y + 1) % 4
-        }
+    if chat.is_typing() || !input.just_pressed(KeyCode::Return) {
+        return;
     }
-    if input.just_pressed(KeyCode::K) {
-        for mut selector in selectors.iter_mut() {
-            selector.y = (4 + selector.y - 1) % 4
-        }
-    }
-    if input.just_pressed(KeyCode::J) {
-        for mut selector in selectors.iter_mut() {
-            selector.x = (selector.x + 1) % 4
-        }
-    }
-    if input.just_pressed(KeyCode::L) {
-        for mut selector in selectors.iter_mut() {
-            selector.x = (4 + selector.x - 1) % 4
-        }
-    }
-    if input.just_pressed(KeyCode::U) {
-        for mut selector in selectors.iter_mut() {
-            selector.z = (selector.z + 1) % 4
-        }
-    }
-    if input.just_pressed(KeyCode::O) {
-        for mut selector in selectors.iter_mut() {
-            selector.z = (4 + selector.z - 1) % 4
-        }
-    }
-    if input.just_pressed(KeyCode::Return) {
-        for selector in &selectors {
-            board.play(Location::new(selector.x, selector.y, selector.z));
-            match board.status {
-                GamePlayStatus::Playing(_) => {}
-                _ => commands
-                    .entity(selector_entity.single())
-                    .despawn_recursive(),
-            }
-        }
+    for selector in &selectors {
+        places.send(placing::Place(Location::new(
+            selector.x, selector.y, selector.z,
+        )));
     }
 }
 
-fn handle_camera_movement(
-    input: Res<Input<KeyCode>>,
-    mut cameras: Query<&mut Transform, With<MainCamera>>,
-    time: Res<Time>,
-) {
-    for mut camera in &mut cameras {
-        if input.pressed(KeyCode::A) {
-            let dir = camera.translation.normalize();
-            let k = dir.cross(Vec3::Y).normalize() / 100.0 / time.delta_seconds();
-            camera.translation += k;
-            *camera = center_looking(*camera);
-        }
-        if input.pressed(KeyCode::D) {
-            let dir = camera.translation.normalize();
-            let k = dir.cross(Vec3::NEG_Y).normalize() / 100.0 / time.delta_seconds();
-            camera.translation += k;
-            *camera = center_looking(*camera);
-        }
+/// Both players' time left, as `A 2:59  B 3:00`, in a game with a clock.
+fn clock_line(game: &MyGame) -> Option<String> {
+    let clock = game.clock?;
+    let clock = match game.status {
+        GamePlayStatus::Playing(player) => clock.ticked(player, game.turn_started.elapsed()),
+        _ => clock,
+    };
+    let show = |left: Duration| {
+        let secs = left.as_secs_f64().ceil() as u64;
+        format!("{}:{:02}", secs / 60, secs % 60)
+    };
+    Some(format!(
+        "A {}  B {}",
+        show(clock.left(Player::A)),
+        show(clock.left(Player::B))
+    ))
+}
 
-        if input.pressed(KeyCode::S) {
-            let dir = camera.translation.normalize();
-            let k = dir.cross(Vec3::NEG_Y).normalize();
-            camera.translation += k.cross(dir) / 100.0 / time.delta_seconds();
-            *camera = center_looking(*camera);
-        }
-        if input.pressed(KeyCode::W) {
-            let dir = camera.translation.normalize();
-            let k = dir.cross(Vec3::NEG_Y).normalize();
-            camera.translation -= k.cross(dir) / 100.0 / time.delta_seconds();
-            *camera = center_looking(*camera);
-        }
-        camera.translation = camera.translation.normalize() * 8.0;
+/// Ends a local game when the player to move runs out of time. Over the
+/// network the server keeps the clock, and says when a flag falls.
+fn flag_fall(mut game: ResMut<MyGame>, network: Option<NonSend<NetworkGame>>) {
+    if network.is_some() || game.clock.is_none() {
+        return;
+    }
+    let thought = game.turn_started.elapsed();
+    // Only look through `ResMut` when the flag's down, so the game isn't
+    // marked changed every frame.
+    if !game.bypass_change_detection().flag(thought) {
+        return;
     }
+    game.set_changed();
 }
 
 #[derive(Component)]
 struct PlayerIndicator;
 
 #[derive(Component)]
-struct Marker;
+struct Marker {
+    at: Location,
+    player: Player,
+}
 
 #[derive(Component)]
 struct Selector {
@@ -292,9 +323,61 @@ struct Selector {
     z: usize,
 }
 
-#[derive(Resource, DerefMut, Deref)]
+#[derive(Resource)]
 pub struct MyGame {
     pub game: TTTTState,
+    /// Every move played so far, in order.
+    pub moves: Vec<Location>,
+    /// When the player to move started thinking, for the clock.
+    pub turn_started: Instant,
+    /// An offer waiting for an answer, and who made it.
+    pub offer: Option<(Player, Offer)>,
+}
+
+impl MyGame {
+    /// A local game played against `control`'s clock.
+    pub fn with_clock(control: TimeControl) -> Self {
+        Self {
+            game: TTTTState::with_clock(control),
+            ..default()
+        }
+    }
+
+    pub fn play(&mut self, loc: Location) -> Result<(), PlaceErr> {
+        self.game.play_timed(loc, self.turn_started.elapsed())?;
+        self.moves.push(loc);
+        self.turn_started = Instant::now();
+        self.offer = None;
+        Ok(())
+    }
+
+    /// Agrees to the offer waiting for an answer, if there is one.
+    pub fn accept_offer(&mut self) {
+        let Some((by, offer)) = self.offer.take() else {
+            return;
+        };
+        let accepted = match offer {
+            Offer::Draw => self.game.agree_draw(),
+            Offer::Takeback => self.game.take_back(by, &mut self.moves),
+        };
+        if accepted.is_ok() {
+            self.turn_started = Instant::now();
+        }
+    }
+}
+
+impl std::ops::Deref for MyGame {
+    type Target = TTTTState;
+
+    fn deref(&self) -> &TTTTState {
+        &self.game
+    }
+}
+
+impl std::ops::DerefMut for MyGame {
+    fn deref_mut(&mut self) -> &mut TTTTState {
+        &mut self.game
+    }
 }
 
 impl Default for MyGame {
@@ -304,7 +387,11 @@ impl Default for MyGame {
                 board: Board::new(),
                 status: GamePlayStatus::Playing(Player::A),
                 players: vec![Player::A, Player::B],
+                clock: None,
             },
+            moves: vec![],
+            turn_started: Instant::now(),
+            offer: None,
         }
     }
 }
@@ -312,45 +399,90 @@ impl Default for MyGame {
 #[derive(Component)]
 struct ClickableNode(Location);
 
-fn create_frame(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let half_thickness = 0.01;
-    let half_length = 1.5;
-    let rail = meshes.add(Mesh::from(shape::Box {
-        min_x: -half_thickness,
-        max_x: half_thickness,
-        min_y: -half_length - half_thickness,
-        max_y: half_length + half_thickness,
-        min_z: -half_thickness,
-        max_z: half_thickness,
-    }));
-
-    for z in 0..4 {
-        for y in 0..4 {
-            for x in 0..4 {
-                commands.spawn((
-                    PbrBundle {
-                        mesh: meshes.add(Mesh::from(shape::Icosphere {
-                            radius: 0.10,
-                            ..default()
-                        })),
-                        material: materials.add(Color::rgba(0.0, 0.0, 0.0, 1.0).into()),
-                        transform: Transform::from_xyz(
-                            x as f32 - 1.5,
-                            y as f32 - 1.5,
-                            z as f32 - 1.5,
-                        ),
-                        ..default()
-                    },
-                    PickableBundle::default(),
-                    ClickableNode((x, y, z).into()),
-                ));
-            }
+/// Every spot on the board, a plane at a time.
+fn all_locations() -> impl Iterator<Item = Location> {
+    (0..4).flat_map(|z| (0..4).flat_map(move |y| (0..4).map(move |x| Location::new(x, y, z))))
+}
+
+/// Where `loc` is drawn, with the board centered on the origin.
+fn position(loc: Location) -> Vec3 {
+    Vec3::new(loc.x as f32 - 1.5, loc.y as f32 - 1.5, loc.z as f32 - 1.5)
+}
+
+/// The meshes and materials the board is drawn with, made once and shared
+/// by every entity that uses them.
+#[derive(Resource)]
+struct BoardAssets {
+    piece: Handle<Mesh>,
+    node: Handle<Mesh>,
+    rail: Handle<Mesh>,
+    a: Handle<StandardMaterial>,
+    b: Handle<StandardMaterial>,
+    selector: Handle<StandardMaterial>,
+    frame: Handle<StandardMaterial>,
+}
+
+impl BoardAssets {
+    fn player(&self, player: Player) -> Handle<StandardMaterial> {
+        match player {
+            Player::A => self.a.clone(),
+            Player::B => self.b.clone(),
         }
     }
+}
+
+impl FromWorld for BoardAssets {
+    fn from_world(world: &mut World) -> Self {
+        let half_thickness = 0.01;
+        let half_length = 1.5;
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let piece = meshes.add(Mesh::from(shape::Icosphere {
+            radius: 0.35,
+            ..default()
+        }));
+        let node = meshes.add(Mesh::from(shape::Icosphere {
+            radius: 0.10,
+            ..default()
+        }));
+        let rail = meshes.add(Mesh::from(shape::Box {
+            min_x: -half_thickness,
+            max_x: half_thickness,
+            min_y: -half_length - half_thickness,
+            max_y: half_length + half_thickness,
+            min_z: -half_thickness,
+            max_z: half_thickness,
+        }));
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        Self {
+            piece,
+            node,
+            rail,
+            a: materials.add(Color::rgba(1.0, 1.0, 1.0, 0.75).into()),
+            b: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.75).into()),
+            selector: materials.add(Color::rgba(0.0, 1.0, 0.0, 0.5).into()),
+            frame: materials.add(Color::rgb(0.0, 0.0, 0.0).into()),
+        }
+    }
+}
+
+fn create_frame(mut commands: Commands, assets: Res<BoardAssets>) {
+    for loc in all_locations() {
+        commands.spawn((
+            PbrBundle {
+                mesh: assets.node.clone(),
+                material: assets.frame.clone(),
+                transform: Transform::from_translation(position(loc)),
+                ..default()
+            },
+            // Picked for hovering and clicking, but without the highlight,
+            // since the piece previewed there says more.
+            PickableMesh::default(),
+            Interaction::default(),
+            FocusPolicy::default(),
+            Hover::default(),
+            ClickableNode(loc),
+        ));
+    }
 
     let num_rails = 4;
     let offset = 1.5;
@@ -358,8 +490,8 @@ fn create_frame(
     for x in 0..num_rails {
         for z in 0..num_rails {
             commands.spawn(PbrBundle {
-                mesh: rail.clone(),
-                material: materials.add(Color::rgb(0.0, 0.0, 0.0).into()),
+                mesh: assets.rail.clone(),
+                material: assets.frame.clone(),
                 transform: Transform::from_xyz(x as f32 - offset, 0.0, z as f32 - offset),
                 ..default()
             });
@@ -369,8 +501,8 @@ fn create_frame(
     for x in 0..num_rails {
         for y in 0..num_rails {
             commands.spawn(PbrBundle {
-                mesh: rail.clone(),
-                material: materials.add(Color::rgb(0.0, 0.0, 0.0).into()),
+                mesh: assets.rail.clone(),
+                material: assets.frame.clone(),
                 transform: {
                     let mut transform =
                         Transform::from_xyz(x as f32 - offset, y as f32 - offset, 0.0);
@@ -385,8 +517,8 @@ fn create_frame(
     for y in 0..num_rails {
         for z in 0..num_rails {
             commands.spawn(PbrBundle {
-                mesh: rail.clone(),
-                material: materials.add(Color::rgb(0.0, 0.0, 0.0).into()),
+                mesh: assets.rail.clone(),
+                material: assets.frame.clone(),
                 transform: {
                     let mut transform =
                         Transform::from_xyz(0.0, y as f32 - offset, z as f32 - offset);
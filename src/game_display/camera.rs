@@ -0,0 +1,238 @@
+use super::{chat::ChatBox, Settings};
+use bevy::{
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+};
+use core::f32::consts::{FRAC_PI_2, PI, TAU};
+
+/// How fast the camera eases toward where it's headed: each second, what's
+/// left of the way shrinks by a factor of `e` this many times over.
+const EASING: f32 = 8.0;
+
+/// Radians turned for every pixel the mouse is dragged.
+const DRAG: f32 = 0.008;
+
+/// Radians a second the keyboard turns the camera.
+const TURN: f32 = 1.5;
+
+/// Radians a second the camera drifts round when auto-rotating.
+const DRIFT: f32 = 0.15;
+
+/// How much closer each line of scroll brings the camera.
+const ZOOM: f32 = 0.1;
+
+const NEAREST: f32 = 4.5;
+const FARTHEST: f32 = 16.0;
+
+/// Just short of straight up or down, where looking at the board loses
+/// track of which way is up.
+const STEEPEST: f32 = FRAC_PI_2 - 0.01;
+
+/// Where a camera looking at the middle of the board is: turned `yaw`
+/// around it, raised `pitch` above it, and `distance` away.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct View {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+}
+
+impl View {
+    pub const TOP: View = View {
+        yaw: 0.0,
+        pitch: STEEPEST,
+        distance: 8.0,
+    };
+    pub const FRONT: View = View {
+        yaw: 0.0,
+        pitch: 0.0,
+        distance: 8.0,
+    };
+    /// Looking down the diagonal from a corner of the board, at
+    /// `atan(1/√2)` above the horizontal.
+    pub const ISOMETRIC: View = View {
+        yaw: -PI / 4.0,
+        pitch: 0.6155,
+        distance: 8.0,
+    };
+
+    /// The view from `translation`, looking at the middle of the board.
+    pub fn from_translation(translation: Vec3) -> Self {
+        let distance = translation.length();
+        Self {
+            yaw: translation.x.atan2(translation.z),
+            pitch: (translation.y / distance).asin(),
+            distance,
+        }
+    }
+
+    pub fn transform(&self) -> Transform {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        let translation =
+            self.distance * Vec3::new(cos_pitch * sin_yaw, sin_pitch, cos_pitch * cos_yaw);
+        Transform::from_translation(translation).looking_at(Vec3::ZERO, Vec3::Y)
+    }
+
+    /// Moves `seconds` of the way from here to `goal`. However the time's
+    /// split up, the camera ends up in the same place, so it moves the same
+    /// at any frame rate.
+    pub fn eased(&self, goal: &View, seconds: f32) -> View {
+        let t = 1.0 - (-EASING * seconds).exp();
+        View {
+            yaw: self.yaw + (goal.yaw - self.yaw) * t,
+            pitch: self.pitch + (goal.pitch - self.pitch) * t,
+            distance: self.distance + (goal.distance - self.distance) * t,
+        }
+    }
+
+    /// `preset`, turned by whole turns to be the shortest way round from
+    /// here, and as far away as we are now.
+    fn nearest(&self, preset: View) -> View {
+        View {
+            yaw: preset.yaw + ((self.yaw - preset.yaw) / TAU).round() * TAU,
+            distance: self.distance,
+            ..preset
+        }
+    }
+
+    fn clamped(self) -> View {
+        View {
+            pitch: self.pitch.clamp(-STEEPEST, STEEPEST),
+            distance: self.distance.clamp(NEAREST, FARTHEST),
+            ..self
+        }
+    }
+}
+
+/// A camera that orbits the board, easing from where it is to where it's
+/// been told to go.
+#[derive(Component)]
+pub struct OrbitCamera {
+    pub view: View,
+    pub goal: View,
+}
+
+impl OrbitCamera {
+    pub fn new(view: View) -> Self {
+        Self { view, goal: view }
+    }
+}
+
+/// `W`/`A`/`S`/`D` turn the camera around the board. `F1`, `F2` and `F3`
+/// swing round to look from the top, the front, or a corner, and `F4`
+/// switches the slow drift round the board on and off.
+pub fn steer_camera(
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    chat: Res<ChatBox>,
+    mut settings: ResMut<Settings>,
+    mut cameras: Query<&mut OrbitCamera>,
+) {
+    let seconds = time.delta_seconds();
+    let typing = chat.is_typing();
+    if !typing && keys.just_pressed(KeyCode::F4) {
+        settings.auto_rotate = !settings.auto_rotate;
+    }
+    for mut camera in &mut cameras {
+        if settings.auto_rotate {
+            camera.goal.yaw += DRIFT * seconds;
+        }
+        if typing {
+            continue;
+        }
+        let turn = TURN * seconds;
+        for (key, yaw, pitch) in [
+            (KeyCode::A, -turn, 0.0),
+            (KeyCode::D, turn, 0.0),
+            (KeyCode::W, 0.0, turn),
+            (KeyCode::S, 0.0, -turn),
+        ] {
+            if keys.pressed(key) {
+                camera.goal.yaw += yaw;
+                camera.goal.pitch += pitch;
+            }
+        }
+        for (key, preset) in [
+            (KeyCode::F1, View::TOP),
+            (KeyCode::F2, View::FRONT),
+            (KeyCode::F3, View::ISOMETRIC),
+        ] {
+            if keys.just_pressed(key) {
+                camera.goal = camera.view.nearest(preset);
+            }
+        }
+    }
+}
+
+/// Dragging with the right mouse button turns the camera around the board,
+/// and the scroll wheel zooms. The camera then eases toward where it's
+/// been sent.
+pub fn move_camera(
+    time: Res<Time>,
+    buttons: Res<Input<MouseButton>>,
+    mut motion: EventReader<MouseMotion>,
+    mut scroll: EventReader<MouseWheel>,
+    mut cameras: Query<(&mut Transform, &mut OrbitCamera)>,
+) {
+    let dragged: Vec2 = motion.iter().map(|motion| motion.delta).sum();
+    let zoomed: f32 = scroll
+        .iter()
+        .map(|scroll| match scroll.unit {
+            MouseScrollUnit::Line => scroll.y,
+            // Pixel-precise trackpads give many small steps.
+            MouseScrollUnit::Pixel => scroll.y / 20.0,
+        })
+        .sum();
+    for (mut transform, mut camera) in &mut cameras {
+        let mut goal = camera.goal;
+        if buttons.pressed(MouseButton::Right) {
+            goal.yaw -= dragged.x * DRAG;
+            goal.pitch += dragged.y * DRAG;
+        }
+        goal.distance *= (1.0 - ZOOM).powf(zoomed);
+        camera.goal = goal.clamped();
+        camera.view = camera.view.eased(&camera.goal, time.delta_seconds());
+        *transform = camera.view.transform();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easing_ends_up_in_the_same_place_at_any_frame_rate() {
+        let start = View::FRONT;
+        let goal = View {
+            yaw: 2.0,
+            pitch: 1.0,
+            distance: 12.0,
+        };
+        let once = start.eased(&goal, 0.2);
+        let mut often = start;
+        for _ in 0..20 {
+            often = often.eased(&goal, 0.01);
+        }
+        assert!((once.yaw - often.yaw).abs() < 1e-4);
+        assert!((once.pitch - often.pitch).abs() < 1e-4);
+        assert!((once.distance - often.distance).abs() < 1e-4);
+    }
+
+    #[test]
+    fn presets_are_reached_the_short_way_round() {
+        let view = View {
+            yaw: 3.0 * TAU + 0.1,
+            ..View::FRONT
+        };
+        assert!((view.nearest(View::FRONT).yaw - 3.0 * TAU).abs() < 1e-4);
+    }
+
+    #[test]
+    fn a_view_comes_back_from_its_transform() {
+        let view = View::from_translation(View::ISOMETRIC.transform().translation);
+        assert!((view.yaw - View::ISOMETRIC.yaw).abs() < 1e-4);
+        assert!((view.pitch - View::ISOMETRIC.pitch).abs() < 1e-4);
+        assert!((view.distance - 8.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,116 @@
+use super::NetworkGame;
+use bevy::prelude::*;
+use tic_tac_toc_toe::net::server::CHAT_LENGTH;
+
+/// How many lines of chat the panel shows.
+const SHOWN: usize = 6;
+
+/// What's being typed into the chat, if anything. While it is, the keyboard
+/// goes to the chat rather than the board.
+#[derive(Resource, Default)]
+pub struct ChatBox {
+    draft: Option<String>,
+}
+
+impl ChatBox {
+    pub fn is_typing(&self) -> bool {
+        self.draft.is_some()
+    }
+}
+
+#[derive(SystemLabel)]
+pub struct Typing;
+
+#[derive(Component)]
+pub struct ChatPanel;
+
+/// The chat panel sits under the `PlayerIndicator`, and only says anything
+/// in network games.
+pub fn spawn_panel(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = TextStyle {
+        font: asset_server.load("fonts/Party Confetti.ttf"),
+        font_size: 24.0,
+        color: Color::WHITE,
+    };
+    commands.spawn((
+        TextBundle::from_section("", style).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                top: Val::Px(140.0),
+                ..default()
+            },
+            max_size: Size::new(Val::Px(500.0), Val::Undefined),
+            ..default()
+        }),
+        ChatPanel,
+    ));
+}
+
+/// `T` starts a message, `Return` sends it and `Escape` gives it up.
+pub fn type_message(
+    input: Res<Input<KeyCode>>,
+    mut typed: EventReader<ReceivedCharacter>,
+    mut chat: ResMut<ChatBox>,
+    network: Option<NonSendMut<NetworkGame>>,
+) {
+    let Some(mut network) = network else {
+        // A message half typed when the game went away goes with it.
+        if chat.is_typing() {
+            chat.draft = None;
+        }
+        return;
+    };
+    let Some(draft) = &mut chat.draft else {
+        if input.just_pressed(KeyCode::T) {
+            // The `t` that opened the chat isn't part of the message.
+            typed.clear();
+            chat.draft = Some(String::new());
+        }
+        return;
+    };
+    for c in typed.iter().map(|typed| typed.char) {
+        if !c.is_control() && draft.chars().count() < CHAT_LENGTH {
+            draft.push(c);
+        }
+    }
+    if input.just_pressed(KeyCode::Back) {
+        draft.pop();
+    }
+    if input.just_pressed(KeyCode::Return) {
+        if !draft.trim().is_empty() {
+            network.say(draft);
+        }
+        chat.draft = None;
+    } else if input.just_pressed(KeyCode::Escape) {
+        chat.draft = None;
+    }
+}
+
+pub fn update_panel(
+    chat: Res<ChatBox>,
+    network: Option<NonSend<NetworkGame>>,
+    mut panels: Query<&mut Text, With<ChatPanel>>,
+) {
+    let Some(network) = network else {
+        for mut panel in &mut panels {
+            panel.sections[0].value.clear();
+        }
+        return;
+    };
+    let mut lines: Vec<String> = network
+        .chat
+        .iter()
+        .rev()
+        .take(SHOWN)
+        .rev()
+        .map(|(from, text)| format!("{}: {}", from, text))
+        .collect();
+    lines.push(match &chat.draft {
+        Some(draft) => format!("> {}_", draft),
+        None => "(T to chat)".to_string(),
+    });
+    for mut panel in &mut panels {
+        panel.sections[0].value = lines.join("\n");
+    }
+}
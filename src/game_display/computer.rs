@@ -0,0 +1,117 @@
+use super::MyGame;
+use bevy::prelude::*;
+use tic_tac_toc_toe::{
+    common::Location,
+    engine::EngineWorker,
+    logic::{
+        computer_player::{accepts, Ponderer, SearchHandle},
+        search::Limits,
+        GamePlayStatus, Player, TTTTState,
+    },
+};
+
+/// The computer's side of a game against it, thinking on a background
+/// thread so the board keeps drawing. Like `NetworkGame`, it's a non-send
+/// resource.
+pub struct ComputerPlayer {
+    pub side: Player,
+    look_ahead: u8,
+    search: Option<SearchHandle>,
+    /// Thinking ahead while it's the other player's turn.
+    ponder: Option<Ponderer>,
+    /// An engine in another process that picks the moves instead, and the
+    /// position it was last asked about.
+    engine: Option<(EngineWorker, Option<TTTTState>)>,
+}
+
+impl ComputerPlayer {
+    pub fn new(side: Player, look_ahead: u8) -> Self {
+        Self {
+            side,
+            look_ahead,
+            search: None,
+            ponder: None,
+            engine: None,
+        }
+    }
+
+    /// Has the engine started by `command` choose the moves, searching as
+    /// deep as the computer would look ahead.
+    pub fn with_engine(self, command: String) -> Self {
+        let limits = Limits::depth(self.look_ahead);
+        Self {
+            engine: Some((EngineWorker::start(command, limits), None)),
+            ..self
+        }
+    }
+
+    /// The engine's move in `state`, once it's answered. An engine that
+    /// can't give one is let go, and the computer plays on by itself.
+    fn ask_engine(&mut self, state: &TTTTState) -> Option<Location> {
+        let (engine, asked) = self.engine.as_mut()?;
+        if asked.as_ref() != Some(state) {
+            engine.ask(state);
+            *asked = Some(state.clone());
+        }
+        // Answers about positions since left behind are dropped.
+        match engine.answer()? {
+            (answered, Ok(Some(loc))) if answered == *state => Some(loc),
+            (answered, failed) if answered == *state => {
+                let why = failed.err().unwrap_or_else(|| "no move".to_string());
+                eprintln!("the engine gave no move, so the computer plays on: {}", why);
+                self.engine = None;
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Plays the computer's moves once it's found them, or once the engine's
+/// given them, and answers offers straight away. On the other player's turn
+/// it ponders, and its search carries on from there if they play the reply
+/// it guessed. A search for a
+/// position that's since changed, say by a takeback, is dropped and
+/// started again.
+pub fn play_computer(computer: Option<NonSendMut<ComputerPlayer>>, mut game: ResMut<MyGame>) {
+    let Some(mut computer) = computer else { return };
+    let computer = &mut *computer;
+    if let Some((by, offer)) = game.offer {
+        if by != computer.side {
+            if accepts(offer) {
+                game.accept_offer();
+            } else {
+                game.offer = None;
+            }
+        }
+    }
+    let look_ahead = computer.look_ahead;
+    let playing = matches!(game.status, GamePlayStatus::Playing(_));
+    if game.status != GamePlayStatus::Playing(computer.side) {
+        computer.search = None;
+        computer.ponder = match computer.ponder.take() {
+            Some(ponder) if ponder.is_for(&game.game) => Some(ponder),
+            _ if playing && computer.engine.is_none() => {
+                Some(Ponderer::start(&game.game, look_ahead))
+            }
+            _ => None,
+        };
+        return;
+    }
+    if computer.engine.is_some() {
+        if let Some(loc) = computer.ask_engine(&game.game) {
+            game.play(loc).ok();
+        }
+        return;
+    }
+    let search = match &mut computer.search {
+        Some(search) if search.is_for(&game.game) => search,
+        search => search.insert(match computer.ponder.take() {
+            Some(ponder) => ponder.resolve(&game.game, look_ahead),
+            None => SearchHandle::spawn(&game.game, look_ahead),
+        }),
+    };
+    if let Some(loc) = search.try_take(&game.game) {
+        game.play(loc).ok();
+    }
+}
@@ -0,0 +1,133 @@
+use super::{AppState, MyGame, NetworkGame};
+use bevy::prelude::*;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use tic_tac_toc_toe::net::{
+    discovery::{discover, Host},
+    protocol::ClientMessage,
+};
+
+/// How long each round of looking for servers waits for answers.
+const ROUND: Duration = Duration::from_secs(1);
+
+const NUMBER_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+/// The "join LAN game" screen: servers found on the local network, kept up
+/// to date by a background thread, one of which is picked with the number
+/// keys.
+#[derive(Resource)]
+pub struct LanBrowser {
+    hosts: Arc<Mutex<Vec<Host>>>,
+    /// How to log in to the server picked.
+    login: ClientMessage,
+}
+
+impl LanBrowser {
+    /// Starts probing `targets` for servers, until the browser is dropped.
+    pub fn start(targets: Vec<SocketAddr>, login: ClientMessage) -> Self {
+        let hosts = Arc::new(Mutex::new(vec![]));
+        let found = Arc::clone(&hosts);
+        thread::spawn(move || {
+            while Arc::strong_count(&found) > 1 {
+                match discover(&targets, ROUND) {
+                    Ok(hosts) => *found.lock().unwrap() = hosts,
+                    Err(_) => thread::sleep(ROUND),
+                }
+            }
+        });
+        Self { hosts, login }
+    }
+
+    fn hosts(&self) -> Vec<Host> {
+        self.hosts.lock().unwrap().clone()
+    }
+}
+
+#[derive(Component)]
+pub struct LanList;
+
+/// The list sits in the bottom corner of the main menu, and only says
+/// anything once we're looking for games.
+pub fn spawn_list(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = TextStyle {
+        font: asset_server.load("fonts/Party Confetti.ttf"),
+        font_size: 30.0,
+        color: Color::WHITE,
+    };
+    commands.spawn((
+        TextBundle::from_section("", style).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        LanList,
+    ));
+}
+
+pub fn update_list(browser: Option<Res<LanBrowser>>, mut lists: Query<&mut Text, With<LanList>>) {
+    let Some(browser) = browser else {
+        for mut list in &mut lists {
+            list.sections[0].value.clear();
+        }
+        return;
+    };
+    let hosts = browser.hosts();
+    let mut text = String::from(if hosts.is_empty() {
+        "Looking for games on the network..."
+    } else {
+        "Join a LAN game:"
+    });
+    for (i, host) in hosts.iter().take(NUMBER_KEYS.len()).enumerate() {
+        text += &format!("\n{}  {}  ({} open)", i + 1, host.addr, host.open.len());
+    }
+    for mut list in &mut lists {
+        list.sections[0].value = text.clone();
+    }
+}
+
+/// Connects to the server picked from the list, taking its oldest open game
+/// or asking for a quick match, and closes the list to start playing.
+pub fn join_lan_game(world: &mut World) {
+    let Some(browser) = world.get_resource::<LanBrowser>() else {
+        return;
+    };
+    let input = world.resource::<Input<KeyCode>>();
+    let Some(host) = NUMBER_KEYS
+        .iter()
+        .position(|key| input.just_pressed(*key))
+        .and_then(|i| browser.hosts().get(i).cloned())
+    else {
+        return;
+    };
+    let request = match host.open.first() {
+        Some(&game) => ClientMessage::Join { game },
+        None => ClientMessage::QuickMatch { computer: false },
+    };
+    let login = browser.login.clone();
+    let network = NetworkGame::connect(&host.addr.to_string(), Some(&login), request);
+    world.remove_resource::<LanBrowser>();
+    world.insert_resource(MyGame::default());
+    world.insert_non_send_resource(network);
+    world
+        .resource_mut::<State<AppState>>()
+        .set(AppState::Playing)
+        .ok();
+}
@@ -0,0 +1,574 @@
+use super::{
+    computer::ComputerPlayer, lan::LanBrowser, navigation::Navigation, placing::PlaceBy, AppState,
+    MyGame, NetworkGame,
+};
+use bevy::prelude::*;
+use std::{
+    io,
+    net::{TcpListener, UdpSocket},
+    thread,
+};
+use tic_tac_toc_toe::{
+    logic::{clock::TimeControl, GamePlayStatus, Player},
+    net::{
+        discovery::{self, DISCOVERY_PORT},
+        protocol::ClientMessage,
+        server::{Server, ServerConfig, PORT},
+    },
+};
+
+/// The clocks the menu cycles through, after playing without one.
+const CLOCKS: [&str; 4] = ["60+1", "180+2", "300", "600+5b"];
+
+/// How far ahead the computer can be set to look.
+const DEPTHS: std::ops::RangeInclusive<u8> = 1..=5;
+
+const IDLE: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED: Color = Color::rgb(0.25, 0.25, 0.25);
+
+/// What the menus have chosen for the next game.
+#[derive(Resource)]
+pub struct Settings {
+    /// The clock local games, and games we host, are played with.
+    pub clock: Option<TimeControl>,
+    /// The side the computer takes against us.
+    pub computer: Player,
+    /// How many moves ahead the computer looks.
+    pub depth: u8,
+    /// An engine in another process to play as the computer, by its command
+    /// line.
+    pub engine: Option<String>,
+    /// Whether the camera drifts slowly round the board.
+    pub auto_rotate: bool,
+    /// Whether the selector keys move it as the board looks from the camera.
+    pub navigation: Navigation,
+    /// Whether a click on the board places a piece, or takes a double click.
+    pub place_by: PlaceBy,
+    /// How to log in to servers. Without one, we register as a guest, and
+    /// log in with the token that gets us afterwards.
+    pub login: Option<ClientMessage>,
+    /// Why the last game picked from the menu couldn't start.
+    pub problem: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            clock: None,
+            computer: Player::B,
+            depth: 3,
+            engine: None,
+            auto_rotate: false,
+            navigation: Navigation::CameraRelative,
+            place_by: PlaceBy::Click,
+            login: None,
+            problem: None,
+        }
+    }
+}
+
+impl Settings {
+    fn login(&mut self) -> ClientMessage {
+        self.login
+            .get_or_insert_with(|| ClientMessage::Register {
+                name: format!("guest-{}", rand::random::<u16>()),
+            })
+            .clone()
+    }
+}
+
+/// The clock after `clock` in the menu: none, then each of `CLOCKS`, then
+/// none again. A clock from the command line that isn't one of them goes
+/// back to none.
+fn next_clock(clock: Option<TimeControl>) -> Option<TimeControl> {
+    let controls: Vec<TimeControl> = CLOCKS.iter().map(|c| c.parse().unwrap()).collect();
+    match clock {
+        None => controls.first().copied(),
+        Some(clock) => controls
+            .iter()
+            .position(|c| *c == clock)
+            .and_then(|i| controls.get(i + 1).copied()),
+    }
+}
+
+/// A fresh game, played against `clock` if there is one.
+pub fn new_game(clock: Option<TimeControl>) -> MyGame {
+    clock.map_or_else(MyGame::default, MyGame::with_clock)
+}
+
+/// A server started from the menu, which serves until the app closes.
+#[derive(Resource)]
+struct Hosting(Server);
+
+/// Everything the menus can do, one per button.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuButton {
+    HotSeat,
+    Computer,
+    Host,
+    Join,
+    ComputerSide,
+    Depth,
+    Clock,
+    AutoRotate,
+    Navigation,
+    PlaceBy,
+    Resume,
+    Rematch,
+    NewGame,
+    MainMenu,
+}
+
+impl MenuButton {
+    fn label(self, settings: &Settings) -> String {
+        match self {
+            MenuButton::HotSeat => "Two players, one computer".to_string(),
+            MenuButton::Computer => match &settings.engine {
+                Some(engine) => format!("Play {}", engine),
+                None => "Play the computer".to_string(),
+            },
+            MenuButton::Host => "Host a network game".to_string(),
+            MenuButton::Join => "Join a network game".to_string(),
+            MenuButton::ComputerSide => format!("Computer plays {}", settings.computer),
+            MenuButton::Depth => format!("Computer looks {} moves ahead", settings.depth),
+            MenuButton::Clock => match settings.clock {
+                Some(clock) => format!("Clock: {}", clock),
+                None => "No clock".to_string(),
+            },
+            MenuButton::AutoRotate if settings.auto_rotate => "Camera drifts round".to_string(),
+            MenuButton::AutoRotate => "Camera holds still".to_string(),
+            MenuButton::Navigation => match settings.navigation {
+                Navigation::CameraRelative => "Keys move as the camera looks".to_string(),
+                Navigation::Absolute => "Keys move along fixed axes".to_string(),
+            },
+            MenuButton::PlaceBy => match settings.place_by {
+                PlaceBy::Click => "Click to place".to_string(),
+                PlaceBy::DoubleClick => "Double-click to place".to_string(),
+            },
+            MenuButton::Resume => "Resume".to_string(),
+            MenuButton::Rematch => "Rematch".to_string(),
+            MenuButton::NewGame => "New game".to_string(),
+            MenuButton::MainMenu => "Main menu".to_string(),
+        }
+    }
+}
+
+/// The root of whichever menu is showing.
+#[derive(Component)]
+pub struct Menu;
+
+fn spawn_menu(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    title: &str,
+    buttons: &[MenuButton],
+) {
+    let font = asset_server.load("fonts/Party Confetti.ttf");
+    let title_style = TextStyle {
+        font: font.clone(),
+        font_size: 50.0,
+        color: Color::WHITE,
+    };
+    let button_style = TextStyle {
+        font,
+        font_size: 28.0,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
+                ..default()
+            },
+            Menu,
+        ))
+        .with_children(|menu| {
+            menu.spawn(TextBundle::from_section(title, title_style));
+            for button in buttons {
+                menu.spawn((
+                    ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(460.0), Val::Px(40.0)),
+                            margin: UiRect::all(Val::Px(4.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        background_color: IDLE.into(),
+                        ..default()
+                    },
+                    *button,
+                ))
+                .with_children(|button| {
+                    button.spawn(TextBundle::from_section("", button_style.clone()));
+                });
+            }
+        });
+}
+
+pub fn spawn_main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    use MenuButton::*;
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        "Tic Tac Toc Toe",
+        &[
+            HotSeat,
+            Computer,
+            Host,
+            Join,
+            ComputerSide,
+            Depth,
+            Clock,
+            AutoRotate,
+            Navigation,
+            PlaceBy,
+        ],
+    );
+}
+
+/// Offers another game once one ends: the same again on this computer, or
+/// the next quick match over the network. Spectators can only leave.
+pub fn spawn_game_over_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game: Res<MyGame>,
+    network: Option<NonSend<NetworkGame>>,
+) {
+    let buttons: &[MenuButton] = match network {
+        Some(network) if network.seat.is_none() => &[MenuButton::MainMenu],
+        Some(_) => &[MenuButton::NewGame, MenuButton::MainMenu],
+        None => &[MenuButton::Rematch, MenuButton::MainMenu],
+    };
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        &game.status.to_string(),
+        buttons,
+    );
+}
+
+/// Pausing stops a local game's clock, but a network game goes on without
+/// us.
+pub fn spawn_pause_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    network: Option<NonSend<NetworkGame>>,
+) {
+    let title = match network {
+        Some(_) => "The game goes on",
+        None => "Paused",
+    };
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        title,
+        &[
+            MenuButton::Resume,
+            MenuButton::AutoRotate,
+            MenuButton::Navigation,
+            MenuButton::PlaceBy,
+            MenuButton::MainMenu,
+        ],
+    );
+}
+
+pub fn despawn_menus(mut commands: Commands, menus: Query<Entity, With<Menu>>) {
+    for menu in &menus {
+        commands.entity(menu).despawn_recursive();
+    }
+}
+
+/// Keeps the buttons' labels in step with the settings, and shows which one
+/// the mouse is over.
+pub fn update_buttons(
+    settings: Res<Settings>,
+    mut buttons: Query<(&MenuButton, &Interaction, &Children, &mut BackgroundColor)>,
+    mut labels: Query<&mut Text>,
+) {
+    for (button, interaction, children, mut background) in &mut buttons {
+        *background = match interaction {
+            Interaction::None => IDLE,
+            Interaction::Hovered | Interaction::Clicked => HOVERED,
+        }
+        .into();
+        let label = button.label(&settings);
+        for child in children {
+            if let Ok(mut text) = labels.get_mut(*child) {
+                if text.sections[0].value != label {
+                    text.sections[0].value = label.clone();
+                }
+            }
+        }
+    }
+}
+
+/// Shows why the last game picked from the main menu didn't start.
+pub fn show_problem(settings: Res<Settings>, mut menus: Query<&mut Text, With<MenuProblem>>) {
+    let problem = settings.problem.clone().unwrap_or_default();
+    for mut text in &mut menus {
+        if text.sections[0].value != problem {
+            text.sections[0].value = problem.clone();
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct MenuProblem;
+
+pub fn spawn_problem(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = TextStyle {
+        font: asset_server.load("fonts/Party Confetti.ttf"),
+        font_size: 24.0,
+        color: Color::ORANGE_RED,
+    };
+    commands.spawn((
+        TextBundle::from_section("", style).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                right: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        MenuProblem,
+    ));
+}
+
+/// Does what the button says. Starting a game needs the whole world, to
+/// swap in the computer or the connection, so that's left to a command.
+pub fn press_buttons(
+    mut commands: Commands,
+    buttons: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction == Interaction::Clicked {
+            let button = *button;
+            commands.add(move |world: &mut World| press(world, button));
+        }
+    }
+}
+
+fn press(world: &mut World, button: MenuButton) {
+    let settings = world.resource::<Settings>();
+    let (side, depth, clock) = (settings.computer, settings.depth, settings.clock);
+    match button {
+        MenuButton::ComputerSide => world.resource_mut::<Settings>().computer = side.other_player(),
+        MenuButton::Depth => {
+            world.resource_mut::<Settings>().depth = if depth < *DEPTHS.end() {
+                depth + 1
+            } else {
+                *DEPTHS.start()
+            }
+        }
+        MenuButton::Clock => world.resource_mut::<Settings>().clock = next_clock(clock),
+        MenuButton::AutoRotate => {
+            let mut settings = world.resource_mut::<Settings>();
+            settings.auto_rotate = !settings.auto_rotate;
+        }
+        MenuButton::HotSeat => start(world, None),
+        MenuButton::Computer => {
+            let computer = ComputerPlayer::new(side, depth);
+            let computer = match world.resource::<Settings>().engine.clone() {
+                Some(engine) => computer.with_engine(engine),
+                None => computer,
+            };
+            start(world, Some(computer));
+        }
+        MenuButton::Host => match host(world) {
+            Ok(network) => {
+                start(world, None);
+                world.insert_non_send_resource(network);
+            }
+            Err(e) => {
+                world.resource_mut::<Settings>().problem =
+                    Some(format!("couldn't host a game: {}", e))
+            }
+        },
+        MenuButton::Join => {
+            let login = {
+                let mut settings = world.resource_mut::<Settings>();
+                settings.problem = None;
+                settings.login()
+            };
+            world.insert_resource(LanBrowser::start(vec![discovery::broadcast()], login));
+        }
+        MenuButton::Navigation => {
+            let mut settings = world.resource_mut::<Settings>();
+            settings.navigation = match settings.navigation {
+                Navigation::CameraRelative => Navigation::Absolute,
+                Navigation::Absolute => Navigation::CameraRelative,
+            };
+        }
+        MenuButton::PlaceBy => {
+            let mut settings = world.resource_mut::<Settings>();
+            settings.place_by = match settings.place_by {
+                PlaceBy::Click => PlaceBy::DoubleClick,
+                PlaceBy::DoubleClick => PlaceBy::Click,
+            };
+        }
+        MenuButton::Resume => {
+            world.resource_mut::<State<AppState>>().pop().ok();
+        }
+        MenuButton::Rematch | MenuButton::NewGame => {
+            // Over the network the server says what clock there is.
+            let game = match world.get_non_send_resource_mut::<NetworkGame>() {
+                Some(mut network) => {
+                    network.queue();
+                    MyGame::default()
+                }
+                None => new_game(clock),
+            };
+            world.insert_resource(game);
+            world
+                .resource_mut::<State<AppState>>()
+                .set(AppState::Playing)
+                .ok();
+        }
+        MenuButton::MainMenu => leave_game(world),
+    }
+}
+
+/// Starts a fresh game on this computer, against `computer` if given.
+/// Whoever we were playing before is let go.
+fn start(world: &mut World, computer: Option<ComputerPlayer>) {
+    let clock = world.resource::<Settings>().clock;
+    world.resource_mut::<Settings>().problem = None;
+    world.remove_resource::<LanBrowser>();
+    world.remove_non_send_resource::<NetworkGame>();
+    world.remove_non_send_resource::<ComputerPlayer>();
+    if let Some(computer) = computer {
+        world.insert_non_send_resource(computer);
+    }
+    world.insert_resource(new_game(clock));
+    world
+        .resource_mut::<State<AppState>>()
+        .set(AppState::Playing)
+        .ok();
+}
+
+/// Starts a server on this computer, the first time, for others on the
+/// network to find, and connects to it for a quick match. New games on it
+/// are played with the clock picked in the menu at the time.
+fn host(world: &mut World) -> io::Result<NetworkGame> {
+    let login = world.resource_mut::<Settings>().login();
+    let clock = world.resource::<Settings>().clock;
+    match world.get_resource::<Hosting>() {
+        Some(Hosting(server)) => server.set_clock(clock),
+        None => {
+            let listener = TcpListener::bind(("0.0.0.0", PORT))?;
+            let server = Server::with_config(ServerConfig { clock, ..default() });
+            let serving = server.clone();
+            thread::spawn(move || serving.serve_tcp(listener));
+            // Players can still come in by address if discovery isn't possible.
+            let discovering = server.clone();
+            if let Ok(socket) = UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT)) {
+                thread::spawn(move || discovering.serve_discovery(socket, PORT));
+            }
+            world.insert_resource(Hosting(server));
+        }
+    }
+    let request = ClientMessage::QuickMatch { computer: false };
+    Ok(NetworkGame::connect(
+        &format!("127.0.0.1:{}", PORT),
+        Some(&login),
+        request,
+    ))
+}
+
+/// Drops whatever game is going on, and goes back to the main menu. A
+/// server we registered with gave us a token, which logs us in next time.
+fn leave_game(world: &mut World) {
+    world.remove_resource::<LanBrowser>();
+    if let Some(network) = world.remove_non_send_resource::<NetworkGame>() {
+        if let Some(login) = network.login() {
+            world.resource_mut::<Settings>().login = Some(login);
+        }
+    }
+    world.remove_non_send_resource::<ComputerPlayer>();
+    let clock = world.resource::<Settings>().clock;
+    world.insert_resource(new_game(clock));
+    world
+        .resource_mut::<State<AppState>>()
+        .replace(AppState::MainMenu)
+        .ok();
+}
+
+/// `Escape` pauses the game, and resumes it again.
+pub fn pause(
+    input: Res<Input<KeyCode>>,
+    mut state: ResMut<State<AppState>>,
+    chat: Res<super::chat::ChatBox>,
+) {
+    if chat.is_typing() || !input.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    match state.current() {
+        AppState::Playing => state.push(AppState::Paused).ok(),
+        AppState::Paused => state.pop().ok(),
+        AppState::MainMenu | AppState::GameOver => None,
+    };
+}
+
+/// When a local game was paused, so its clock can pick up where it was.
+#[derive(Resource)]
+pub struct PausedAt(std::time::Instant);
+
+pub fn stop_clock(mut commands: Commands) {
+    commands.insert_resource(PausedAt(std::time::Instant::now()));
+}
+
+pub fn restart_clock(
+    mut commands: Commands,
+    paused_at: Option<Res<PausedAt>>,
+    mut game: ResMut<MyGame>,
+    network: Option<NonSend<NetworkGame>>,
+) {
+    let Some(paused_at) = paused_at else { return };
+    commands.remove_resource::<PausedAt>();
+    if network.is_none() {
+        game.turn_started += paused_at.0.elapsed();
+    }
+}
+
+/// Moves from playing to the game over screen once the game's decided.
+pub fn end_game(game: Res<MyGame>, mut state: ResMut<State<AppState>>) {
+    if !matches!(game.status, GamePlayStatus::Playing(_)) {
+        state.set(AppState::GameOver).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_clock_setting_goes_round_to_none() {
+        let mut clock = None;
+        let mut seen = vec![];
+        for _ in 0..=CLOCKS.len() {
+            clock = next_clock(clock);
+            seen.push(clock.map(|c| c.to_string()));
+        }
+        assert_eq!(
+            seen,
+            vec![
+                Some("60+1".to_string()),
+                Some("180+2".to_string()),
+                Some("300".to_string()),
+                Some("600+5b".to_string()),
+                None
+            ]
+        );
+        assert_eq!(next_clock(Some("45".parse().unwrap())), None);
+    }
+}
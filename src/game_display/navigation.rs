@@ -0,0 +1,114 @@
+use super::{camera::OrbitCamera, chat::ChatBox, Selector, Settings};
+use bevy::prelude::*;
+
+/// Which way the selector keys move it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Navigation {
+    /// `I`/`K` up and down, `J`/`L` left and right, and `U`/`O` away and
+    /// back, as the board looks from the camera, along whichever of its
+    /// axes is nearest.
+    CameraRelative,
+    /// The same keys always along the same axes of the board, however it's
+    /// turned.
+    Absolute,
+}
+
+/// One step in each direction along each of the board's axes.
+const STEPS: [[i32; 3]; 6] = [
+    [1, 0, 0],
+    [-1, 0, 0],
+    [0, 1, 0],
+    [0, -1, 0],
+    [0, 0, 1],
+    [0, 0, -1],
+];
+
+/// The step along the board's axes that's closest to `direction`, leaving
+/// out any along the axes in `taken`.
+fn nearest_step(direction: Vec3, taken: &[[i32; 3]]) -> [i32; 3] {
+    let along =
+        |step: &[i32; 3]| direction.dot(Vec3::new(step[0] as f32, step[1] as f32, step[2] as f32));
+    STEPS
+        .into_iter()
+        .filter(|step| {
+            !taken
+                .iter()
+                .any(|t| (0..3).any(|i| t[i] != 0 && step[i] != 0))
+        })
+        .max_by(|a, b| along(a).total_cmp(&along(b)))
+        .expect("there's always an axis left")
+}
+
+/// The steps that go right, up and away from the camera, one along each of
+/// the board's axes. Right is settled first, then up, so looking at the
+/// board corner on still gives each key an axis of its own.
+fn camera_steps(camera: &Transform) -> [[i32; 3]; 3] {
+    let right = nearest_step(camera.right(), &[]);
+    let up = nearest_step(camera.up(), &[right]);
+    let away = nearest_step(camera.forward(), &[right, up]);
+    [right, up, away]
+}
+
+fn negated(step: [i32; 3]) -> [i32; 3] {
+    step.map(|d| -d)
+}
+
+/// Moves the selector with `I`/`K`, `J`/`L` and `U`/`O`, which way depending
+/// on `Settings::navigation`.
+pub fn move_selector(
+    input: Res<Input<KeyCode>>,
+    chat: Res<ChatBox>,
+    settings: Res<Settings>,
+    cameras: Query<&Transform, With<OrbitCamera>>,
+    mut selectors: Query<&mut Selector>,
+) {
+    if chat.is_typing() {
+        return;
+    }
+    let [right, up, away] = match (settings.navigation, cameras.get_single()) {
+        (Navigation::CameraRelative, Ok(camera)) => camera_steps(camera),
+        _ => [[-1, 0, 0], [0, 1, 0], [0, 0, 1]],
+    };
+    let keys = [
+        (KeyCode::I, up),
+        (KeyCode::K, negated(up)),
+        (KeyCode::J, negated(right)),
+        (KeyCode::L, right),
+        (KeyCode::U, away),
+        (KeyCode::O, negated(away)),
+    ];
+    for (key, step) in keys {
+        if !input.just_pressed(key) {
+            continue;
+        }
+        for mut selector in &mut selectors {
+            let wrap = |at: usize, by: i32| (at as i32 + by).rem_euclid(4) as usize;
+            selector.x = wrap(selector.x, step[0]);
+            selector.y = wrap(selector.y, step[1]);
+            selector.z = wrap(selector.z, step[2]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_follow_the_camera_round_the_board() {
+        // From the front, right is along x.
+        let front = Transform::from_xyz(0.0, 0.0, 8.0).looking_at(Vec3::ZERO, Vec3::Y);
+        assert_eq!(camera_steps(&front), [[1, 0, 0], [0, 1, 0], [0, 0, -1]]);
+
+        // From behind, everything but up turns round.
+        let behind = Transform::from_xyz(0.0, 0.0, -8.0).looking_at(Vec3::ZERO, Vec3::Y);
+        assert_eq!(camera_steps(&behind), [[-1, 0, 0], [0, 1, 0], [0, 0, 1]]);
+
+        // Halfway over the top, up and away would both be nearest to y, but
+        // each gets an axis of its own.
+        let above = Transform::from_xyz(0.0, 5.0, 5.0).looking_at(Vec3::ZERO, Vec3::Y);
+        let [right, up, away] = camera_steps(&above);
+        assert_eq!(right, [1, 0, 0]);
+        assert_ne!(up.map(i32::abs), away.map(i32::abs));
+    }
+}
@@ -0,0 +1,281 @@
+use super::{MyGame, Selector};
+use bevy::prelude::*;
+use std::{
+    io,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+use tic_tac_toc_toe::{
+    common::Location,
+    logic::{GamePlayStatus, Player, TTTTState},
+    net::{
+        client::Connection,
+        protocol::{ClientMessage, GameId, ServerMessage},
+    },
+};
+
+/// How long to wait between attempts to reach the server again.
+const RETRY: Duration = Duration::from_secs(1);
+
+/// Playing or watching a game hosted by a server. `MyGame` then only
+/// mirrors the server's state, and the local player can only move for their
+/// own seat. Spectators have no seat, and no selector either.
+///
+/// Players log in before asking for a seat. If the connection drops, this
+/// keeps trying to get back in: players by logging in again and resuming
+/// with the session token the server gave them, spectators by watching
+/// again.
+///
+/// Everyone in the game can chat; what's been said is kept here for the
+/// chat panel.
+///
+/// Connecting happens on a background thread, so the window keeps drawing
+/// while the server is slow to answer or can't be reached.
+///
+/// The connection reads on a background thread through a channel receiver,
+/// which can't be shared between threads, so this is a non-send resource.
+pub struct NetworkGame {
+    addr: String,
+    /// `None` while the connection is lost.
+    connection: Option<Connection>,
+    /// The connection being made, if we're trying.
+    dialing: Option<Receiver<io::Result<Connection>>>,
+    /// The token to log in with, once we have one.
+    account: Option<String>,
+    /// The token to get our seat back with.
+    token: Option<String>,
+    retry_at: Instant,
+    /// The game and seat the server gave us, once it has.
+    pub seat: Option<(GameId, Player)>,
+    /// The game we're following as a spectator, once the server agrees.
+    pub watching: Option<GameId>,
+    /// The last thing that went wrong, shown instead of the status.
+    pub problem: Option<String>,
+    /// Who said what in the game, oldest first.
+    pub chat: Vec<(String, String)>,
+}
+
+impl NetworkGame {
+    /// Starts connecting, to log in with `login` if given and send
+    /// `request`, which should ask for a seat or to watch a game. If the
+    /// server can't be reached, that's the problem shown.
+    pub fn connect(addr: &str, login: Option<&ClientMessage>, request: ClientMessage) -> Self {
+        let account = match login {
+            Some(ClientMessage::Login { token }) => Some(token.clone()),
+            _ => None,
+        };
+        Self {
+            addr: addr.to_string(),
+            connection: None,
+            dialing: Some(dial(addr.to_string(), login.cloned(), request)),
+            account,
+            token: None,
+            retry_at: Instant::now(),
+            seat: None,
+            watching: None,
+            problem: None,
+            chat: vec![],
+        }
+    }
+
+    /// Asks the server to play `loc`, if it's our turn in `game`.
+    pub fn play(&mut self, game: &MyGame, loc: Location) {
+        if !self
+            .seat
+            .is_some_and(|(_, me)| game.status == GamePlayStatus::Playing(me))
+        {
+            return;
+        }
+        let Some(connection) = &mut self.connection else {
+            return;
+        };
+        let message = ClientMessage::Move {
+            at: loc,
+            seq: Some(game.turn()),
+        };
+        if let Err(e) = connection.send(&message) {
+            self.problem = Some(e.to_string());
+        }
+    }
+
+    /// Says `text` to everyone in the game.
+    pub fn say(&mut self, text: &str) {
+        self.act(&ClientMessage::Chat {
+            text: text.to_string(),
+        });
+    }
+
+    /// Sends `message` for our seat, such as resigning or answering an
+    /// offer. Spectators have nothing to say but chat.
+    pub fn act(&mut self, message: &ClientMessage) {
+        if self.seat.is_none() && !matches!(message, ClientMessage::Chat { .. }) {
+            return;
+        }
+        let Some(connection) = &mut self.connection else {
+            return;
+        };
+        if let Err(e) = connection.send(message) {
+            self.problem = Some(e.to_string());
+        }
+    }
+
+    /// Gives up our seat, if the game's still going, and asks for the next
+    /// quick match.
+    pub fn queue(&mut self) {
+        self.seat = None;
+        self.token = None;
+        self.watching = None;
+        self.chat.clear();
+        let Some(connection) = &mut self.connection else {
+            return;
+        };
+        if let Err(e) = connection.send(&ClientMessage::QuickMatch { computer: false }) {
+            self.problem = Some(e.to_string());
+        }
+    }
+
+    /// How to log in to this server next time, once it's given us a token.
+    pub fn login(&self) -> Option<ClientMessage> {
+        self.account
+            .clone()
+            .map(|token| ClientMessage::Login { token })
+    }
+
+    /// Waits for the connection being made, or tries to get back into the
+    /// game after losing the connection, at most once every `RETRY`.
+    fn reconnect(&mut self) {
+        if let Some(dialing) = &self.dialing {
+            match dialing.try_recv() {
+                Ok(Ok(connection)) => self.connection = Some(connection),
+                Ok(Err(e)) => self.problem = Some(format!("couldn't reach {}: {}", self.addr, e)),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => (),
+            }
+            self.dialing = None;
+            return;
+        }
+        if Instant::now() < self.retry_at {
+            return;
+        }
+        self.retry_at = Instant::now() + RETRY;
+        let request = match (&self.token, self.watching) {
+            (Some(token), _) => ClientMessage::Resume {
+                token: token.clone(),
+            },
+            (None, Some(game)) => ClientMessage::Watch { game },
+            (None, None) => return,
+        };
+        self.dialing = Some(dial(self.addr.clone(), self.login(), request));
+    }
+}
+
+/// Connects to `addr` on a background thread, logging in with `login` if
+/// given and sending `request`, and hands over the connection when it's up.
+fn dial(
+    addr: String,
+    login: Option<ClientMessage>,
+    request: ClientMessage,
+) -> Receiver<io::Result<Connection>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let connected = Connection::connect(addr.as_str()).and_then(|mut connection| {
+            if let Some(login) = &login {
+                connection.send(login)?;
+            }
+            connection.send(&request)?;
+            Ok(connection)
+        });
+        sender.send(connected).ok();
+    });
+    receiver
+}
+
+/// The one move that turns `before` into `after`, if that's what happened.
+fn move_between(before: &TTTTState, after: &TTTTState) -> Option<Location> {
+    if after.turn() != before.turn() + 1 {
+        return None;
+    }
+    (0..4)
+        .flat_map(|z| (0..4).flat_map(move |y| (0..4).map(move |x| Location::new(x, y, z))))
+        .find(|loc| before.board.at(*loc).is_none() && after.board.at(*loc).is_some())
+}
+
+pub fn receive_updates(
+    network: Option<NonSendMut<NetworkGame>>,
+    mut game: ResMut<MyGame>,
+    mut commands: Commands,
+    selectors: Query<Entity, With<Selector>>,
+) {
+    let Some(mut network) = network else { return };
+    let network = &mut *network;
+    loop {
+        let Some(connection) = &network.connection else {
+            return network.reconnect();
+        };
+        match connection.try_recv() {
+            // Leaving the game keeps the token in the settings, to log in
+            // with next time.
+            Ok(Some(ServerMessage::Registered { token, .. })) => network.account = Some(token),
+            Ok(Some(ServerMessage::LoggedIn { .. })) => (),
+            Ok(Some(ServerMessage::Welcome {
+                game,
+                player,
+                token,
+            })) => {
+                network.seat = Some((game, player));
+                network.token = Some(token);
+                // The server replays the chat so far after this.
+                network.chat.clear();
+            }
+            Ok(Some(ServerMessage::Watching { game })) => {
+                network.watching = Some(game);
+                network.chat.clear();
+                for selector in &selectors {
+                    commands.entity(selector).despawn_recursive();
+                }
+            }
+            Ok(Some(ServerMessage::History { moves })) => game.moves = moves,
+            Ok(Some(ServerMessage::State(state))) => {
+                network.problem = None;
+                if let Some(loc) = move_between(&game.game, &state) {
+                    game.moves.push(loc);
+                }
+                // The clock, if there is one, follows in its own message.
+                let clock = game.clock;
+                game.game = TTTTState { clock, ..state };
+                // Any move, or settling the offer, leaves nothing to answer.
+                game.offer = None;
+            }
+            Ok(Some(ServerMessage::Offered { offer, by })) => game.offer = Some((by, offer)),
+            Ok(Some(ServerMessage::Declined { offer, by })) => {
+                game.offer = None;
+                network.problem = Some(format!("{} declined the {}", by, offer.word()));
+            }
+            Ok(Some(ServerMessage::Clock(clock))) => {
+                game.clock = Some(clock);
+                game.turn_started = Instant::now();
+            }
+            Ok(Some(ServerMessage::Error { message })) => network.problem = Some(message),
+            // The game over screen follows from the state.
+            Ok(Some(ServerMessage::GameOver { .. })) => (),
+            Ok(Some(ServerMessage::Chat { from, text })) => network.chat.push((from, text)),
+            Ok(Some(ServerMessage::Games { .. })) => (),
+            Ok(None) => break,
+            Err(e) => {
+                network.problem = Some(e.to_string());
+                network.connection = None;
+            }
+        }
+    }
+}
+
+/// What to show under the status when playing over the network.
+pub fn describe(network: &NetworkGame) -> String {
+    match (&network.problem, network.seat, network.watching) {
+        (Some(problem), _, _) => problem.clone(),
+        (None, Some((game, me)), _) => format!("Game {}: you are {}", game, me),
+        (None, None, Some(game)) => format!("Watching game {}", game),
+        (None, None, None) => "Waiting for the server".to_string(),
+    }
+}
@@ -0,0 +1,75 @@
+use super::{chat::ChatBox, computer::ComputerPlayer, MyGame, NetworkGame};
+use bevy::prelude::*;
+use tic_tac_toc_toe::{
+    logic::{GamePlayStatus, Offer, Player},
+    net::protocol::ClientMessage,
+};
+
+/// `R` resigns, `E` offers a draw and `Z` asks to take back a move; `Y` and
+/// `N` answer the other player's offer. Over the network these go to the
+/// server for our seat. On one computer the player to move resigns or
+/// offers a draw, while a takeback is asked for by whoever just moved, and
+/// the other player answers at the same keyboard. Against the computer we
+/// always speak for our own side, and the computer answers.
+pub fn make_offers(
+    input: Res<Input<KeyCode>>,
+    mut game: ResMut<MyGame>,
+    network: Option<NonSendMut<NetworkGame>>,
+    computer: Option<NonSend<ComputerPlayer>>,
+    chat: Res<ChatBox>,
+) {
+    if chat.is_typing() {
+        return;
+    }
+    if let Some(mut network) = network {
+        let message = if input.just_pressed(KeyCode::R) {
+            ClientMessage::Resign
+        } else if input.just_pressed(KeyCode::E) {
+            ClientMessage::Offer { offer: Offer::Draw }
+        } else if input.just_pressed(KeyCode::Z) {
+            ClientMessage::Offer {
+                offer: Offer::Takeback,
+            }
+        } else if input.just_pressed(KeyCode::Y) {
+            ClientMessage::Accept
+        } else if input.just_pressed(KeyCode::N) {
+            ClientMessage::Decline
+        } else {
+            return;
+        };
+        // The server answers, and the game changes when it says so.
+        return network.act(&message);
+    }
+    let GamePlayStatus::Playing(to_move) = game.status else {
+        return;
+    };
+    let me = computer.map(|computer| computer.side.other_player());
+    if input.just_pressed(KeyCode::R) {
+        game.resign(me.unwrap_or(to_move)).ok();
+    } else if input.just_pressed(KeyCode::E) && game.offer.is_none() {
+        game.offer = Some((me.unwrap_or(to_move), Offer::Draw));
+    } else if input.just_pressed(KeyCode::Z) && game.offer.is_none() && !game.moves.is_empty() {
+        game.offer = Some((me.unwrap_or(to_move.other_player()), Offer::Takeback));
+    } else if input.just_pressed(KeyCode::Y) && me.is_none() {
+        game.accept_offer();
+    } else if input.just_pressed(KeyCode::N) && me.is_none() {
+        game.offer = None;
+    }
+}
+
+/// What to tell the players about the offer waiting for an answer, if
+/// there is one. `me` is our seat in a network game.
+pub fn prompt(game: &MyGame, me: Option<Player>) -> Option<String> {
+    let (by, offer) = game.offer?;
+    if me == Some(by) {
+        return Some(format!(
+            "Waiting for an answer to your {} offer",
+            offer.word()
+        ));
+    }
+    let asks = match offer {
+        Offer::Draw => "offers a draw",
+        Offer::Takeback => "asks to take back a move",
+    };
+    Some(format!("{} {}: Y accepts, N declines", by, asks))
+}
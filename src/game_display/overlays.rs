@@ -0,0 +1,129 @@
+use super::{position, MyGame};
+use bevy::prelude::*;
+use tic_tac_toc_toe::{
+    common::Location,
+    logic::{GamePlayStatus, Player, WinReason},
+};
+
+/// How far a rail reaches past the spots at either end of its line.
+const OVERHANG: f32 = 0.4;
+
+/// Anything drawn over the board to help read it: the last move's halo,
+/// the rails along lines about to be won, and the glow along the line that
+/// won.
+#[derive(Component)]
+pub struct Overlay;
+
+#[derive(Resource)]
+pub struct OverlayAssets {
+    halo: Handle<Mesh>,
+    /// A rail one unit long along `y`, stretched to fit each line.
+    rail: Handle<Mesh>,
+    last_move: Handle<StandardMaterial>,
+    threat_a: Handle<StandardMaterial>,
+    threat_b: Handle<StandardMaterial>,
+    win: Handle<StandardMaterial>,
+}
+
+impl FromWorld for OverlayAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let halo = meshes.add(Mesh::from(shape::Icosphere {
+            radius: 0.45,
+            ..default()
+        }));
+        let rail = meshes.add(Mesh::from(shape::Box::new(0.08, 1.0, 0.08)));
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        Self {
+            halo,
+            rail,
+            last_move: materials.add(Color::rgba(1.0, 0.8, 0.0, 0.3).into()),
+            threat_a: materials.add(Color::rgba(1.0, 0.3, 0.3, 0.4).into()),
+            threat_b: materials.add(Color::rgba(0.3, 0.3, 1.0, 0.4).into()),
+            win: materials.add(StandardMaterial {
+                base_color: Color::rgb(1.0, 0.85, 0.2),
+                emissive: Color::rgb(1.0, 0.7, 0.0),
+                unlit: true,
+                ..default()
+            }),
+        }
+    }
+}
+
+impl OverlayAssets {
+    fn threat(&self, player: Player) -> Handle<StandardMaterial> {
+        match player {
+            Player::A => self.threat_a.clone(),
+            Player::B => self.threat_b.clone(),
+        }
+    }
+}
+
+/// Where a rail along `line` goes: through its middle, turned to point
+/// along it, and stretched to its length.
+fn rail_along(line: [Location; 4]) -> Transform {
+    let (start, end) = (position(line[0]), position(line[3]));
+    let along = end - start;
+    Transform {
+        translation: (start + end) / 2.0,
+        rotation: Quat::from_rotation_arc(Vec3::Y, along.normalize()),
+        scale: Vec3::new(1.0, along.length() + 2.0 * OVERHANG, 1.0),
+    }
+}
+
+/// Redraws the overlays when the game changes. While the game's on, every
+/// line a player could finish next move gets a rail in a color of its own
+/// for each player; once someone fills a line, it glows instead.
+pub fn update_overlays(
+    mut commands: Commands,
+    game: Res<MyGame>,
+    assets: Res<OverlayAssets>,
+    overlays: Query<Entity, With<Overlay>>,
+) {
+    if !game.is_changed() {
+        return;
+    }
+    for overlay in &overlays {
+        commands.entity(overlay).despawn();
+    }
+    if let Some(&last) = game.moves.last() {
+        commands.spawn((
+            PbrBundle {
+                mesh: assets.halo.clone(),
+                material: assets.last_move.clone(),
+                transform: Transform::from_translation(position(last)),
+                ..default()
+            },
+            Overlay,
+        ));
+    }
+    let rails: Vec<([Location; 4], Handle<StandardMaterial>)> = match game.status {
+        GamePlayStatus::Playing(_) => game
+            .board
+            .threats()
+            .into_iter()
+            .map(|threat| (threat.line, assets.threat(threat.player)))
+            .collect(),
+        GamePlayStatus::Win {
+            reason: WinReason::Line,
+            ..
+        } => game
+            .board
+            .winning_line()
+            .map(|line| (line, assets.win.clone()))
+            .into_iter()
+            .collect(),
+        _ => vec![],
+    };
+    for (line, material) in rails {
+        commands.spawn((
+            PbrBundle {
+                mesh: assets.rail.clone(),
+                material,
+                transform: rail_along(line),
+                ..default()
+            },
+            Overlay,
+        ));
+    }
+}
@@ -0,0 +1,195 @@
+use super::{
+    computer::ComputerPlayer, position, BoardAssets, ClickableNode, MyGame, NetworkGame, Selector,
+    Settings,
+};
+use bevy::prelude::*;
+use bevy_mod_picking::{Hover, PickingEvent};
+use std::time::{Duration, Instant};
+use tic_tac_toc_toe::{
+    common::Location,
+    logic::{GamePlayStatus, Player},
+};
+
+/// The longest gap between the two clicks of a double click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// How a click on the board places a piece.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaceBy {
+    Click,
+    /// The first click only moves the selector there.
+    DoubleClick,
+}
+
+/// A piece to place for whoever's playing on this computer, by `Return` or
+/// a click.
+pub struct Place(pub Location);
+
+/// The player who can place a piece from this computer right now, if
+/// anyone: over the network only in our own seat, and against the computer
+/// only on our own turn.
+fn mover(
+    game: &MyGame,
+    network: Option<&NetworkGame>,
+    computer: Option<&ComputerPlayer>,
+) -> Option<Player> {
+    let GamePlayStatus::Playing(to_move) = game.status else {
+        return None;
+    };
+    match (network, computer) {
+        (Some(network), _) => network.seat.map(|(_, me)| me).filter(|me| *me == to_move),
+        (None, Some(computer)) if computer.side == to_move => None,
+        _ => Some(to_move),
+    }
+}
+
+/// Plays the pieces asked for. Over the network the server decides, and the
+/// board changes when it says so.
+pub fn place_pieces(
+    mut places: EventReader<Place>,
+    mut game: ResMut<MyGame>,
+    mut network: Option<NonSendMut<NetworkGame>>,
+    computer: Option<NonSend<ComputerPlayer>>,
+) {
+    for Place(loc) in places.iter() {
+        if mover(&game, network.as_deref(), computer.as_deref()).is_none() {
+            continue;
+        }
+        match &mut network {
+            Some(network) => network.play(&game, *loc),
+            None => {
+                game.play(*loc).ok();
+            }
+        }
+    }
+}
+
+/// Clicking a spot moves the selector there, and places a piece with the
+/// click or the double click `Settings::place_by` asks for.
+pub fn click_to_place(
+    mut events: EventReader<PickingEvent>,
+    nodes: Query<&ClickableNode>,
+    mut selectors: Query<&mut Selector>,
+    settings: Res<Settings>,
+    mut last_click: Local<Option<(Entity, Instant)>>,
+    mut places: EventWriter<Place>,
+) {
+    for event in events.iter() {
+        let PickingEvent::Clicked(entity) = event else {
+            continue;
+        };
+        let Ok(ClickableNode(loc)) = nodes.get(*entity) else {
+            continue;
+        };
+        for mut selector in &mut selectors {
+            selector.x = loc.x;
+            selector.y = loc.y;
+            selector.z = loc.z;
+        }
+        let double =
+            last_click.is_some_and(|(last, at)| last == *entity && at.elapsed() <= DOUBLE_CLICK);
+        if settings.place_by == PlaceBy::Click || double {
+            places.send(Place(*loc));
+            *last_click = None;
+        } else {
+            *last_click = Some((*entity, Instant::now()));
+        }
+    }
+}
+
+/// The piece shown where the mouse is, before it's placed.
+#[derive(Component)]
+pub struct Ghost;
+
+#[derive(Resource)]
+pub struct GhostMaterials {
+    a: Handle<StandardMaterial>,
+    b: Handle<StandardMaterial>,
+    not_allowed: Handle<StandardMaterial>,
+}
+
+impl FromWorld for GhostMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        Self {
+            a: materials.add(Color::rgba(1.0, 1.0, 1.0, 0.3).into()),
+            b: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.3).into()),
+            not_allowed: materials.add(Color::rgba(1.0, 0.0, 0.0, 0.4).into()),
+        }
+    }
+}
+
+pub fn spawn_ghost(mut commands: Commands, assets: Res<BoardAssets>, ghosts: Res<GhostMaterials>) {
+    commands.spawn((
+        PbrBundle {
+            mesh: assets.piece.clone(),
+            material: ghosts.a.clone(),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        },
+        Ghost,
+    ));
+}
+
+/// Shows a see-through piece in the mover's color on the spot under the
+/// mouse, or, on a spot that's taken, a red one and the "not allowed"
+/// cursor.
+pub fn preview_piece(
+    nodes: Query<(&ClickableNode, &Hover)>,
+    game: Res<MyGame>,
+    network: Option<NonSend<NetworkGame>>,
+    computer: Option<NonSend<ComputerPlayer>>,
+    materials: Res<GhostMaterials>,
+    mut ghosts: Query<
+        (
+            &mut Transform,
+            &mut Visibility,
+            &mut Handle<StandardMaterial>,
+        ),
+        With<Ghost>,
+    >,
+    mut windows: ResMut<Windows>,
+) {
+    let hovered = nodes
+        .iter()
+        .find(|(_, hover)| hover.hovered())
+        .map(|(ClickableNode(loc), _)| *loc);
+    let mover = mover(&game, network.as_deref(), computer.as_deref());
+    let (shown, cursor) = match (hovered, mover) {
+        (Some(loc), Some(_)) if game.board.at(loc).is_some() => (
+            Some((loc, materials.not_allowed.clone())),
+            CursorIcon::NotAllowed,
+        ),
+        (Some(loc), Some(Player::A)) => (Some((loc, materials.a.clone())), CursorIcon::Hand),
+        (Some(loc), Some(Player::B)) => (Some((loc, materials.b.clone())), CursorIcon::Hand),
+        _ => (None, CursorIcon::Default),
+    };
+    for (mut transform, mut visibility, mut material) in &mut ghosts {
+        visibility.is_visible = shown.is_some();
+        if let Some((loc, shade)) = &shown {
+            transform.translation = position(*loc);
+            *material = shade.clone();
+        }
+    }
+    set_cursor(&mut windows, cursor);
+}
+
+/// Takes the preview away while the game isn't being played.
+pub fn hide_ghost(mut ghosts: Query<&mut Visibility, With<Ghost>>, mut windows: ResMut<Windows>) {
+    for mut visibility in &mut ghosts {
+        visibility.is_visible = false;
+    }
+    set_cursor(&mut windows, CursorIcon::Default);
+}
+
+/// Only reaches into the window when the cursor needs to change.
+fn set_cursor(windows: &mut ResMut<Windows>, icon: CursorIcon) {
+    if windows
+        .get_primary()
+        .is_some_and(|window| window.cursor_icon() != icon)
+    {
+        if let Some(window) = windows.get_primary_mut() {
+            window.set_cursor_icon(icon);
+        }
+    }
+}
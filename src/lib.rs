@@ -0,0 +1,5 @@
+pub mod common;
+pub mod engine;
+pub mod logic;
+pub mod net;
+pub mod text_display;
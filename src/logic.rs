@@ -1,16 +1,21 @@
-use std::fmt::Display;
+use std::{fmt::Display, time::Duration};
 
-use crate::common::Location;
+use serde::{Deserialize, Serialize};
 
-use self::calculated::LINES;
+use crate::common::{Location, SIZE};
 
-pub mod computer_player;
+use self::{calculated::LINES, clock::Clock};
 
-const SIZE: usize = 4;
+pub mod clock;
+pub mod computer_player;
+pub mod network;
+pub mod notation;
+pub mod search;
+pub mod tournament;
 
 mod calculated;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum Player {
     A,
     B,
@@ -36,7 +41,7 @@ impl Display for Player {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Board {
     pub spots: [[[Option<Player>; SIZE]; SIZE]; SIZE],
 }
@@ -49,7 +54,9 @@ impl Board {
     }
 
     pub fn place(&mut self, player: Player, loc: Location) -> Result<PlaceResult, PlaceErr> {
-        if self.spots[loc.z][loc.y][loc.x].is_some() {
+        if !loc.is_on_board() {
+            Err(PlaceErr::OffBoard)
+        } else if self.spots[loc.z][loc.y][loc.x].is_some() {
             Err(PlaceErr::Occupied)
         } else {
             self.spots[loc.z][loc.y][loc.x] = Some(player);
@@ -72,6 +79,46 @@ impl Board {
     pub fn at(&self, loc: Location) -> Option<Player> {
         self.spots[loc.z][loc.y][loc.x]
     }
+
+    /// The line someone has filled, if there is one.
+    pub fn winning_line(&self) -> Option<[Location; 4]> {
+        LINES.iter().copied().find(|line| {
+            let first = self.at(line[0]);
+            first.is_some() && line.iter().all(|loc| self.at(*loc) == first)
+        })
+    }
+
+    /// Every line one player could finish with their next stone.
+    pub fn threats(&self) -> Vec<Threat> {
+        LINES
+            .iter()
+            .filter_map(|line| {
+                let gaps: Vec<Location> = line
+                    .iter()
+                    .copied()
+                    .filter(|loc| self.at(*loc).is_none())
+                    .collect();
+                let [gap] = gaps[..] else {
+                    return None;
+                };
+                let stones: Vec<Player> = line.iter().filter_map(|loc| self.at(*loc)).collect();
+                let player = stones[0];
+                stones.iter().all(|p| *p == player).then_some(Threat {
+                    player,
+                    line: *line,
+                    gap,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A line with three of `player`'s stones, and `gap` left to fill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Threat {
+    pub player: Player,
+    pub line: [Location; 4],
+    pub gap: Location,
 }
 
 impl Default for Board {
@@ -86,38 +133,283 @@ pub enum PlaceResult {
     GameOver,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PlaceErr {
     Occupied,
+    OffBoard,
+    GameOver,
+    /// The player's time ran out before they moved.
+    OutOfTime,
+    /// A takeback was asked for by a player who hasn't moved yet.
+    NothingToTakeBack,
+}
+
+impl Display for PlaceErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlaceErr::Occupied => write!(f, "that spot is taken"),
+            PlaceErr::OffBoard => write!(f, "that spot isn't on the board"),
+            PlaceErr::GameOver => write!(f, "the game is over"),
+            PlaceErr::OutOfTime => write!(f, "you ran out of time"),
+            PlaceErr::NothingToTakeBack => write!(f, "there's no move of yours to take back"),
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+impl std::error::Error for PlaceErr {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TTTTState {
     pub board: Board,
     pub status: GamePlayStatus,
     pub players: Vec<Player>,
+    /// Each player's time, in a timed game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<Clock>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum GamePlayStatus {
     Playing(Player),
+    Draw { reason: DrawReason },
+    Win { player: Player, reason: WinReason },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WinReason {
+    /// Four in a line.
+    Line,
+    /// The other player gave up.
+    Resignation,
+    /// The other player's time ran out.
+    Timeout,
+    /// The other player left and didn't come back.
+    Forfeit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DrawReason {
+    /// The board filled up without a line.
+    FullBoard,
+    /// One player offered a draw and the other accepted.
+    Agreement,
+}
+
+impl WinReason {
+    /// How the reason is written in positions and messages.
+    pub fn word(self) -> &'static str {
+        match self {
+            WinReason::Line => "line",
+            WinReason::Resignation => "resignation",
+            WinReason::Timeout => "timeout",
+            WinReason::Forfeit => "forfeit",
+        }
+    }
+
+    pub fn from_word(word: &str) -> Option<Self> {
+        [
+            WinReason::Line,
+            WinReason::Resignation,
+            WinReason::Timeout,
+            WinReason::Forfeit,
+        ]
+        .into_iter()
+        .find(|reason| reason.word() == word)
+    }
+}
+
+impl GamePlayStatus {
+    /// A win for `player` by four in a line.
+    pub fn line(player: Player) -> Self {
+        GamePlayStatus::Win {
+            player,
+            reason: WinReason::Line,
+        }
+    }
+
+    pub fn winner(&self) -> Option<Player> {
+        match self {
+            GamePlayStatus::Win { player, .. } => Some(*player),
+            GamePlayStatus::Playing(_) | GamePlayStatus::Draw { .. } => None,
+        }
+    }
+}
+
+impl Display for GamePlayStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GamePlayStatus::Playing(player) => write!(f, "{}", player),
+            GamePlayStatus::Draw {
+                reason: DrawReason::FullBoard,
+            } => write!(f, "Draw"),
+            GamePlayStatus::Draw {
+                reason: DrawReason::Agreement,
+            } => write!(f, "Draw agreed"),
+            GamePlayStatus::Win { player, reason } => {
+                let loser = player.other_player();
+                match reason {
+                    WinReason::Line => write!(f, "{} won", player),
+                    WinReason::Resignation => write!(f, "{} resigned; {} won", loser, player),
+                    WinReason::Timeout => write!(f, "{} ran out of time; {} won", loser, player),
+                    WinReason::Forfeit => write!(f, "{} left; {} won", loser, player),
+                }
+            }
+        }
+    }
+}
+
+/// Something one player can ask of the other, who accepts or declines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Offer {
     Draw,
-    Win(Player),
+    /// Taking back the asker's last move, and the reply to it if there's
+    /// been one, so it's their move again.
+    Takeback,
+}
+
+impl Offer {
+    pub fn word(self) -> &'static str {
+        match self {
+            Offer::Draw => "draw",
+            Offer::Takeback => "takeback",
+        }
+    }
+
+    pub fn from_word(word: &str) -> Option<Self> {
+        [Offer::Draw, Offer::Takeback]
+            .into_iter()
+            .find(|offer| offer.word() == word)
+    }
 }
 
 impl TTTTState {
-    pub fn play(&mut self, loc: Location) {
+    pub fn new() -> Self {
+        Self {
+            board: Board::new(),
+            status: GamePlayStatus::Playing(Player::A),
+            players: vec![Player::A, Player::B],
+            clock: None,
+        }
+    }
+
+    /// A new game where each player has `control` to make their moves in.
+    pub fn with_clock(control: clock::TimeControl) -> Self {
+        Self {
+            clock: Some(Clock::new(control)),
+            ..Self::new()
+        }
+    }
+
+    /// Plays `loc` for whoever's turn it is. Nothing changes if the move
+    /// isn't allowed.
+    pub fn play(&mut self, loc: Location) -> Result<(), PlaceErr> {
         match self.status {
-            GamePlayStatus::Playing(player) => match self.board.place(player, loc) {
-                Ok(PlaceResult::Continue) => {
-                    self.status = GamePlayStatus::Playing(player.other_player());
+            GamePlayStatus::Playing(player) => {
+                match self.board.place(player, loc)? {
+                    PlaceResult::Continue if self.turn() == SIZE * SIZE * SIZE => {
+                        self.status = GamePlayStatus::Draw {
+                            reason: DrawReason::FullBoard,
+                        };
+                    }
+                    PlaceResult::Continue => {
+                        self.status = GamePlayStatus::Playing(player.other_player());
+                    }
+                    PlaceResult::GameOver => self.status = GamePlayStatus::line(player),
                 }
-                Ok(PlaceResult::GameOver) => self.status = GamePlayStatus::Win(player),
-                Err(PlaceErr::Occupied) => (),
-            },
-            GamePlayStatus::Draw => (),
-            GamePlayStatus::Win(_) => (),
+                Ok(())
+            }
+            GamePlayStatus::Draw { .. } | GamePlayStatus::Win { .. } => Err(PlaceErr::GameOver),
+        }
+    }
+
+    /// Like `play`, for a move that took `thought`, charged to the mover's
+    /// clock in a timed game. A move that comes too late isn't played: the
+    /// mover loses on time instead.
+    pub fn play_timed(&mut self, loc: Location, thought: Duration) -> Result<(), PlaceErr> {
+        let GamePlayStatus::Playing(player) = self.status else {
+            return Err(PlaceErr::GameOver);
+        };
+        if self.flag(thought) {
+            return Err(PlaceErr::OutOfTime);
+        }
+        self.play(loc)?;
+        if let Some(clock) = &mut self.clock {
+            clock.charge(player, thought);
+        }
+        Ok(())
+    }
+
+    /// Ends the game if the player to move has run out of time, `thought`
+    /// into their move, and says whether they had.
+    pub fn flag(&mut self, thought: Duration) -> bool {
+        let GamePlayStatus::Playing(player) = self.status else {
+            return false;
+        };
+        let fallen = self
+            .clock
+            .is_some_and(|clock| clock.has_run_out(player, thought));
+        if fallen {
+            self.status = GamePlayStatus::Win {
+                player: player.other_player(),
+                reason: WinReason::Timeout,
+            };
+        }
+        fallen
+    }
+
+    /// `player` gives up, whoever's turn it is.
+    pub fn resign(&mut self, player: Player) -> Result<(), PlaceErr> {
+        if !matches!(self.status, GamePlayStatus::Playing(_)) {
+            return Err(PlaceErr::GameOver);
+        }
+        self.status = GamePlayStatus::Win {
+            player: player.other_player(),
+            reason: WinReason::Resignation,
+        };
+        Ok(())
+    }
+
+    /// Ends the game as a win for whoever's left, as `player` went away and
+    /// didn't come back.
+    pub fn forfeit(&mut self, player: Player) -> Result<(), PlaceErr> {
+        if !matches!(self.status, GamePlayStatus::Playing(_)) {
+            return Err(PlaceErr::GameOver);
+        }
+        self.status = GamePlayStatus::Win {
+            player: player.other_player(),
+            reason: WinReason::Forfeit,
+        };
+        Ok(())
+    }
+
+    /// Ends the game in a draw both players agreed to.
+    pub fn agree_draw(&mut self) -> Result<(), PlaceErr> {
+        if !matches!(self.status, GamePlayStatus::Playing(_)) {
+            return Err(PlaceErr::GameOver);
         }
+        self.status = GamePlayStatus::Draw {
+            reason: DrawReason::Agreement,
+        };
+        Ok(())
+    }
+
+    /// Takes back `player`'s last move from the game and from `moves`, the
+    /// moves played so far, along with the reply to it if there's been one,
+    /// so it's `player`'s move again. The clock isn't wound back.
+    pub fn take_back(&mut self, player: Player, moves: &mut Vec<Location>) -> Result<(), PlaceErr> {
+        let GamePlayStatus::Playing(to_move) = self.status else {
+            return Err(PlaceErr::GameOver);
+        };
+        let plies = if to_move == player { 2 } else { 1 };
+        if moves.len() < plies {
+            return Err(PlaceErr::NothingToTakeBack);
+        }
+        for loc in moves.drain(moves.len() - plies..) {
+            self.board.spots[loc.z][loc.y][loc.x] = None;
+        }
+        self.status = GamePlayStatus::Playing(player);
+        Ok(())
     }
 
     pub fn turn(&self) -> usize {
@@ -129,6 +421,22 @@ impl TTTTState {
     }
 }
 
+impl Default for TTTTState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The game after `moves` from the start, for tests to set up positions.
+#[cfg(test)]
+pub(crate) fn state_after(moves: &[(usize, usize, usize)]) -> TTTTState {
+    let mut state = TTTTState::new();
+    for m in moves {
+        state.play((*m).into()).unwrap();
+    }
+    state
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -167,6 +475,24 @@ mod tests {
         assert_eq!(result, Ok(PlaceResult::GameOver));
     }
 
+    #[test]
+    fn three_in_a_line_with_the_fourth_empty_is_a_threat() {
+        let mut board = Board::new();
+        board.place(Player::A, Location::new(0, 0, 0)).unwrap();
+        board.place(Player::A, Location::new(1, 1, 1)).unwrap();
+        board.place(Player::A, Location::new(3, 3, 3)).unwrap();
+        board.place(Player::A, Location::new(1, 0, 0)).unwrap();
+        board.place(Player::B, Location::new(2, 0, 0)).unwrap();
+        let threats = board.threats();
+        assert_eq!(threats.len(), 1);
+        assert_eq!(threats[0].player, Player::A);
+        assert_eq!(threats[0].gap, Location::new(2, 2, 2));
+        assert_eq!(board.winning_line(), None);
+
+        board.place(Player::A, Location::new(2, 2, 2)).unwrap();
+        assert_eq!(board.winning_line(), Some(threats[0].line));
+    }
+
     #[test]
     fn the_game_continues_when_4_are_not_placed_in_a_line() {
         let mut board = Board::new();
@@ -186,4 +512,123 @@ mod tests {
         let result = board.place(Player::B, Location::new(3, 0, 0));
         assert_eq!(result, Ok(PlaceResult::Continue));
     }
+
+    #[test]
+    fn filling_the_board_without_a_line_is_a_draw() {
+        let mut state = TTTTState {
+            board: Board::new(),
+            status: GamePlayStatus::Playing(Player::A),
+            players: vec![Player::A, Player::B],
+            clock: None,
+        };
+        // One layer per entry, rows from y = 0 upwards; no line is single-colored.
+        let layers = [
+            ["BABB", "ABAA", "AABB", "BABA"],
+            ["BBAA", "BABB", "AAAB", "ABBB"],
+            ["AABA", "BBAB", "BBBA", "AAAB"],
+            ["BBBA", "AAAB", "ABAA", "ABAB"],
+        ];
+        let (mut a, mut b): (Vec<Location>, Vec<Location>) = (vec![], vec![]);
+        for (z, layer) in layers.iter().enumerate() {
+            for (y, row) in layer.iter().enumerate() {
+                for (x, c) in row.chars().enumerate() {
+                    match c {
+                        'A' => a.push(Location::new(x, y, z)),
+                        _ => b.push(Location::new(x, y, z)),
+                    }
+                }
+            }
+        }
+        for (a, b) in a.into_iter().zip(b) {
+            state.play(a).unwrap();
+            state.play(b).unwrap();
+        }
+        assert_eq!(
+            state.status,
+            GamePlayStatus::Draw {
+                reason: DrawReason::FullBoard
+            }
+        );
+    }
+
+    #[test]
+    fn illegal_moves_are_refused_without_changing_the_game() {
+        let mut state = TTTTState::new();
+        state.play(Location::new(0, 0, 0)).unwrap();
+        let before = state.clone();
+        assert_eq!(state.play(Location::new(0, 0, 0)), Err(PlaceErr::Occupied));
+        assert_eq!(state, before);
+
+        for x in 1..4 {
+            state.play(Location::new(x, 1, 0)).unwrap();
+            state.play(Location::new(x, 0, 0)).unwrap();
+        }
+        assert_eq!(state.status, GamePlayStatus::line(Player::A));
+        assert_eq!(state.play(Location::new(3, 3, 3)), Err(PlaceErr::GameOver));
+    }
+
+    #[test]
+    fn timed_moves_are_charged_and_late_ones_lose() {
+        let control = "10+1".parse().unwrap();
+        let mut state = TTTTState::with_clock(control);
+        let secs = Duration::from_secs;
+        state.play_timed(Location::new(0, 0, 0), secs(3)).unwrap();
+        assert_eq!(state.clock.unwrap().left(Player::A), secs(8));
+        assert!(!state.flag(secs(9)));
+        assert_eq!(
+            state.play_timed(Location::new(1, 0, 0), secs(10)),
+            Err(PlaceErr::OutOfTime)
+        );
+        assert_eq!(
+            state.status,
+            GamePlayStatus::Win {
+                player: Player::A,
+                reason: WinReason::Timeout
+            }
+        );
+        assert_eq!(state.status.winner(), Some(Player::A));
+        assert_eq!(state.turn(), 1);
+
+        let mut untimed = TTTTState::new();
+        untimed
+            .play_timed(Location::new(0, 0, 0), secs(999))
+            .unwrap();
+        assert!(!untimed.flag(secs(999)));
+    }
+
+    #[test]
+    fn players_can_resign_agree_a_draw_or_take_back_moves() {
+        let mut resigned = TTTTState::new();
+        resigned.resign(Player::A).unwrap();
+        assert_eq!(resigned.status.winner(), Some(Player::B));
+        assert_eq!(
+            resigned.status.to_string(),
+            "Player A resigned; Player B won"
+        );
+        assert_eq!(resigned.agree_draw(), Err(PlaceErr::GameOver));
+
+        let mut state = TTTTState::new();
+        let mut moves = vec![];
+        assert_eq!(
+            state.take_back(Player::A, &mut moves),
+            Err(PlaceErr::NothingToTakeBack)
+        );
+        for loc in [Location::new(0, 0, 0), Location::new(1, 0, 0)] {
+            state.play(loc).unwrap();
+            moves.push(loc);
+        }
+        // B asks straight after moving: only B's move goes.
+        state.take_back(Player::B, &mut moves).unwrap();
+        assert_eq!((state.turn(), moves.len()), (1, 1));
+        assert_eq!(state.status, GamePlayStatus::Playing(Player::B));
+        // A asks after B's replied: both moves go, and it's A's turn again.
+        state.play(Location::new(2, 0, 0)).unwrap();
+        moves.push(Location::new(2, 0, 0));
+        state.take_back(Player::A, &mut moves).unwrap();
+        assert_eq!(state, TTTTState::new());
+        assert!(moves.is_empty());
+
+        state.agree_draw().unwrap();
+        assert_eq!(state.status.to_string(), "Draw agreed");
+    }
 }
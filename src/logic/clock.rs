@@ -0,0 +1,209 @@
+//! Chess clocks. Both players start with the same base time, which runs
+//! down while it's their move, and may get some back after each move: all
+//! of a Fischer increment, or with a Bronstein delay only as much of the
+//! delay as the move used, so the clock never gains. Sudden death gives
+//! nothing back.
+//!
+//! Time controls are written in seconds: `300` is five minutes sudden death,
+//! `180+2` three minutes with a two second Fischer increment, and `180+2b`
+//! the same with a Bronstein delay instead.
+
+use super::Player;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+/// What a player gets back after each move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Bonus {
+    None,
+    Fischer(#[serde(with = "millis")] Duration),
+    Bronstein(#[serde(with = "millis")] Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeControl {
+    #[serde(with = "millis")]
+    pub base: Duration,
+    pub bonus: Bonus,
+}
+
+impl Display for TimeControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.base.as_secs_f64())?;
+        match self.bonus {
+            Bonus::None => Ok(()),
+            Bonus::Fischer(increment) => write!(f, "+{}", increment.as_secs_f64()),
+            Bonus::Bronstein(delay) => write!(f, "+{}b", delay.as_secs_f64()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTimeControlError(pub String);
+
+impl Display for ParseTimeControlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "bad time control {}: use seconds, like 300, 180+2 or 180+2b",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseTimeControlError {}
+
+impl FromStr for TimeControl {
+    type Err = ParseTimeControlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || ParseTimeControlError(s.to_string());
+        let seconds = |word: &str| {
+            word.parse::<f64>()
+                .ok()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .ok_or_else(bad)
+        };
+        let (base, bonus) = match s.trim().split_once('+') {
+            None => (seconds(s.trim())?, Bonus::None),
+            Some((base, delay)) => match delay.strip_suffix('b') {
+                Some(delay) => (seconds(base)?, Bonus::Bronstein(seconds(delay)?)),
+                None => (seconds(base)?, Bonus::Fischer(seconds(delay)?)),
+            },
+        };
+        if base.is_zero() {
+            return Err(bad());
+        }
+        Ok(Self { base, bonus })
+    }
+}
+
+/// The time each player has left, as of the start of the current move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Clock {
+    pub control: TimeControl,
+    #[serde(with = "millis")]
+    pub a: Duration,
+    #[serde(with = "millis")]
+    pub b: Duration,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        Self {
+            control,
+            a: control.base,
+            b: control.base,
+        }
+    }
+
+    pub fn left(&self, player: Player) -> Duration {
+        match player {
+            Player::A => self.a,
+            Player::B => self.b,
+        }
+    }
+
+    fn left_mut(&mut self, player: Player) -> &mut Duration {
+        match player {
+            Player::A => &mut self.a,
+            Player::B => &mut self.b,
+        }
+    }
+
+    /// The clock as it reads `thought` into `player`'s move.
+    pub fn ticked(&self, player: Player, thought: Duration) -> Self {
+        let mut clock = *self;
+        let left = clock.left_mut(player);
+        *left = left.saturating_sub(thought);
+        clock
+    }
+
+    /// Whether `player` has used up their time, `thought` into their move.
+    pub fn has_run_out(&self, player: Player, thought: Duration) -> bool {
+        thought >= self.left(player)
+    }
+
+    /// Takes `thought` off `player`'s time for a move, and gives back their
+    /// bonus.
+    pub fn charge(&mut self, player: Player, thought: Duration) {
+        let back = match self.control.bonus {
+            Bonus::None => Duration::ZERO,
+            Bonus::Fischer(increment) => increment,
+            Bonus::Bronstein(delay) => delay.min(thought),
+        };
+        let left = self.left_mut(player);
+        *left = left.saturating_sub(thought) + back;
+    }
+}
+
+/// Durations as whole milliseconds, which is plenty for a clock and reads
+/// better in JSON than seconds and nanoseconds.
+mod millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn time_controls_round_trip_through_text() {
+        for (text, bonus) in [
+            ("300", Bonus::None),
+            ("180+2", Bonus::Fischer(secs(2))),
+            ("180+2b", Bonus::Bronstein(secs(2))),
+        ] {
+            let control: TimeControl = text.parse().unwrap();
+            assert_eq!(control.bonus, bonus);
+            assert_eq!(control.to_string(), text);
+        }
+        assert_eq!("0.5".parse::<TimeControl>().unwrap().base.as_millis(), 500);
+        for bad in ["", "0", "-5", "3m", "180+", "180+2c", "180+-2"] {
+            assert!(bad.parse::<TimeControl>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn bonuses_are_given_back_after_each_move() {
+        let control = |bonus| TimeControl {
+            base: secs(60),
+            bonus,
+        };
+        let mut sudden_death = Clock::new(control(Bonus::None));
+        sudden_death.charge(Player::A, secs(10));
+        assert_eq!(sudden_death.left(Player::A), secs(50));
+        assert_eq!(sudden_death.left(Player::B), secs(60));
+
+        let mut fischer = Clock::new(control(Bonus::Fischer(secs(5))));
+        fischer.charge(Player::B, secs(1));
+        assert_eq!(fischer.left(Player::B), secs(64));
+
+        let mut bronstein = Clock::new(control(Bonus::Bronstein(secs(5))));
+        bronstein.charge(Player::A, secs(3));
+        assert_eq!(bronstein.left(Player::A), secs(60));
+        bronstein.charge(Player::A, secs(8));
+        assert_eq!(bronstein.left(Player::A), secs(57));
+
+        let ticked = bronstein.ticked(Player::A, secs(7));
+        assert_eq!(
+            (ticked.left(Player::A), ticked.left(Player::B)),
+            (secs(50), secs(60))
+        );
+        assert!(!bronstein.has_run_out(Player::A, secs(56)));
+        assert!(bronstein.has_run_out(Player::A, secs(57)));
+    }
+}
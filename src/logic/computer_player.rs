@@ -1,142 +1,288 @@
+use super::search::{analyse, analyse_until, Evaluator, Limits, Position, SearchInfo};
 use super::*;
 use crate::common::Location;
-use minimax::{self, Strategy};
-
-#[derive(Debug, Clone, Copy)]
-struct TTTT;
-impl minimax::Game for TTTT {
-    type S = TTTTState;
-    type M = TTTTMove;
-
-    fn generate_moves(state: &TTTTState, moves: &mut Vec<TTTTMove>) {
-        match state.status {
-            GamePlayStatus::Playing(player) => {
-                let spots = state.board.spots;
-                for (z, plane) in spots.iter().enumerate() {
-                    for (y, row) in plane.iter().enumerate() {
-                        for (x, spot) in row.iter().enumerate() {
-                            if spot.is_none() {
-                                let m = TTTTMove {
-                                    player,
-                                    loc: Location::new(x, y, z),
-                                };
-                                moves.push(m);
-                            }
-                        }
-                    }
-                }
-            }
-            _ => (),
-        }
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, Receiver, TryRecvError},
+    Arc,
+};
+use std::thread;
+
+/// A flag shared between a running search and whoever started it.
+/// Clones share the same flag, so cancelling any of them stops the search.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    fn get_winner(state: &TTTTState) -> Option<minimax::Winner> {
-        match state.status {
-            GamePlayStatus::Playing(_) => None,
-            GamePlayStatus::Draw => Some(minimax::Winner::Draw),
-            GamePlayStatus::Win(_) => Some(minimax::Winner::PlayerJustMoved),
-        }
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
     }
-}
 
-impl minimax::Move for TTTTMove {
-    type G = TTTT;
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
 
-    fn apply(&self, state: &mut TTTTState) {
-        state.board.place(self.player, self.loc).unwrap();
+/// The hand-written heuristic: every line a player could still complete is
+/// worth one point, doubled for each of their stones already in it.
+pub struct Eval;
+impl Evaluator for Eval {
+    fn evaluate(&self, position: &Position) -> i32 {
+        let me = position.to_move();
+        eval_sub(me, position) - eval_sub(me.other_player(), position)
     }
+}
+
+#[cfg(test)]
+fn next(player: Player, board: &Board, look_ahead: u8) -> Location {
+    let start = TTTTState {
+        board: board.clone(),
+        status: GamePlayStatus::Playing(player),
+        players: vec![Player::A, Player::B],
+        clock: None,
+    };
+    search(&start, look_ahead, &CancelToken::new()).unwrap()
+}
 
-    fn undo(&self, state: &mut TTTTState) {
-        let Location { x, y, z } = self.loc;
-        state.board.spots[z][y][x] = None;
-        state.status = GamePlayStatus::Playing(self.player);
+/// Picks a move for whoever is to play in `state`. Returns `None` if the
+/// game is already over or the search was cancelled before it finished.
+pub fn search(state: &TTTTState, look_ahead: u8, cancel: &CancelToken) -> Option<Location> {
+    analyse(&Eval, state, Limits::depth(look_ahead), cancel).map(|info| info.best)
+}
+
+/// How the computer answers an offer from its opponent: it gladly gives a
+/// move back, but never agrees a draw while there's a game to win.
+pub fn accepts(offer: Offer) -> bool {
+    match offer {
+        Offer::Takeback => true,
+        Offer::Draw => false,
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-struct TTTTMove {
-    player: Player,
-    loc: Location,
+/// An evaluator a search on another thread can use.
+pub type SharedEval = Arc<dyn Evaluator + Send + Sync>;
+
+/// A search running on its own thread. Dropping the handle cancels it.
+pub struct SearchHandle {
+    position: TTTTState,
+    limits: Limits,
+    cancel: CancelToken,
+    stop: CancelToken,
+    result: Receiver<Option<SearchInfo>>,
+    finished: Option<Option<SearchInfo>>,
 }
 
-struct Eval;
-impl minimax::Evaluator for Eval {
-    type G = TTTT;
-
-    fn evaluate(&self, s: &TTTTState) -> minimax::Evaluation {
-        match s.status {
-            GamePlayStatus::Playing(_) => -eval(&s.board) as minimax::Evaluation,
-            GamePlayStatus::Draw => 0 as minimax::Evaluation,
-            GamePlayStatus::Win(player) => {
-                if player == s.players[0] {
-                    minimax::BEST_EVAL
-                } else {
-                    minimax::WORST_EVAL
-                }
+impl SearchHandle {
+    pub fn spawn(state: &TTTTState, look_ahead: u8) -> Self {
+        Self::spawn_with(Arc::new(Eval), state, Limits::depth(look_ahead))
+    }
+
+    /// Searches `state` with `eval`, as far as `limits` allow.
+    pub fn spawn_with(eval: SharedEval, state: &TTTTState, limits: Limits) -> Self {
+        let (cancel, stop) = (CancelToken::new(), CancelToken::new());
+        let (sender, result) = mpsc::channel();
+        let position = state.clone();
+        {
+            let position = position.clone();
+            let (cancel, stop) = (cancel.clone(), stop.clone());
+            thread::spawn(move || {
+                let info = analyse_until(&*eval, &position, limits, &cancel, &stop);
+                // The receiver is gone if the handle was dropped, which is fine.
+                let _ = sender.send(info);
+            });
+        }
+        Self {
+            position,
+            limits,
+            cancel,
+            stop,
+            result,
+            finished: None,
+        }
+    }
+
+    /// The position this search was started from.
+    pub fn position(&self) -> &TTTTState {
+        &self.position
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// A token that ends the search early, keeping the deepest iteration
+    /// that finished, for whoever isn't holding the handle.
+    pub fn stopper(&self) -> CancelToken {
+        self.stop.clone()
+    }
+
+    /// Whether this search is still relevant to the game as it is now.
+    pub fn is_for(&self, current: &TTTTState) -> bool {
+        !self.is_cancelled() && self.position == *current
+    }
+
+    /// Returns the chosen move once the search is done, but only if `current`
+    /// is still the position it searched. A stale result is never returned.
+    pub fn try_take(&mut self, current: &TTTTState) -> Option<Location> {
+        if !self.is_for(current) {
+            return None;
+        }
+        if self.finished.is_none() {
+            match self.result.try_recv() {
+                Ok(info) => self.finished = Some(info),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => self.finished = Some(None),
             }
         }
+        self.finished.as_ref()?.as_ref().map(|info| info.best)
+    }
+
+    /// Blocks until the search is done.
+    pub fn wait(self) -> Option<Location> {
+        self.wait_info().map(|info| info.best)
+    }
+
+    /// Blocks until the search is done, and says how it went.
+    pub fn wait_info(mut self) -> Option<SearchInfo> {
+        let finished = match self.finished.take() {
+            Some(finished) => finished,
+            None => self.result.recv().unwrap_or(None),
+        };
+        finished.filter(|_| !self.is_cancelled())
+    }
+}
+
+impl Drop for SearchHandle {
+    fn drop(&mut self) {
+        self.cancel.cancel();
     }
 }
 
-#[derive(Default)]
-struct Spots {
-    i: usize,
+/// Searches ahead on the opponent's time. After the computer moves, the
+/// opponent's most likely reply is guessed and the position after it is
+/// searched, both in the background. If the guess was right, that work is
+/// reused. Dropping the ponderer cancels it.
+pub struct Ponderer {
+    position: TTTTState,
+    cancel: CancelToken,
+    guess: Receiver<(Location, SearchHandle)>,
+    guessed: Option<(Location, SearchHandle)>,
 }
 
-impl Iterator for Spots {
-    type Item = Location;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let x = self.i % 4;
-        let y = self.i % 16 / 4;
-        let z = self.i / 16;
-        if x < 4 && y < 4 && z < 4 {
-            self.i += 1;
-            Some(Location { x, y, z })
-        } else {
-            None
+impl Ponderer {
+    /// Starts pondering on `state`, where it's the opponent's turn.
+    pub fn start(state: &TTTTState, look_ahead: u8) -> Self {
+        Self::start_with(Arc::new(Eval), state, Limits::depth(look_ahead))
+    }
+
+    /// Starts pondering on `state` with `eval`, searching the guessed
+    /// reply as far as `limits` allow.
+    pub fn start_with(eval: SharedEval, state: &TTTTState, limits: Limits) -> Self {
+        let cancel = CancelToken::new();
+        let (sender, guess) = mpsc::channel();
+        {
+            let (mut after, cancel) = (state.clone(), cancel.clone());
+            thread::spawn(move || {
+                // A shallow guess is plenty: a wrong one costs only the ponder time.
+                let guess = Limits::depth(limits.depth.min(2));
+                let Some(info) = analyse(&*eval, &after, guess, &cancel) else {
+                    return;
+                };
+                if after.play(info.best).is_err()
+                    || !matches!(after.status, GamePlayStatus::Playing(_))
+                {
+                    return;
+                }
+                // Nobody's waiting for it if the ponderer was dropped.
+                let _ = sender.send((info.best, SearchHandle::spawn_with(eval, &after, limits)));
+            });
+        }
+        Self {
+            position: state.clone(),
+            cancel,
+            guess,
+            guessed: None,
         }
     }
-}
 
-fn next(player: Player, board: &Board, look_ahead: u8) -> Location {
-    let start = TTTTState {
-        board: board.clone(),
-        status: GamePlayStatus::Playing(player),
-        players: vec![Player::A, Player::B],
-    };
-    let mut strategy = minimax::Negamax::new(Eval, look_ahead);
-    let k = strategy.choose_move(&start);
+    /// Whether this is pondering on the game as it is now.
+    pub fn is_for(&self, current: &TTTTState) -> bool {
+        self.position == *current
+    }
+
+    /// The reply it's guessing, once it's guessed one.
+    pub fn predicted(&mut self) -> Option<Location> {
+        self.poll();
+        self.guessed.as_ref().map(|(predicted, _)| *predicted)
+    }
+
+    fn poll(&mut self) {
+        if self.guessed.is_none() {
+            self.guessed = self.guess.try_recv().ok();
+        }
+    }
+
+    /// Hands over the search for `current`, the position after the opponent
+    /// actually replied. On a correct guess this is the pondering search,
+    /// otherwise it's cancelled and a fresh search is started.
+    pub fn resolve(self, current: &TTTTState, look_ahead: u8) -> SearchHandle {
+        self.resolve_with(Arc::new(Eval), current, Limits::depth(look_ahead))
+    }
 
-    k.unwrap().loc
+    /// Like `resolve`, for a search with `eval` and `limits`. The pondering
+    /// search is only reused if it was started with the same limits.
+    pub fn resolve_with(
+        mut self,
+        eval: SharedEval,
+        current: &TTTTState,
+        limits: Limits,
+    ) -> SearchHandle {
+        self.poll();
+        match self.guessed.take() {
+            Some((_, search)) if search.is_for(current) && search.limits() == limits => search,
+            _ => SearchHandle::spawn_with(eval, current, limits),
+        }
+    }
 }
 
-fn eval(board: &Board) -> f32 {
-    eval_sub(Player::A, board) - eval_sub(Player::B, board)
+impl Drop for Ponderer {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
 }
 
-fn eval_sub(player: Player, board: &Board) -> f32 {
-    LINES
+#[cfg(test)]
+fn eval(board: &Board) -> i32 {
+    Eval.evaluate(&Position::new(board, Player::A))
+}
+
+fn eval_sub(player: Player, position: &Position) -> i32 {
+    let theirs = position.line_counts(player.other_player());
+    position
+        .line_counts(player)
         .iter()
-        .map(|l| {
-            let mut score = 1.0_f32;
-            for spot in l {
-                if let Some(p) = board.at(*spot) {
-                    if p != player {
-                        return 0.0;
-                    }
-                    score *= 2.0;
-                }
-            }
-            score
-        })
-        .reduce(|a, b| a + b)
-        .unwrap()
+        .zip(theirs)
+        .map(|(&mine, &theirs)| if theirs > 0 { 0 } else { 1 << mine })
+        .sum()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::logic::state_after;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn with_a_one_step_look_ahead_ai_should_win_if_possible() {
@@ -174,4 +320,86 @@ mod tests {
 
         assert!(eval(&good_board) > eval(&bad_board));
     }
+
+    #[test]
+    fn background_search_finds_the_winning_move() {
+        let state = state_after(&[
+            (0, 0, 0),
+            (0, 1, 1),
+            (1, 0, 0),
+            (0, 2, 2),
+            (2, 0, 0),
+            (0, 3, 1),
+        ]);
+        let search = SearchHandle::spawn(&state, 1);
+        assert_eq!(search.wait(), Some(Location::new(3, 0, 0)));
+    }
+
+    #[test]
+    fn a_cancelled_search_stops_early_and_has_no_result() {
+        let state = state_after(&[]);
+        let search = SearchHandle::spawn(&state, 6);
+        search.cancel();
+        let started = Instant::now();
+        assert_eq!(search.wait(), None);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn a_result_for_a_changed_board_is_not_returned() {
+        let state = state_after(&[(0, 0, 0), (1, 1, 1)]);
+        let mut search = SearchHandle::spawn(&state, 1);
+        let mut changed = state.clone();
+        changed.play(Location::new(2, 2, 2)).unwrap();
+
+        while search.try_take(&state).is_none() {
+            thread::yield_now();
+        }
+        assert_eq!(search.try_take(&changed), None);
+    }
+
+    /// Waits for `ponder` to guess the opponent's reply.
+    fn predicted(ponder: &mut Ponderer) -> Location {
+        loop {
+            if let Some(predicted) = ponder.predicted() {
+                return predicted;
+            }
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn pondering_reuses_the_search_when_the_predicted_reply_is_played() {
+        let state = state_after(&[(0, 0, 0), (0, 1, 1), (1, 0, 0), (0, 2, 2), (2, 0, 0)]);
+        let mut ponder = Ponderer::start(&state, 2);
+        assert_eq!(predicted(&mut ponder), Location::new(3, 0, 0));
+
+        let mut after = state.clone();
+        after.play(Location::new(3, 0, 0)).unwrap();
+        let pondering = ponder.guessed.as_ref().unwrap().1.cancel.clone();
+        let search = ponder.resolve(&after, 2);
+        assert_eq!(*search.position(), after);
+        search.cancel();
+        assert!(pondering.is_cancelled());
+    }
+
+    #[test]
+    fn pondering_starts_over_when_a_different_reply_is_played() {
+        let state = state_after(&[(0, 0, 0), (0, 1, 1), (1, 0, 0), (0, 2, 2), (2, 0, 0)]);
+        let mut ponder = Ponderer::start(&state, 2);
+        predicted(&mut ponder);
+
+        let mut after = state.clone();
+        after.play(Location::new(3, 3, 3)).unwrap();
+        let search = ponder.resolve(&after, 1);
+        assert_eq!(*search.position(), after);
+        assert_eq!(search.wait(), Some(Location::new(3, 0, 0)));
+    }
+
+    #[test]
+    fn a_stopped_search_still_has_a_move() {
+        let search = SearchHandle::spawn_with(Arc::new(Eval), &state_after(&[]), Limits::depth(8));
+        search.stopper().cancel();
+        assert!(search.wait_info().is_some());
+    }
 }
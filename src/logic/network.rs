@@ -0,0 +1,257 @@
+//! A small neural network that can stand in for `Eval`.
+//!
+//! The inputs are the 128 occupancy bits of the board, seen from the player
+//! to move: 64 for their stones and 64 for the opponent's. One hidden layer
+//! of rectified units feeds a single `tanh` output, which is scaled into a
+//! search score. It's trained from self-play by the `train` binary.
+
+use super::search::{Evaluator, Position, CELLS};
+use rand::Rng;
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+pub const INPUTS: usize = 2 * CELLS;
+
+/// The score the search sees for an output of 1.0.
+const SCALE: f32 = 1000.0;
+
+const HEADER: &str = "tttt-mlp";
+
+/// The most hidden units a weights file may have: far more than is useful,
+/// so a damaged file can't ask for gigabytes.
+pub const MAX_HIDDEN: usize = 1 << 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Network {
+    hidden: usize,
+    /// `hidden` rows of `INPUTS` weights each.
+    w1: Vec<f32>,
+    b1: Vec<f32>,
+    w2: Vec<f32>,
+    b2: f32,
+}
+
+/// The inputs that are set, as indices into the 128 inputs.
+pub fn active_inputs(position: &Position) -> Vec<usize> {
+    let me = position.to_move();
+    (0..CELLS)
+        .filter_map(|cell| match position.at(cell) {
+            Some(p) if p == me => Some(cell),
+            Some(_) => Some(CELLS + cell),
+            None => None,
+        })
+        .collect()
+}
+
+impl Network {
+    /// A network with small random weights.
+    pub fn random<R: Rng>(hidden: usize, rng: &mut R) -> Self {
+        let scale = 1.0 / (INPUTS as f32).sqrt();
+        Self {
+            hidden,
+            w1: (0..hidden * INPUTS)
+                .map(|_| rng.gen_range(-scale..scale))
+                .collect(),
+            b1: (0..hidden).map(|_| rng.gen_range(0.0..scale)).collect(),
+            w2: (0..hidden).map(|_| rng.gen_range(-scale..scale)).collect(),
+            b2: 0.0,
+        }
+    }
+
+    pub fn hidden(&self) -> usize {
+        self.hidden
+    }
+
+    fn hidden_layer(&self, active: &[usize]) -> Vec<f32> {
+        (0..self.hidden)
+            .map(|h| {
+                let row = &self.w1[h * INPUTS..(h + 1) * INPUTS];
+                let sum = self.b1[h] + active.iter().map(|&i| row[i]).sum::<f32>();
+                sum.max(0.0)
+            })
+            .collect()
+    }
+
+    /// The output in `-1.0..=1.0`, from the point of view of the player to move.
+    pub fn predict(&self, active: &[usize]) -> f32 {
+        let hidden = self.hidden_layer(active);
+        let sum = self.b2 + hidden.iter().zip(&self.w2).map(|(h, w)| h * w).sum::<f32>();
+        sum.tanh()
+    }
+
+    /// One step of gradient descent on the squared error against `target`.
+    /// Returns the error before the step.
+    pub fn train(&mut self, active: &[usize], target: f32, learning_rate: f32) -> f32 {
+        let hidden = self.hidden_layer(active);
+        let output =
+            (self.b2 + hidden.iter().zip(&self.w2).map(|(h, w)| h * w).sum::<f32>()).tanh();
+        let error = output - target;
+        let d_sum = error * (1.0 - output * output);
+
+        let rows = self.w1.chunks_mut(INPUTS);
+        for (((row, b1), w2), h) in rows.zip(&mut self.b1).zip(&mut self.w2).zip(hidden) {
+            if h > 0.0 {
+                let d_hidden = d_sum * *w2;
+                for &i in active {
+                    row[i] -= learning_rate * d_hidden;
+                }
+                *b1 -= learning_rate * d_hidden;
+            }
+            *w2 -= learning_rate * d_sum * h;
+        }
+        self.b2 -= learning_rate * d_sum;
+        error * error
+    }
+
+    /// Reads weights written by `save`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl std::fmt::Display for Network {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} {} {}", HEADER, INPUTS, self.hidden)?;
+        writeln!(f, "{}", self.b2)?;
+        for row in [&self.w2[..], &self.b1[..]]
+            .into_iter()
+            .chain(self.w1.chunks(INPUTS))
+        {
+            let line: Vec<String> = row.iter().map(f32::to_string).collect();
+            writeln!(f, "{}", line.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Network {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |what: &str| io::Error::new(ErrorKind::InvalidData, what.to_string());
+        let mut words = s.split_whitespace();
+        if words.next() != Some(HEADER) {
+            return Err(invalid("not a network weights file"));
+        }
+        let mut count = |what: &str| -> io::Result<usize> {
+            words
+                .next()
+                .ok_or_else(|| invalid(&format!("missing {}", what)))?
+                .parse()
+                .map_err(|_| invalid(&format!("bad {}", what)))
+        };
+        if count("input count")? != INPUTS {
+            return Err(invalid("wrong number of inputs"));
+        }
+        let hidden = count("hidden size")?;
+        if !(1..=MAX_HIDDEN).contains(&hidden) {
+            return Err(invalid(&format!(
+                "hidden size must be 1 to {}, not {}",
+                MAX_HIDDEN, hidden
+            )));
+        }
+        let hidden_weights = hidden
+            .checked_mul(INPUTS)
+            .ok_or_else(|| invalid("hidden size too big"))?;
+        let mut number = |what: &str| -> io::Result<f32> {
+            words
+                .next()
+                .ok_or_else(|| invalid(&format!("missing {}", what)))?
+                .parse()
+                .map_err(|_| invalid(&format!("bad number in {}", what)))
+        };
+        let b2 = number("output bias")?;
+        let w2 = (0..hidden)
+            .map(|_| number("output weights"))
+            .collect::<io::Result<_>>()?;
+        let b1 = (0..hidden)
+            .map(|_| number("hidden biases"))
+            .collect::<io::Result<_>>()?;
+        let w1 = (0..hidden_weights)
+            .map(|_| number("hidden weights"))
+            .collect::<io::Result<_>>()?;
+        if words.next().is_some() {
+            return Err(invalid("trailing data"));
+        }
+        Ok(Self {
+            hidden,
+            w1,
+            b1,
+            w2,
+            b2,
+        })
+    }
+}
+
+impl Evaluator for Network {
+    fn evaluate(&self, position: &Position) -> i32 {
+        (self.predict(&active_inputs(position)) * SCALE) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Location;
+    use crate::logic::{Board, Player};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn inputs_are_relative_to_the_player_to_move() {
+        let mut board = Board::new();
+        board.place(Player::A, Location::new(1, 0, 0)).unwrap();
+        board.place(Player::B, Location::new(2, 0, 0)).unwrap();
+
+        let a = active_inputs(&Position::new(&board, Player::A));
+        let b = active_inputs(&Position::new(&board, Player::B));
+        assert_eq!(a, vec![1, CELLS + 2]);
+        assert_eq!(b, vec![CELLS + 1, 2]);
+    }
+
+    #[test]
+    fn training_moves_the_output_towards_the_target() {
+        let mut network = Network::random(16, &mut StdRng::seed_from_u64(1));
+        let mut board = Board::new();
+        board.place(Player::A, Location::new(0, 0, 0)).unwrap();
+        board.place(Player::B, Location::new(3, 3, 3)).unwrap();
+        let active = active_inputs(&Position::new(&board, Player::A));
+        let before = network.train(&active, 0.5, 0.05);
+        for _ in 0..200 {
+            network.train(&active, 0.5, 0.05);
+        }
+        let after = network.train(&active, 0.5, 0.05);
+        assert!(after < before);
+        assert!((network.predict(&active) - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn weights_survive_a_round_trip_through_text() {
+        let network = Network::random(4, &mut StdRng::seed_from_u64(2));
+        let parsed: Network = network.to_string().parse().unwrap();
+        assert_eq!(parsed, network);
+    }
+
+    #[test]
+    fn a_truncated_weights_file_is_rejected() {
+        let network = Network::random(4, &mut StdRng::seed_from_u64(3));
+        let text = network.to_string();
+        let err = text[..text.len() / 2].parse::<Network>().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn implausible_sizes_are_rejected() {
+        for header in ["4.5", "-1", "0", "1e9", "18446744073709551615"] {
+            let text = format!("{} {} {} 0", HEADER, INPUTS, header);
+            let err = text.parse::<Network>().unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "{}", header);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Position notation: the four layers from the bottom up separated by `/`,
+//! each as 16 spots row by row from `a1` (`A`, `B` or `.` for empty), then a
+//! space and whose turn it is, `A` or `B`, or `-` once the game is over.
+//! A game that ended off the board says how instead: `A:resignation`,
+//! `A:timeout` or `A:forfeit` for a win by A, and `draw:agreement` for an
+//! agreed draw.
+//!
+//! The starting position is
+//! `................/................/................/................ A`.
+
+use super::{
+    calculated::LINES, Board, DrawReason, GamePlayStatus, Player, TTTTState, WinReason, SIZE,
+};
+use crate::common::Location;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePositionError(pub String);
+
+impl Display for ParsePositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bad position: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParsePositionError {}
+
+pub(crate) fn letter(player: Player) -> char {
+    match player {
+        Player::A => 'A',
+        Player::B => 'B',
+    }
+}
+
+impl TTTTState {
+    pub fn to_notation(&self) -> String {
+        let layers: Vec<String> = (0..SIZE)
+            .map(|z| {
+                (0..SIZE * SIZE)
+                    .map(
+                        |i| match self.board.at(Location::new(i % SIZE, i / SIZE, z)) {
+                            Some(Player::A) => 'A',
+                            Some(Player::B) => 'B',
+                            None => '.',
+                        },
+                    )
+                    .collect()
+            })
+            .collect();
+        let side = match self.status {
+            GamePlayStatus::Playing(Player::A) => "A".to_string(),
+            GamePlayStatus::Playing(Player::B) => "B".to_string(),
+            GamePlayStatus::Win {
+                player,
+                reason: reason @ (WinReason::Resignation | WinReason::Timeout | WinReason::Forfeit),
+            } => format!("{}:{}", letter(player), reason.word()),
+            GamePlayStatus::Draw {
+                reason: DrawReason::Agreement,
+            } => "draw:agreement".to_string(),
+            _ => "-".to_string(),
+        };
+        format!("{} {}", layers.join("/"), side)
+    }
+
+    pub fn from_notation(notation: &str) -> Result<Self, ParsePositionError> {
+        let err = |why: &str| ParsePositionError(format!("{} in `{}`", why, notation));
+        let (layers, side) = notation
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| err("no side to move"))?;
+        let layers: Vec<&str> = layers.split('/').collect();
+        if layers.len() != SIZE {
+            return Err(err("there must be 4 layers"));
+        }
+        let mut board = Board::new();
+        for (z, layer) in layers.iter().enumerate() {
+            if layer.chars().count() != SIZE * SIZE {
+                return Err(err("each layer needs 16 spots"));
+            }
+            for (i, c) in layer.chars().enumerate() {
+                board.spots[z][i / SIZE][i % SIZE] = match c {
+                    'A' | 'a' => Some(Player::A),
+                    'B' | 'b' => Some(Player::B),
+                    '.' => None,
+                    _ => return Err(err("spots are A, B or .")),
+                };
+            }
+        }
+
+        let winners: Vec<Player> = LINES
+            .iter()
+            .filter_map(|line| {
+                let first = board.at(line[0])?;
+                line.iter()
+                    .all(|l| board.at(*l) == Some(first))
+                    .then_some(first)
+            })
+            .collect();
+        let mut state = Self {
+            board,
+            ..Self::new()
+        };
+        let ended = side.trim().split_once(':');
+        state.status = match (winners.first(), side.trim()) {
+            (Some(&winner), _) if winners.iter().all(|w| *w == winner) => {
+                GamePlayStatus::line(winner)
+            }
+            (Some(_), _) => return Err(err("both players have a line")),
+            (None, "draw:agreement") => GamePlayStatus::Draw {
+                reason: DrawReason::Agreement,
+            },
+            (None, _) if ended.is_some() => {
+                let (winner, reason) = ended.unwrap();
+                let player = match winner {
+                    "A" | "a" => Player::A,
+                    "B" | "b" => Player::B,
+                    _ => return Err(err("the winner is A or B")),
+                };
+                match WinReason::from_word(reason) {
+                    Some(
+                        reason @ (WinReason::Resignation | WinReason::Timeout | WinReason::Forfeit),
+                    ) => GamePlayStatus::Win { player, reason },
+                    _ => {
+                        return Err(err(
+                            "games end off the board by resignation, timeout or forfeit",
+                        ))
+                    }
+                }
+            }
+            (None, _) if state.turn() == SIZE * SIZE * SIZE => GamePlayStatus::Draw {
+                reason: DrawReason::FullBoard,
+            },
+            (None, "A" | "a") => GamePlayStatus::Playing(Player::A),
+            (None, "B" | "b") => GamePlayStatus::Playing(Player::B),
+            (None, _) => return Err(err("the side to move is A or B")),
+        };
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_starting_position() {
+        let start = "................/................/................/................ A";
+        assert_eq!(TTTTState::new().to_notation(), start);
+        assert_eq!(TTTTState::from_notation(start), Ok(TTTTState::new()));
+    }
+
+    #[test]
+    fn positions_round_trip() {
+        let mut state = TTTTState::new();
+        for loc in ["a11", "b21", "d44", "c32", "a12"] {
+            state.play(loc.parse().unwrap()).unwrap();
+            let notation = state.to_notation();
+            assert_eq!(TTTTState::from_notation(&notation), Ok(state.clone()));
+        }
+        assert_eq!(
+            state.to_notation(),
+            "A....B........../A.........B...../................/...............A B"
+        );
+    }
+
+    #[test]
+    fn a_completed_line_is_a_win() {
+        let state = TTTTState::from_notation(
+            "AAAA............/BBB............./................/................ B",
+        )
+        .unwrap();
+        assert_eq!(state.status, GamePlayStatus::line(Player::A));
+        assert!(state.to_notation().ends_with(" -"));
+    }
+
+    #[test]
+    fn games_ended_off_the_board_say_how() {
+        let mut state = TTTTState::new();
+        state.play("a11".parse().unwrap()).unwrap();
+        let mut resigned = state.clone();
+        resigned.resign(Player::B).unwrap();
+        let mut timed_out = state.clone();
+        timed_out.status = GamePlayStatus::Win {
+            player: Player::B,
+            reason: WinReason::Timeout,
+        };
+        let mut forfeited = state.clone();
+        forfeited.forfeit(Player::A).unwrap();
+        let mut agreed = state;
+        agreed.agree_draw().unwrap();
+        for (state, side) in [
+            (resigned, " A:resignation"),
+            (timed_out, " B:timeout"),
+            (forfeited, " B:forfeit"),
+            (agreed, " draw:agreement"),
+        ] {
+            let notation = state.to_notation();
+            assert!(notation.ends_with(side), "{}", notation);
+            assert_eq!(TTTTState::from_notation(&notation), Ok(state));
+        }
+        let line = "................/................/................/................ A:line";
+        assert!(TTTTState::from_notation(line).is_err());
+    }
+
+    #[test]
+    fn malformed_positions_are_rejected() {
+        for bad in [
+            "",
+            "................/................/................/................",
+            "................/................/................ A",
+            "................/................/................/...............  A",
+            "................/................/................/...............x A",
+            "................/................/................/................ C",
+        ] {
+            assert!(TTTTState::from_notation(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,688 @@
+//! Alpha-beta search over the game, built on the line tables.
+//!
+//! Moves that win, block a win, or make a new threat are searched first,
+//! followed by killer moves and the history heuristic. The root uses
+//! iterative deepening with aspiration windows and every node uses a null
+//! window for all but its first move. None of this changes the value that is
+//! found: at a given depth it's the same as a plain minimax.
+
+use super::{calculated::LINES, computer_player::CancelToken, Board, Player, TTTTState, SIZE};
+use crate::common::Location;
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+pub const CELLS: usize = SIZE * SIZE * SIZE;
+
+/// The score for winning right now. Wins further away score a bit less, so
+/// the search prefers the quickest win and the slowest loss.
+pub const WIN: i32 = 1_000_000;
+
+const INFINITY: i32 = WIN + 1;
+const ASPIRATION_WINDOW: i32 = 32;
+
+pub fn index(loc: Location) -> usize {
+    loc.x + SIZE * loc.y + SIZE * SIZE * loc.z
+}
+
+pub fn location(index: usize) -> Location {
+    Location::new(index % SIZE, index / SIZE % SIZE, index / (SIZE * SIZE))
+}
+
+/// For every cell, the indices into `LINES` of the lines that pass through it.
+fn lines_through(cell: usize) -> &'static [usize] {
+    static TABLE: OnceLock<Vec<Vec<usize>>> = OnceLock::new();
+    &TABLE.get_or_init(|| {
+        let mut table = vec![vec![]; CELLS];
+        for (i, line) in LINES.iter().enumerate() {
+            for loc in line {
+                table[index(*loc)].push(i);
+            }
+        }
+        table
+    })[cell]
+}
+
+const fn zobrist_keys() -> [[u64; CELLS]; 2] {
+    // splitmix64, so the keys are the same on every run
+    let mut keys = [[0; CELLS]; 2];
+    let mut state: u64 = 0x5155_4249_4320_3444;
+    let mut i = 0;
+    while i < 2 * CELLS {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        keys[i / CELLS][i % CELLS] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+}
+
+const ZOBRIST: [[u64; CELLS]; 2] = zobrist_keys();
+
+fn side(player: Player) -> usize {
+    match player {
+        Player::A => 0,
+        Player::B => 1,
+    }
+}
+
+/// A board laid out for searching: cells by index, and how many stones each
+/// player has in every line, kept up to date as moves are made and undone.
+#[derive(Debug, Clone)]
+pub struct Position {
+    cells: [Option<Player>; CELLS],
+    counts: [[u8; LINES.len()]; 2],
+    to_move: Player,
+    stones: usize,
+    hash: u64,
+    won: bool,
+}
+
+impl Position {
+    pub fn new(board: &Board, to_move: Player) -> Self {
+        let mut position = Self {
+            cells: [None; CELLS],
+            counts: [[0; LINES.len()]; 2],
+            to_move,
+            stones: 0,
+            hash: 0,
+            won: false,
+        };
+        for cell in 0..CELLS {
+            if let Some(player) = board.at(location(cell)) {
+                position.put(cell, player);
+            }
+        }
+        position
+    }
+
+    /// The position to search from, or `None` if the game is over.
+    pub fn from_state(state: &TTTTState) -> Option<Self> {
+        match state.status {
+            super::GamePlayStatus::Playing(player) => Some(Self::new(&state.board, player)),
+            _ => None,
+        }
+    }
+
+    pub fn to_move(&self) -> Player {
+        self.to_move
+    }
+
+    pub fn at(&self, cell: usize) -> Option<Player> {
+        self.cells[cell]
+    }
+
+    pub fn stones(&self) -> usize {
+        self.stones
+    }
+
+    /// How many stones `player` has in each line, in the order of `LINES`.
+    pub fn line_counts(&self, player: Player) -> &[u8; LINES.len()] {
+        &self.counts[side(player)]
+    }
+
+    /// Whether the last move completed a line.
+    pub fn is_won(&self) -> bool {
+        self.won
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.stones == CELLS
+    }
+
+    fn put(&mut self, cell: usize, player: Player) {
+        self.cells[cell] = Some(player);
+        self.stones += 1;
+        self.hash ^= ZOBRIST[side(player)][cell];
+        for &line in lines_through(cell) {
+            self.counts[side(player)][line] += 1;
+        }
+    }
+
+    pub fn play(&mut self, cell: usize) {
+        let player = self.to_move;
+        self.put(cell, player);
+        self.won = lines_through(cell)
+            .iter()
+            .any(|&line| self.counts[side(player)][line] == 4);
+        self.to_move = player.other_player();
+    }
+
+    pub fn undo(&mut self, cell: usize) {
+        let player = self.to_move.other_player();
+        self.cells[cell] = None;
+        self.stones -= 1;
+        self.hash ^= ZOBRIST[side(player)][cell];
+        for &line in lines_through(cell) {
+            self.counts[side(player)][line] -= 1;
+        }
+        self.won = false;
+        self.to_move = player;
+    }
+
+    pub fn empty_cells(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..CELLS).filter(|&cell| self.cells[cell].is_none())
+    }
+}
+
+/// Scores positions that aren't over yet.
+pub trait Evaluator {
+    /// The score from the point of view of the player to move. Higher is
+    /// better, and it must stay well inside `WIN`.
+    fn evaluate(&self, position: &Position) -> i32;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The deepest iteration to search.
+    pub depth: u8,
+    /// Stop after this long, with the result of the last finished iteration.
+    pub movetime: Option<Duration>,
+}
+
+impl Limits {
+    pub fn depth(depth: u8) -> Self {
+        Self {
+            depth,
+            movetime: None,
+        }
+    }
+
+    pub fn movetime(movetime: Duration) -> Self {
+        Self {
+            depth: CELLS as u8,
+            movetime: Some(movetime),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchInfo {
+    pub best: Location,
+    pub score: i32,
+    /// The depth of the last iteration that finished.
+    pub depth: u8,
+    pub nodes: u64,
+    /// The expected line of play, starting with `best`.
+    pub pv: Vec<Location>,
+}
+
+/// Searches `state` with iterative deepening. Returns `None` if the game is
+/// over or the search was cancelled. Running out of time isn't cancelling:
+/// the first iteration always finishes, and the deepest one that finished
+/// is reported.
+pub fn analyse<E: Evaluator + ?Sized>(
+    eval: &E,
+    state: &TTTTState,
+    limits: Limits,
+    cancel: &CancelToken,
+) -> Option<SearchInfo> {
+    analyse_until(eval, state, limits, cancel, &CancelToken::new())
+}
+
+/// Like `analyse`, but `stop` can also end the search early. Unlike
+/// cancelling, stopping keeps the result of the last finished iteration.
+pub fn analyse_until<E: Evaluator + ?Sized>(
+    eval: &E,
+    state: &TTTTState,
+    limits: Limits,
+    cancel: &CancelToken,
+    stop: &CancelToken,
+) -> Option<SearchInfo> {
+    let position = Position::from_state(state)?;
+    if position.is_full() {
+        return None;
+    }
+    let mut searcher = Searcher::new(eval, position, cancel, stop);
+    let started = Instant::now();
+    let mut info: Option<SearchInfo> = None;
+    for depth in 1..=limits.depth.min((CELLS - searcher.position.stones()) as u8) {
+        if depth > 1 {
+            searcher.deadline = limits.movetime.map(|t| started + t);
+            searcher.may_stop = true;
+            if searcher.out_of_time() {
+                break;
+            }
+        }
+        let score = match &info {
+            Some(prev) if depth > 2 => searcher.aspiration(depth, prev.score),
+            _ => searcher.root(depth, -INFINITY, INFINITY),
+        };
+        if searcher.aborted {
+            break;
+        }
+        let best = searcher.root_best?;
+        info = Some(SearchInfo {
+            best: location(best),
+            score,
+            depth,
+            nodes: searcher.nodes,
+            pv: searcher.principal_variation(best, depth),
+        });
+        if score.abs() > WIN - CELLS as i32 {
+            // A forced result has been found; deeper won't change it.
+            break;
+        }
+    }
+    if cancel.is_cancelled() {
+        return None;
+    }
+    info
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    hash: u64,
+    depth: u8,
+    score: i32,
+    bound: Bound,
+    best: Option<u8>,
+}
+
+const TABLE_SIZE: usize = 1 << 18;
+const KILLERS: usize = 2;
+
+struct Searcher<'a, E: ?Sized> {
+    eval: &'a E,
+    position: Position,
+    cancel: &'a CancelToken,
+    stop: &'a CancelToken,
+    /// Stopping and deadlines only count once the first iteration is done.
+    may_stop: bool,
+    deadline: Option<Instant>,
+    aborted: bool,
+    nodes: u64,
+    root_best: Option<usize>,
+    table: Vec<Option<Entry>>,
+    killers: [[Option<usize>; KILLERS]; CELLS + 1],
+    history: [[u32; CELLS]; 2],
+}
+
+impl<'a, E: Evaluator + ?Sized> Searcher<'a, E> {
+    fn new(
+        eval: &'a E,
+        position: Position,
+        cancel: &'a CancelToken,
+        stop: &'a CancelToken,
+    ) -> Self {
+        Self {
+            eval,
+            position,
+            cancel,
+            stop,
+            may_stop: false,
+            deadline: None,
+            aborted: false,
+            nodes: 0,
+            root_best: None,
+            table: vec![None; TABLE_SIZE],
+            killers: [[None; KILLERS]; CELLS + 1],
+            history: [[0; CELLS]; 2],
+        }
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if !self.aborted && self.nodes.is_multiple_of(1024) {
+            self.aborted = self.cancel.is_cancelled() || self.out_of_time();
+        }
+        self.aborted
+    }
+
+    fn out_of_time(&self) -> bool {
+        self.may_stop
+            && (self.stop.is_cancelled() || self.deadline.is_some_and(|d| Instant::now() >= d))
+    }
+
+    fn aspiration(&mut self, depth: u8, guess: i32) -> i32 {
+        let (mut alpha, mut beta) = (guess - ASPIRATION_WINDOW, guess + ASPIRATION_WINDOW);
+        loop {
+            let score = self.root(depth, alpha, beta);
+            if self.aborted {
+                return score;
+            }
+            if score <= alpha {
+                alpha = -INFINITY;
+            } else if score >= beta {
+                beta = INFINITY;
+            } else {
+                return score;
+            }
+        }
+    }
+
+    fn root(&mut self, depth: u8, alpha: i32, beta: i32) -> i32 {
+        self.negamax(depth, 0, alpha, beta)
+    }
+
+    fn probe(&self) -> Option<Entry> {
+        let hash = self.position.hash;
+        self.table[hash as usize % TABLE_SIZE].filter(|e| e.hash == hash)
+    }
+
+    fn store(&mut self, depth: u8, score: i32, bound: Bound, best: Option<usize>) {
+        let hash = self.position.hash;
+        let slot = &mut self.table[hash as usize % TABLE_SIZE];
+        if slot.is_none_or(|e| e.hash != hash || e.depth <= depth) {
+            *slot = Some(Entry {
+                hash,
+                depth,
+                score,
+                bound,
+                best: best.map(|b| b as u8),
+            });
+        }
+    }
+
+    fn negamax(&mut self, depth: u8, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        if self.should_stop() {
+            return 0;
+        }
+        if self.position.is_won() {
+            return -(WIN - ply as i32);
+        }
+        if self.position.is_full() {
+            return 0;
+        }
+        if depth == 0 {
+            return self.eval.evaluate(&self.position);
+        }
+
+        let entry = self.probe();
+        if ply > 0 {
+            if let Some(e) = entry.filter(|e| e.depth >= depth) {
+                match e.bound {
+                    Bound::Exact => return e.score,
+                    Bound::Lower if e.score >= beta => return e.score,
+                    Bound::Upper if e.score <= alpha => return e.score,
+                    _ => (),
+                }
+            }
+        }
+
+        let original_alpha = alpha;
+        let moves = self.ordered_moves(ply, entry.and_then(|e| e.best).map(usize::from));
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        for (i, &cell) in moves.iter().enumerate() {
+            self.position.play(cell);
+            let score = if i == 0 {
+                -self.negamax(depth - 1, ply + 1, -beta, -alpha)
+            } else {
+                let score = -self.negamax(depth - 1, ply + 1, -alpha - 1, -alpha);
+                if score > alpha && score < beta {
+                    -self.negamax(depth - 1, ply + 1, -beta, -alpha)
+                } else {
+                    score
+                }
+            };
+            self.position.undo(cell);
+            if self.aborted {
+                return 0;
+            }
+            if score > best {
+                best = score;
+                best_move = Some(cell);
+                if ply == 0 {
+                    self.root_best = Some(cell);
+                }
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                self.remember_cutoff(cell, depth, ply);
+                break;
+            }
+        }
+
+        let bound = if best <= original_alpha {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.store(depth, best, bound, best_move);
+        best
+    }
+
+    fn remember_cutoff(&mut self, cell: usize, depth: u8, ply: usize) {
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(cell) {
+            killers[1] = killers[0];
+            killers[0] = Some(cell);
+        }
+        let history = &mut self.history[side(self.position.to_move())][cell];
+        *history = history.saturating_add(u32::from(depth) * u32::from(depth));
+    }
+
+    /// Empty cells, most promising first.
+    fn ordered_moves(&self, ply: usize, hash_move: Option<usize>) -> Vec<usize> {
+        let me = side(self.position.to_move());
+        let them = 1 - me;
+        let counts = &self.position.counts;
+        let mut moves: Vec<(i64, usize)> = self
+            .position
+            .empty_cells()
+            .map(|cell| {
+                let mut score: i64 = 0;
+                for &line in lines_through(cell) {
+                    let (mine, theirs) = (counts[me][line], counts[them][line]);
+                    score += match (mine, theirs) {
+                        (3, 0) => 1 << 40,
+                        (0, 3) => 1 << 36,
+                        (2, 0) => 1 << 26,
+                        (0, 2) => 1 << 25,
+                        (n, 0) => 1 << (20 + n),
+                        (0, n) => 1 << (19 + n),
+                        _ => 0,
+                    };
+                }
+                if Some(cell) == hash_move {
+                    score += 1 << 50;
+                }
+                if self.killers[ply].contains(&Some(cell)) {
+                    score += 1 << 30;
+                }
+                score += i64::from(self.history[me][cell]);
+                (score, cell)
+            })
+            .collect();
+        moves.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        moves.into_iter().map(|(_, cell)| cell).collect()
+    }
+
+    fn principal_variation(&mut self, best: usize, depth: u8) -> Vec<Location> {
+        let mut pv = vec![best];
+        self.position.play(best);
+        while pv.len() < depth as usize && !self.position.is_won() {
+            match self.probe().and_then(|e| e.best).map(usize::from) {
+                Some(cell) if self.position.at(cell).is_none() => {
+                    pv.push(cell);
+                    self.position.play(cell);
+                }
+                _ => break,
+            }
+        }
+        for &cell in pv.iter().rev() {
+            self.position.undo(cell);
+        }
+        pv.into_iter().map(location).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::{computer_player::Eval, state_after};
+
+    /// Plain minimax with the same scoring, to check the search against.
+    fn minimax(position: &mut Position, depth: u8, ply: usize, nodes: &mut u64) -> i32 {
+        *nodes += 1;
+        if position.is_won() {
+            return -(WIN - ply as i32);
+        }
+        if position.is_full() {
+            return 0;
+        }
+        if depth == 0 {
+            return Eval.evaluate(position);
+        }
+        let cells: Vec<usize> = position.empty_cells().collect();
+        let mut best = -INFINITY;
+        for cell in cells {
+            position.play(cell);
+            best = best.max(-minimax(position, depth - 1, ply + 1, nodes));
+            position.undo(cell);
+        }
+        best
+    }
+
+    fn positions() -> Vec<TTTTState> {
+        vec![
+            state_after(&[]),
+            state_after(&[(0, 0, 0), (1, 1, 1)]),
+            state_after(&[(0, 0, 0), (1, 1, 1), (1, 0, 0), (2, 2, 2), (2, 0, 0)]),
+            state_after(&[(1, 1, 1), (2, 2, 2), (1, 2, 1), (0, 0, 3), (1, 3, 1)]),
+            state_after(&[
+                (0, 0, 0),
+                (3, 3, 3),
+                (1, 1, 0),
+                (3, 3, 0),
+                (2, 2, 0),
+                (0, 3, 3),
+                (3, 0, 3),
+            ]),
+            state_after(&[
+                (1, 1, 1),
+                (2, 2, 2),
+                (1, 2, 2),
+                (2, 1, 1),
+                (1, 1, 2),
+                (2, 2, 1),
+                (0, 0, 0),
+                (3, 3, 3),
+            ]),
+        ]
+    }
+
+    #[test]
+    fn finds_the_same_value_as_plain_minimax() {
+        for depth in 1..=3 {
+            for state in positions() {
+                let info =
+                    analyse(&Eval, &state, Limits::depth(depth), &CancelToken::new()).unwrap();
+                let mut position = Position::from_state(&state).unwrap();
+                let expected = minimax(&mut position, depth, 0, &mut 0);
+                assert_eq!(info.score, expected, "depth {depth} in {state:?}");
+
+                position.play(index(info.best));
+                let of_best = -minimax(&mut position, depth - 1, 1, &mut 0);
+                assert_eq!(of_best, expected, "depth {depth} in {state:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn searches_a_ply_deeper_in_fewer_nodes_than_plain_minimax() {
+        for state in positions() {
+            let info = analyse(&Eval, &state, Limits::depth(4), &CancelToken::new()).unwrap();
+            let mut minimax_nodes = 0;
+            minimax(
+                &mut Position::from_state(&state).unwrap(),
+                3,
+                0,
+                &mut minimax_nodes,
+            );
+            assert!(
+                info.nodes * 4 < minimax_nodes,
+                "{} vs {} in {state:?}",
+                info.nodes,
+                minimax_nodes
+            );
+        }
+    }
+
+    #[test]
+    fn prefers_the_quickest_win() {
+        let state = state_after(&[
+            (0, 0, 0),
+            (0, 3, 3),
+            (1, 0, 0),
+            (3, 3, 0),
+            (2, 0, 0),
+            (3, 0, 3),
+        ]);
+        let info = analyse(&Eval, &state, Limits::depth(3), &CancelToken::new()).unwrap();
+        assert_eq!(info.best, Location::new(3, 0, 0));
+        assert_eq!(info.score, WIN - 1);
+        assert_eq!(info.depth, 1);
+    }
+
+    #[test]
+    fn the_principal_variation_starts_with_the_best_move() {
+        for state in positions() {
+            let info = analyse(&Eval, &state, Limits::depth(3), &CancelToken::new()).unwrap();
+            assert_eq!(info.pv[0], info.best);
+            let mut after = state.clone();
+            for loc in &info.pv {
+                assert_eq!(after.board.at(*loc), None);
+                after.play(*loc).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn a_movetime_still_finishes_the_first_iteration() {
+        let info = analyse(
+            &Eval,
+            &state_after(&[]),
+            Limits::movetime(Duration::ZERO),
+            &CancelToken::new(),
+        )
+        .unwrap();
+        assert_eq!(info.depth, 1);
+    }
+
+    #[test]
+    fn stopping_keeps_the_last_finished_iteration() {
+        let stop = CancelToken::new();
+        stop.cancel();
+        let info = analyse_until(
+            &Eval,
+            &state_after(&[]),
+            Limits::depth(6),
+            &CancelToken::new(),
+            &stop,
+        )
+        .unwrap();
+        assert_eq!(info.depth, 1);
+    }
+
+    #[test]
+    fn making_and_undoing_moves_restores_the_position() {
+        let state = positions().pop().unwrap();
+        let mut position = Position::from_state(&state).unwrap();
+        let before = position.clone();
+        let cells: Vec<usize> = position.empty_cells().take(5).collect();
+        for &cell in &cells {
+            position.play(cell);
+        }
+        for &cell in cells.iter().rev() {
+            position.undo(cell);
+        }
+        assert_eq!(position.cells, before.cells);
+        assert_eq!(position.counts, before.counts);
+        assert_eq!(position.hash, before.hash);
+        assert_eq!(position.to_move, before.to_move);
+    }
+}
@@ -0,0 +1,235 @@
+//! Playing engines against each other to see which is stronger.
+
+use super::{
+    computer_player::CancelToken,
+    search::{analyse, location, Evaluator, Limits, CELLS},
+    GamePlayStatus, Player, TTTTState,
+};
+use crate::common::Location;
+use rand::{seq::SliceRandom, Rng};
+
+/// Anything that can pick moves: a search with some evaluator, or a bot.
+pub trait Agent {
+    fn name(&self) -> String;
+
+    /// The move to play in `state`, or `None` to give up.
+    fn choose(&mut self, state: &TTTTState) -> Option<Location>;
+}
+
+/// A fixed-depth search using `eval`.
+pub struct SearchAgent<E> {
+    pub name: String,
+    pub eval: E,
+    pub limits: Limits,
+}
+
+impl<E: Evaluator> Agent for SearchAgent<E> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn choose(&mut self, state: &TTTTState) -> Option<Location> {
+        analyse(&self.eval, state, self.limits, &CancelToken::new()).map(|info| info.best)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchResult {
+    pub first_wins: u32,
+    pub second_wins: u32,
+    pub draws: u32,
+}
+
+impl MatchResult {
+    pub fn games(&self) -> u32 {
+        self.first_wins + self.second_wins + self.draws
+    }
+
+    /// The first agent's share of the points, counting draws as half.
+    pub fn first_score(&self) -> f32 {
+        (self.first_wins as f32 + self.draws as f32 / 2.0) / self.games().max(1) as f32
+    }
+}
+
+/// Plays the opening moves and then lets `a` and `b` take turns. An agent
+/// that gives up or plays an occupied cell loses. An opening that finishes
+/// the game decides it, and whatever's left of it isn't played.
+pub fn play_game(a: &mut dyn Agent, b: &mut dyn Agent, opening: &[Location]) -> GamePlayStatus {
+    let mut state = TTTTState::new();
+    for loc in opening {
+        if !matches!(state.status, GamePlayStatus::Playing(_)) {
+            break;
+        }
+        state.play(*loc).expect("openings only use free spots");
+    }
+    while let GamePlayStatus::Playing(player) = state.status {
+        let choice = match player {
+            Player::A => a.choose(&state),
+            Player::B => b.choose(&state),
+        };
+        if choice.is_none_or(|loc| state.play(loc).is_err()) {
+            state.resign(player).expect("the game isn't over");
+        }
+    }
+    state.status
+}
+
+/// Plays every opening twice, once with each agent moving first.
+pub fn play_match(
+    first: &mut dyn Agent,
+    second: &mut dyn Agent,
+    openings: &[Vec<Location>],
+) -> MatchResult {
+    let mut result = MatchResult::default();
+    for opening in openings {
+        for first_is_a in [true, false] {
+            let status = if first_is_a {
+                play_game(first, second, opening)
+            } else {
+                play_game(second, first, opening)
+            };
+            match status {
+                GamePlayStatus::Win { player, .. } if (player == Player::A) == first_is_a => {
+                    result.first_wins += 1
+                }
+                GamePlayStatus::Win { .. } => result.second_wins += 1,
+                _ => result.draws += 1,
+            }
+        }
+    }
+    result
+}
+
+/// Openings of `length` random moves, so deterministic engines don't play
+/// the same game over and over. None of them finishes the game, so `length`
+/// has to be less than `CELLS`.
+pub fn random_openings<R: Rng>(count: usize, length: usize, rng: &mut R) -> Vec<Vec<Location>> {
+    assert!(
+        length < CELLS,
+        "an opening of {} moves leaves no game",
+        length
+    );
+    (0..count).map(|_| random_opening(length, rng)).collect()
+}
+
+/// Random moves that don't finish the game, starting over in the rare case
+/// that there aren't `length` of them.
+fn random_opening<R: Rng>(length: usize, rng: &mut R) -> Vec<Location> {
+    loop {
+        let mut cells: Vec<usize> = (0..CELLS).collect();
+        cells.shuffle(rng);
+        let mut state = TTTTState::new();
+        let mut opening = vec![];
+        for loc in cells.into_iter().map(location) {
+            if opening.len() == length {
+                break;
+            }
+            let mut next = state.clone();
+            next.play(loc).expect("openings only use free spots");
+            if matches!(next.status, GamePlayStatus::Playing(_)) {
+                state = next;
+                opening.push(loc);
+            }
+        }
+        if opening.len() == length {
+            return opening;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::computer_player::Eval;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    struct Resigner;
+    impl Agent for Resigner {
+        fn name(&self) -> String {
+            "resigner".to_string()
+        }
+
+        fn choose(&mut self, _state: &TTTTState) -> Option<Location> {
+            None
+        }
+    }
+
+    fn searcher(depth: u8) -> SearchAgent<Eval> {
+        SearchAgent {
+            name: format!("depth {}", depth),
+            eval: Eval,
+            limits: Limits::depth(depth),
+        }
+    }
+
+    #[test]
+    fn an_agent_that_gives_up_loses_every_game() {
+        let openings = random_openings(2, 2, &mut StdRng::seed_from_u64(1));
+        let result = play_match(&mut searcher(1), &mut Resigner, &openings);
+        assert_eq!(
+            result,
+            MatchResult {
+                first_wins: 4,
+                second_wins: 0,
+                draws: 0
+            }
+        );
+        assert_eq!(result.first_score(), 1.0);
+    }
+
+    #[test]
+    fn the_same_engine_on_both_sides_scores_even() {
+        let openings = random_openings(2, 4, &mut StdRng::seed_from_u64(2));
+        let result = play_match(&mut searcher(1), &mut searcher(1), &openings);
+        assert_eq!(result.games(), 4);
+        assert_eq!(result.first_score(), 0.5);
+    }
+
+    #[test]
+    fn openings_never_finish_the_game() {
+        for opening in random_openings(20, 40, &mut StdRng::seed_from_u64(4)) {
+            let mut state = TTTTState::new();
+            for loc in opening {
+                state.play(loc).unwrap();
+            }
+            assert_eq!(state.turn(), 40);
+            assert!(matches!(state.status, GamePlayStatus::Playing(_)));
+        }
+    }
+
+    #[test]
+    fn an_opening_that_finishes_the_game_decides_it() {
+        // A finishes a row on the seventh move; the last two never happen.
+        let opening: Vec<Location> = [
+            (0, 0, 0),
+            (0, 0, 1),
+            (1, 0, 0),
+            (1, 0, 1),
+            (2, 0, 0),
+            (2, 0, 1),
+            (3, 0, 0),
+            (3, 0, 1),
+            (0, 0, 2),
+        ]
+        .into_iter()
+        .map(Location::from)
+        .collect();
+        let status = play_game(&mut Resigner, &mut Resigner, &opening);
+        assert!(matches!(
+            status,
+            GamePlayStatus::Win {
+                player: Player::A,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn openings_never_repeat_a_cell() {
+        for opening in random_openings(20, 10, &mut StdRng::seed_from_u64(3)) {
+            for (i, loc) in opening.iter().enumerate() {
+                assert!(!opening[..i].contains(loc));
+            }
+        }
+    }
+}
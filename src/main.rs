@@ -1,14 +1,85 @@
 use bevy::prelude::*;
-use game_display::{GameDisplayPlugin, MyGame};
+use game_display::{AppState, GameDisplayPlugin, LanBrowser, MyGame, NetworkGame, Settings};
+use std::{env, process};
+use tic_tac_toc_toe::{
+    logic::clock::TimeControl,
+    net::{discovery, protocol::ClientMessage},
+};
 
-mod common;
 mod game_display;
-mod logic;
+
+const USAGE: &str = "usage: tic-tac-toc-toe [--connect HOST:PORT [--game ID | --watch ID]] \
+[--lan] [--discover HOST:PORT]... [--name NAME | --token TOKEN] [--clock BASE[+BONUS[b]]] \
+[--engine COMMAND]";
 
 fn main() {
-    App::new()
-        .insert_resource(MyGame::default())
-        .add_plugins(DefaultPlugins)
-        .add_plugin(GameDisplayPlugin)
-        .run();
+    let mut connect = None;
+    let mut request = ClientMessage::QuickMatch { computer: false };
+    let mut lan = vec![];
+    let mut login = None;
+    let mut settings = Settings::default();
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        if flag == "--lan" {
+            lan.push(discovery::broadcast());
+            continue;
+        }
+        match (flag.as_str(), args.next()) {
+            ("--connect", Some(addr)) => connect = Some(addr),
+            ("--name", Some(name)) => login = Some(ClientMessage::Register { name }),
+            ("--token", Some(token)) => login = Some(ClientMessage::Login { token }),
+            ("--clock", Some(control)) if control.parse::<TimeControl>().is_ok() => {
+                settings.clock = Some(control.parse().unwrap())
+            }
+            ("--engine", Some(command)) => settings.engine = Some(command),
+            ("--game", Some(id)) if id.parse::<u32>().is_ok() => {
+                request = ClientMessage::Join {
+                    game: id.parse().unwrap(),
+                }
+            }
+            ("--discover", Some(addr)) if addr.parse::<std::net::SocketAddr>().is_ok() => {
+                lan.push(addr.parse().unwrap())
+            }
+            ("--watch", Some(id)) if id.parse::<u32>().is_ok() => {
+                request = ClientMessage::Watch {
+                    game: id.parse().unwrap(),
+                }
+            }
+            _ => {
+                eprintln!("bad option {}\n{}", flag, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+    let watching = matches!(request, ClientMessage::Watch { .. });
+    if login.is_none() && (!lan.is_empty() || (connect.is_some() && !watching)) {
+        eprintln!(
+            "playing online needs --name NAME to register, or --token TOKEN\n{}",
+            USAGE
+        );
+        process::exit(2);
+    }
+
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
+        .add_plugin(GameDisplayPlugin {
+            start: match connect {
+                Some(_) => AppState::Playing,
+                None => AppState::MainMenu,
+            },
+        });
+    app.insert_resource(
+        settings
+            .clock
+            .map_or_else(MyGame::default, MyGame::with_clock),
+    );
+    if !lan.is_empty() {
+        app.insert_resource(LanBrowser::start(lan, login.clone().unwrap()));
+    }
+    if let Some(addr) = connect {
+        app.insert_non_send_resource(NetworkGame::connect(addr.as_str(), login.as_ref(), request));
+    }
+
+    app.insert_resource(Settings { login, ..settings });
+    app.run();
 }
@@ -0,0 +1,11 @@
+//! Playing over the network. The server owns every game and checks each
+//! move with `TTTTState::play`; clients only send the moves they'd like to
+//! make and show whatever state the server sends back.
+
+pub mod admin;
+pub mod bot_api;
+pub mod client;
+pub mod discovery;
+pub mod protocol;
+pub mod server;
+pub mod store;
@@ -0,0 +1,242 @@
+//! An HTTP endpoint for whoever runs the server, served on localhost, to
+//! see how it's doing and step in when a game goes wrong:
+//!
+//! | request | does |
+//! | --- | --- |
+//! | `GET /metrics` | Prometheus text metrics: games, clients, moves per minute and the computer's thinking time |
+//! | `GET /games` | every game the server holds, with its players and status |
+//! | `GET /games/1` | the same for game 1, with its position and moves |
+//! | `POST /games/1/abort` | stops game 1, telling its players and spectators |
+
+use super::{
+    bot_api::{failure, header, Reply},
+    protocol::{letter, GameId},
+    server::{GameSummary, Server, Stats},
+};
+use serde_json::{json, Map, Value};
+use std::{fmt::Write as _, io, net::TcpListener, thread};
+use tiny_http::{Method, Request, Response};
+
+/// The default port, only ever on localhost.
+pub const ADMIN_PORT: u16 = 7882;
+
+/// `stats` in the Prometheus text format.
+fn metrics(stats: &Stats) -> String {
+    let mut text = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, String)]| {
+        writeln!(text, "# HELP {} {}", name, help).unwrap();
+        writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+        for (labels, value) in samples {
+            writeln!(text, "{}{} {}", name, labels, value).unwrap();
+        }
+    };
+    metric(
+        "tttt_games",
+        "gauge",
+        "Games waiting for a player, and games being played.",
+        &[
+            (r#"{state="open"}"#, stats.open_games.to_string()),
+            (r#"{state="running"}"#, stats.running_games.to_string()),
+        ],
+    );
+    metric(
+        "tttt_clients",
+        "gauge",
+        "Connected clients.",
+        &[("", stats.clients.to_string())],
+    );
+    metric(
+        "tttt_spectators",
+        "gauge",
+        "Clients watching a game.",
+        &[("", stats.spectators.to_string())],
+    );
+    metric(
+        "tttt_moves_total",
+        "counter",
+        "Moves played since the server started.",
+        &[("", stats.moves.to_string())],
+    );
+    metric(
+        "tttt_moves_per_minute",
+        "gauge",
+        "Moves played in the last minute.",
+        &[("", stats.moves_last_minute.to_string())],
+    );
+    metric(
+        "tttt_computer_think_seconds",
+        "summary",
+        "Time the computer spent choosing its moves.",
+        &[
+            ("_sum", stats.think_time.as_secs_f64().to_string()),
+            ("_count", stats.computer_moves.to_string()),
+        ],
+    );
+    text
+}
+
+/// `game` as JSON, with its position and moves if `full`.
+fn summary_json(game: &GameSummary, full: bool) -> Value {
+    let players: Map<String, Value> = game
+        .players
+        .iter()
+        .map(|(player, name)| (letter(*player).to_string(), json!(name)))
+        .collect();
+    let mut summary = json!({
+        "game": game.id,
+        "players": players,
+        "away": game.away,
+        "spectators": game.spectators,
+        "quick": game.quick,
+        "status": game.state.status,
+        "turn": game.moves.len(),
+    });
+    if full {
+        summary["state"] = json!(game.state);
+        summary["moves"] = json!(game.moves);
+    }
+    summary
+}
+
+fn route(server: &Server, method: &Method, url: &str) -> Reply {
+    let path = url.split_once('?').map_or(url, |(path, _)| path);
+    let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    let game = |word: &str| word.parse::<GameId>().ok();
+    match (method, parts.as_slice()) {
+        (Method::Get, ["metrics"]) => Reply::Text(200, metrics(&server.stats())),
+        (Method::Get, ["games"]) => Reply::Json(
+            200,
+            server
+                .summaries()
+                .iter()
+                .map(|game| summary_json(game, false))
+                .collect(),
+        ),
+        (Method::Get, ["games", id]) if game(id).is_some() => {
+            let id = game(id).unwrap();
+            match server.summary(id) {
+                Some(game) => Reply::Json(200, summary_json(&game, true)),
+                None => failure(404, &format!("there's no game {}", id)),
+            }
+        }
+        (Method::Post, ["games", id, "abort"]) if game(id).is_some() => {
+            let id = game(id).unwrap();
+            if server.abort(id) {
+                Reply::Json(200, json!({ "aborted": id }))
+            } else {
+                failure(404, &format!("there's no game {}", id))
+            }
+        }
+        _ => failure(404, &format!("no such endpoint: {} {}", method, path)),
+    }
+}
+
+impl Server {
+    /// Serves the admin endpoint on `listener`. Only listen on localhost:
+    /// anyone who can reach it can stop games.
+    pub fn serve_admin(&self, listener: TcpListener) -> io::Result<()> {
+        let http = tiny_http::Server::from_listener(listener, None).map_err(io::Error::other)?;
+        for request in http.incoming_requests() {
+            let server = self.clone();
+            thread::spawn(move || server.answer_admin(request));
+        }
+        Ok(())
+    }
+
+    fn answer_admin(&self, request: Request) -> io::Result<()> {
+        let (status, body, content_type) = match route(self, request.method(), request.url()) {
+            Reply::Json(status, value) => (status, value.to_string(), "application/json"),
+            Reply::Text(status, text) => (status, text, "text/plain; version=0.0.4"),
+            Reply::Stream(_) => unreachable!("nothing on the admin endpoint streams"),
+        };
+        request.respond(
+            Response::from_string(body)
+                .with_status_code(status)
+                .with_header(header("Content-Type", content_type)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::Location,
+        net::{
+            protocol::{ClientMessage, ServerMessage},
+            server::ClientId,
+        },
+    };
+    use std::sync::mpsc::Receiver;
+
+    fn seated(server: &Server, name: &str) -> (ClientId, Receiver<ServerMessage>) {
+        let (client, messages) = server.connect();
+        let name = name.to_string();
+        server.handle(client, ClientMessage::Register { name });
+        server.handle(client, ClientMessage::QuickMatch { computer: false });
+        (client, messages)
+    }
+
+    #[test]
+    fn metrics_count_games_clients_and_moves() {
+        let server = Server::new();
+        let (a, _) = seated(&server, "alice");
+        seated(&server, "bob");
+        seated(&server, "carol");
+        server.handle(
+            a,
+            ClientMessage::Move {
+                at: Location::new(0, 0, 0),
+                seq: None,
+            },
+        );
+        let Reply::Text(200, text) = route(&server, &Method::Get, "/metrics") else {
+            panic!("no metrics");
+        };
+        for line in [
+            r#"tttt_games{state="open"} 1"#,
+            r#"tttt_games{state="running"} 1"#,
+            "tttt_clients 3",
+            "tttt_moves_total 1",
+            "tttt_moves_per_minute 1",
+            "tttt_computer_think_seconds_count 0",
+            "# TYPE tttt_moves_total counter",
+        ] {
+            assert!(text.lines().any(|l| l == line), "no {} in\n{}", line, text);
+        }
+    }
+
+    #[test]
+    fn games_are_listed_inspected_and_aborted() {
+        let server = Server::new();
+        let (_, a) = seated(&server, "alice");
+        seated(&server, "bob");
+        let Reply::Json(200, games) = route(&server, &Method::Get, "/games") else {
+            panic!("no games");
+        };
+        assert_eq!(games[0]["players"], json!({ "A": "alice", "B": "bob" }));
+        assert_eq!(games[0]["turn"], 0);
+        assert!(games[0].get("state").is_none());
+        let Reply::Json(200, game) = route(&server, &Method::Get, "/games/1") else {
+            panic!("game 1 wasn't found");
+        };
+        assert_eq!(game["moves"], json!([]));
+
+        assert_eq!(
+            route(&server, &Method::Post, "/games/1/abort"),
+            Reply::Json(200, json!({ "aborted": 1 }))
+        );
+        let told = a.try_iter().last();
+        let message = "game 1 was stopped by the server".to_string();
+        assert_eq!(told, Some(ServerMessage::Error { message }));
+        assert_eq!(server.game(1), None);
+        assert_eq!(
+            route(&server, &Method::Post, "/games/1/abort"),
+            failure(404, "there's no game 1")
+        );
+        assert_eq!(
+            route(&server, &Method::Get, "/games/1"),
+            failure(404, "there's no game 1")
+        );
+    }
+}
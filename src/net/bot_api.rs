@@ -0,0 +1,563 @@
+//! An HTTP API for bots, served on localhost. A bot registers a name once,
+//! keeps the token it's given, and sends it with every request that takes a
+//! seat or plays a move. It then polls or streams its games:
+//!
+//! | request | does |
+//! | --- | --- |
+//! | `POST /register` | claims the name in the body, answering `{"name": "bot", "token": "..."}` |
+//! | `GET /games` | `{"open": [1], "running": [2]}` |
+//! | `POST /games` | starts a game, answering `{"game": 1, "player": "A"}` |
+//! | `POST /games/1/join` | takes the free seat in game 1, answering the same |
+//! | `POST /quickmatch` | joins the game waiting longest, or starts one |
+//! | `GET /games/1` | `{"game": 1, "state": {...}, "moves": [...]}`, or with `?format=notation` just the position |
+//! | `GET /players/alice/games` | every game alice has played that the server kept, with who played it and when |
+//! | `GET /games/1/stream` | every update to game 1 as a line of JSON, as the WebSocket sends them |
+//! | `POST /games/1/move` | plays the body, `b32` or `{"x": 1, "y": 2, "z": 1}`, with an optional move number |
+//! | `POST /games/1/resign` | gives up game 1 |
+//! | `POST /games/1/offer` | offers the other player a `draw` or asks for a `takeback`, as the body says |
+//! | `POST /games/1/accept` | accepts the other player's offer; `/decline` turns it down |
+//!
+//! The token goes in an `Authorization: Bearer <token>` header. Refused moves get
+//! `{"error": "..."}` with a status saying why: 409 when the game isn't in a
+//! state to take the move and 422 when the spot is taken. Offers and answers
+//! are refused the same way.
+
+use super::{
+    protocol::{letter, ClientMessage, GameId, ServerMessage},
+    server::{ClientId, GameAction, MoveError, Server},
+    store::GameRecord,
+};
+use crate::{
+    common::{Location, ParseLocationError},
+    logic::{GamePlayStatus, Offer, PlaceErr, TTTTState},
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    io::{self, Write},
+    net::TcpListener,
+    sync::mpsc::Receiver,
+    thread,
+};
+use tiny_http::{Header, Method, Request, Response};
+
+/// The default port, only ever on localhost.
+pub const BOT_API_PORT: u16 = 7881;
+
+/// What a request gets back.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Reply {
+    Json(u16, Value),
+    Text(u16, String),
+    /// Updates to a game, for as long as it lasts.
+    Stream(GameId),
+}
+
+pub(super) fn failure(status: u16, message: &str) -> Reply {
+    Reply::Json(status, json!({ "error": message }))
+}
+
+fn status_of(e: &MoveError) -> u16 {
+    match e {
+        MoveError::NotJoined | MoveError::Watching | MoveError::NotYourSeat => 403,
+        MoveError::Place(PlaceErr::Occupied) => 422,
+        MoveError::Place(PlaceErr::OffBoard) => 400,
+        MoveError::Place(
+            PlaceErr::GameOver | PlaceErr::OutOfTime | PlaceErr::NothingToTakeBack,
+        )
+        | MoveError::WaitingForOpponent
+        | MoveError::AlreadyPlayed(_)
+        | MoveError::Ahead { .. }
+        | MoveError::NotYourTurn
+        | MoveError::OfferPending
+        | MoveError::NoOffer
+        | MoveError::OwnOffer => 409,
+    }
+}
+
+/// A move as posted, in JSON.
+#[derive(Deserialize)]
+struct MoveRequest {
+    #[serde(flatten)]
+    at: Location,
+    seq: Option<usize>,
+}
+
+/// Reads a posted move, either `b32 [seq]` or JSON.
+fn parse_move(body: &str) -> Result<(Location, Option<usize>), String> {
+    if body.trim_start().starts_with('{') {
+        let request: MoveRequest = serde_json::from_str(body).map_err(|e| e.to_string())?;
+        return Ok((request.at, request.seq));
+    }
+    let mut words = body.split_whitespace();
+    let at = words
+        .next()
+        .ok_or("missing move")?
+        .parse()
+        .map_err(|e: ParseLocationError| e.to_string())?;
+    let seq = match words.next() {
+        Some(seq) => Some(
+            seq.parse()
+                .map_err(|_| format!("bad move number: {}", seq))?,
+        ),
+        None => None,
+    };
+    match words.next() {
+        Some(word) => Err(format!("unexpected {}", word)),
+        None => Ok((at, seq)),
+    }
+}
+
+fn game_json(id: GameId, state: &TTTTState, moves: Vec<Location>) -> Value {
+    json!({ "game": id, "state": state, "moves": moves })
+}
+
+fn record_json(record: &GameRecord) -> Value {
+    let mut game = game_json(record.id, &record.state, record.moves.clone());
+    let players: serde_json::Map<String, Value> = record
+        .players
+        .iter()
+        .map(|(player, name)| (letter(*player).to_string(), json!(name)))
+        .collect();
+    game["players"] = Value::Object(players);
+    game["abandoned"] = json!(record.abandoned);
+    game["chat"] = record
+        .chat
+        .iter()
+        .map(|line| json!({ "from": line.from, "text": line.text, "sent_at": line.sent_at }))
+        .collect();
+    game["started_at"] = json!(record.started_at);
+    game["updated_at"] = json!(record.updated_at);
+    game
+}
+
+fn no_token() -> Reply {
+    failure(
+        401,
+        "send your token in an Authorization: Bearer <token> header",
+    )
+}
+
+fn register(server: &Server, body: &str) -> Reply {
+    let (client, messages) = server.connect();
+    server.handle(
+        client,
+        ClientMessage::Register {
+            name: body.trim().to_string(),
+        },
+    );
+    server.disconnect(client);
+    match messages.try_recv() {
+        Ok(ServerMessage::Registered { name, token }) => {
+            Reply::Json(201, json!({ "name": name, "token": token }))
+        }
+        Ok(ServerMessage::Error { message }) if message.ends_with("is taken") => {
+            failure(409, &message)
+        }
+        Ok(ServerMessage::Error { message }) => failure(400, &message),
+        _ => unreachable!("registering is answered straight away"),
+    }
+}
+
+/// Asks for a seat as a new client logged in with `token`, answering with
+/// the welcome or the error.
+fn take_seat(server: &Server, token: Option<&str>, request: ClientMessage, created: bool) -> Reply {
+    let Some(token) = token else {
+        return no_token();
+    };
+    let (client, messages) = server.connect();
+    server.handle(
+        client,
+        ClientMessage::Login {
+            token: token.to_string(),
+        },
+    );
+    if !matches!(messages.try_recv(), Ok(ServerMessage::LoggedIn { .. })) {
+        server.disconnect(client);
+        return failure(401, "that token isn't anyone's");
+    }
+    server.handle(client, request);
+    match messages.try_recv() {
+        Ok(ServerMessage::Welcome { game, player, .. }) => {
+            server.detach(client);
+            Reply::Json(
+                if created { 201 } else { 200 },
+                json!({ "game": game, "player": player }),
+            )
+        }
+        Ok(ServerMessage::Error { message }) => {
+            server.disconnect(client);
+            let status = if message.starts_with("there's no game") {
+                404
+            } else {
+                409
+            };
+            failure(status, &message)
+        }
+        _ => unreachable!("seats are answered straight away"),
+    }
+}
+
+fn show_game(server: &Server, id: GameId, notation: bool) -> Reply {
+    let (Some(state), Some(moves)) = (server.game(id), server.moves(id)) else {
+        // Games that are over live on in the store, if there is one.
+        return match server.saved_game(id) {
+            Some(record) if notation => Reply::Text(200, record.state.to_notation()),
+            Some(record) => Reply::Json(200, record_json(&record)),
+            None => failure(404, &format!("there's no game {}", id)),
+        };
+    };
+    game_reply(id, &state, moves, notation)
+}
+
+fn game_reply(id: GameId, state: &TTTTState, moves: Vec<Location>, notation: bool) -> Reply {
+    if notation {
+        Reply::Text(200, state.to_notation())
+    } else {
+        Reply::Json(200, game_json(id, state, moves))
+    }
+}
+
+/// The connection the player with `token` holds their seat in game `id`
+/// through.
+fn seat(server: &Server, id: GameId, token: Option<&str>) -> Result<ClientId, Reply> {
+    let Some(name) = token.and_then(|token| server.account(token)) else {
+        return Err(no_token());
+    };
+    if let Some(client) = server.seat_of(&name, id) {
+        return Ok(client);
+    }
+    // Seats are given up when the game's over, but it's still theirs.
+    let played = server.saved_game(id).is_some_and(|record| {
+        record.players.iter().any(|(_, n)| *n == name)
+            && !matches!(record.state.status, GamePlayStatus::Playing(_))
+    });
+    Err(if played {
+        failure(409, "the game is over")
+    } else {
+        failure(403, &format!("{} isn't playing in game {}", name, id))
+    })
+}
+
+fn post_move(
+    server: &Server,
+    id: GameId,
+    token: Option<&str>,
+    body: &str,
+    notation: bool,
+) -> Reply {
+    let client = match seat(server, id, token) {
+        Ok(client) => client,
+        Err(reply) => return reply,
+    };
+    let (at, seq) = match parse_move(body) {
+        Ok(request) => request,
+        Err(e) => return failure(400, &e),
+    };
+    match server.submit_move(client, at, seq) {
+        Ok((state, moves)) => game_reply(id, &state, moves, notation),
+        Err(e) => failure(status_of(&e), &e.to_string()),
+    }
+}
+
+fn post_action(
+    server: &Server,
+    id: GameId,
+    token: Option<&str>,
+    action: GameAction,
+    notation: bool,
+) -> Reply {
+    let client = match seat(server, id, token) {
+        Ok(client) => client,
+        Err(reply) => return reply,
+    };
+    match server.submit_action(client, action) {
+        Ok((state, moves)) => game_reply(id, &state, moves, notation),
+        Err(e) => failure(status_of(&e), &e.to_string()),
+    }
+}
+
+fn route(server: &Server, method: &Method, url: &str, token: Option<&str>, body: &str) -> Reply {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let notation = query.split('&').any(|pair| pair == "format=notation");
+    let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    let game = |word: &str| word.parse::<GameId>().ok();
+    match (method, parts.as_slice()) {
+        (Method::Get, ["games"]) => {
+            let (open, running) = server.games();
+            Reply::Json(200, json!({ "open": open, "running": running }))
+        }
+        (Method::Post, ["register"]) => register(server, body),
+        (Method::Post, ["games"]) => take_seat(server, token, ClientMessage::Create, true),
+        (Method::Post, ["quickmatch"]) => {
+            let computer = query.split('&').any(|pair| pair == "computer=true");
+            take_seat(server, token, ClientMessage::QuickMatch { computer }, false)
+        }
+        (Method::Post, ["games", id, "join"]) if game(id).is_some() => {
+            let game = game(id).unwrap();
+            take_seat(server, token, ClientMessage::Join { game }, false)
+        }
+        (Method::Get, ["games", id]) if game(id).is_some() => {
+            show_game(server, game(id).unwrap(), notation)
+        }
+        (Method::Get, ["games", id, "stream"]) if game(id).is_some() => {
+            let id = game(id).unwrap();
+            match server.game(id) {
+                Some(_) => Reply::Stream(id),
+                None => failure(404, &format!("there's no game {}", id)),
+            }
+        }
+        (Method::Post, ["games", id, "move"]) if game(id).is_some() => {
+            post_move(server, game(id).unwrap(), token, body, notation)
+        }
+        (Method::Post, ["games", id, action]) if game(id).is_some() => {
+            let action = match *action {
+                "resign" => GameAction::Resign,
+                "accept" => GameAction::Answer { accept: true },
+                "decline" => GameAction::Answer { accept: false },
+                "offer" => match Offer::from_word(body.trim()) {
+                    Some(offer) => GameAction::Offer(offer),
+                    None => return failure(400, "offer a draw or a takeback"),
+                },
+                _ => return failure(404, &format!("no such endpoint: {} {}", method, path)),
+            };
+            post_action(server, game(id).unwrap(), token, action, notation)
+        }
+        (Method::Get, ["players", name, "games"]) => match server.games_of(name) {
+            Some(Ok(games)) => Reply::Json(200, games.iter().map(record_json).collect()),
+            Some(Err(e)) => failure(500, &e.to_string()),
+            None => failure(404, "this server doesn't keep games"),
+        },
+        _ => failure(404, &format!("no such endpoint: {} {}", method, path)),
+    }
+}
+
+pub(super) fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("headers are ASCII")
+}
+
+impl Server {
+    /// Serves the bot API on `listener`, answering each request on its own
+    /// thread. Only listen on localhost: anyone who can reach the API can
+    /// start games.
+    pub fn serve_bot_api(&self, listener: TcpListener) -> io::Result<()> {
+        let http = tiny_http::Server::from_listener(listener, None).map_err(io::Error::other)?;
+        for request in http.incoming_requests() {
+            let server = self.clone();
+            thread::spawn(move || server.answer(request));
+        }
+        Ok(())
+    }
+
+    fn answer(&self, mut request: Request) -> io::Result<()> {
+        let mut body = String::new();
+        if request.as_reader().read_to_string(&mut body).is_err() {
+            return request.respond(Response::empty(400));
+        }
+        let token = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Authorization"))
+            .and_then(|h| h.value.as_str().strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
+        let reply = route(
+            self,
+            request.method(),
+            request.url(),
+            token.as_deref(),
+            &body,
+        );
+        match reply {
+            Reply::Json(status, value) => request.respond(
+                Response::from_string(value.to_string())
+                    .with_status_code(status)
+                    .with_header(header("Content-Type", "application/json")),
+            ),
+            Reply::Text(status, text) => request.respond(
+                Response::from_string(text)
+                    .with_status_code(status)
+                    .with_header(header("Content-Type", "text/plain")),
+            ),
+            Reply::Stream(game) => {
+                let (client, messages) = self.connect();
+                self.handle(client, ClientMessage::Watch { game });
+                let streamed = stream(request.into_writer(), messages);
+                self.disconnect(client);
+                streamed
+            }
+        }
+    }
+}
+
+/// Writes each message for a spectator as a line of JSON as soon as it
+/// arrives, until the game ends or the bot hangs up. tiny_http buffers
+/// responses it writes itself, so this writes the response by hand, ended
+/// by closing the connection.
+fn stream(mut writer: Box<dyn Write + Send>, messages: Receiver<ServerMessage>) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nConnection: close\r\n\r\n"
+    )?;
+    writer.flush()?;
+    for message in messages {
+        let json = serde_json::to_string(&message).expect("messages always serialize");
+        writeln!(writer, "{}", json)?;
+        writer.flush()?;
+        if matches!(
+            message,
+            ServerMessage::GameOver { .. } | ServerMessage::Error { .. }
+        ) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(server: &Server, url: &str, token: Option<&str>, body: &str) -> Reply {
+        route(server, &Method::Post, url, token, body)
+    }
+
+    fn get(server: &Server, url: &str) -> Reply {
+        route(server, &Method::Get, url, None, "")
+    }
+
+    fn register_as(server: &Server, name: &str) -> String {
+        let Reply::Json(201, account) = post(server, "/register", None, name) else {
+            panic!("{} wasn't registered", name);
+        };
+        account["token"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn names_are_registered_once() {
+        let server = Server::new();
+        register_as(&server, "deep-blue");
+        assert_eq!(
+            post(&server, "/register", None, "deep-blue"),
+            failure(409, "deep-blue is taken")
+        );
+        assert!(matches!(
+            post(&server, "/register", None, "deep blue"),
+            Reply::Json(400, _)
+        ));
+    }
+
+    #[test]
+    fn bots_take_seats_and_list_games() {
+        let server = Server::new();
+        let (a, b) = (register_as(&server, "a"), register_as(&server, "b"));
+        assert_eq!(post(&server, "/games", None, ""), no_token());
+        let Reply::Json(201, welcome) = post(&server, "/games", Some(&a), "") else {
+            panic!("the game wasn't created");
+        };
+        assert_eq!(welcome, json!({ "game": 1, "player": "A" }));
+        assert_eq!(
+            get(&server, "/games"),
+            Reply::Json(200, json!({ "open": [1], "running": [] }))
+        );
+        assert_eq!(
+            post(&server, "/games/1/join", Some(&a), ""),
+            failure(409, "you're already playing in game 1")
+        );
+        let Reply::Json(200, welcome) = post(&server, "/games/1/join", Some(&b), "") else {
+            panic!("the game wasn't joined");
+        };
+        assert_eq!(welcome["player"], "B");
+        assert_eq!(
+            post(&server, "/games/1/join", Some(&b), ""),
+            failure(409, "game 1 is full")
+        );
+        assert_eq!(
+            post(&server, "/games/8/join", Some(&b), ""),
+            failure(404, "there's no game 8")
+        );
+    }
+
+    #[test]
+    fn moves_are_played_and_refusals_explained() {
+        let server = Server::new();
+        let (a, b) = (register_as(&server, "a"), register_as(&server, "b"));
+        post(&server, "/quickmatch", Some(&a), "");
+        post(&server, "/quickmatch", Some(&b), "");
+
+        assert_eq!(post(&server, "/games/1/move", None, "a11"), no_token());
+        assert_eq!(
+            post(
+                &server,
+                "/games/1/move",
+                Some(&register_as(&server, "c")),
+                "a11"
+            ),
+            failure(403, "c isn't playing in game 1")
+        );
+        assert_eq!(
+            post(&server, "/games/1/move", Some(&b), "a11"),
+            failure(409, "it isn't your turn")
+        );
+        assert!(matches!(
+            post(&server, "/games/1/move", Some(&a), "e11"),
+            Reply::Json(400, _)
+        ));
+        let Reply::Json(200, game) = post(&server, "/games/1/move", Some(&a), "a11 0") else {
+            panic!("the move wasn't played");
+        };
+        assert_eq!(game["moves"], json!([{ "x": 0, "y": 0, "z": 0 }]));
+        assert_eq!(
+            post(
+                &server,
+                "/games/1/move",
+                Some(&b),
+                r#"{"x": 0, "y": 0, "z": 0}"#
+            ),
+            failure(422, "that spot is taken")
+        );
+        assert_eq!(
+            post(&server, "/games/1/move", Some(&a), "b11 0"),
+            failure(409, "move 0 has already been played")
+        );
+        assert_eq!(
+            get(&server, "/games/1?format=notation"),
+            Reply::Text(200, server.game(1).unwrap().to_notation())
+        );
+    }
+
+    #[test]
+    fn bots_offer_draws_and_resign() {
+        let server = Server::new();
+        let (a, b) = (register_as(&server, "a"), register_as(&server, "b"));
+        post(&server, "/quickmatch", Some(&a), "");
+        post(&server, "/quickmatch", Some(&b), "");
+
+        assert!(matches!(
+            post(&server, "/games/1/offer", Some(&a), "tea"),
+            Reply::Json(400, _)
+        ));
+        assert_eq!(
+            post(&server, "/games/1/accept", Some(&b), ""),
+            failure(409, "there's no offer to answer")
+        );
+        assert!(matches!(
+            post(&server, "/games/1/offer", Some(&a), "draw"),
+            Reply::Json(200, _)
+        ));
+        let Reply::Json(200, game) = post(&server, "/games/1/decline", Some(&b), "") else {
+            panic!("the offer wasn't declined");
+        };
+        assert_eq!(game["state"]["status"], json!({ "Playing": "A" }));
+        let Reply::Json(200, game) = post(&server, "/games/1/resign", Some(&b), "") else {
+            panic!("b didn't resign");
+        };
+        assert_eq!(
+            game["state"]["status"],
+            json!({ "Win": { "player": "A", "reason": "Resignation" } })
+        );
+        assert_eq!(
+            post(&server, "/games/1/dance", Some(&a), ""),
+            failure(404, "no such endpoint: POST /games/1/dance")
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! The client side of a TCP connection to the game server.
+
+use super::protocol::{ClientMessage, ServerMessage};
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+pub struct Connection {
+    stream: TcpStream,
+    messages: Receiver<ServerMessage>,
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(ErrorKind::UnexpectedEof, "the server closed the connection")
+}
+
+impl Connection {
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else { break };
+                if let Ok(message) = line.parse() {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(Self { stream, messages })
+    }
+
+    pub fn send(&mut self, message: &ClientMessage) -> io::Result<()> {
+        writeln!(self.stream, "{}", message)
+    }
+
+    /// The next message if one has arrived, without waiting.
+    pub fn try_recv(&self) -> io::Result<Option<ServerMessage>> {
+        match self.messages.try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(disconnected()),
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> io::Result<ServerMessage> {
+        self.messages.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => io::Error::new(ErrorKind::TimedOut, "no message"),
+            RecvTimeoutError::Disconnected => disconnected(),
+        })
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.stream.shutdown(Shutdown::Both).ok();
+    }
+}
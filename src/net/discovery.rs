@@ -0,0 +1,159 @@
+//! Finding servers on the local network without typing addresses. A client
+//! sends a probe over UDP, usually to the broadcast address, and every
+//! server that hears it answers with its TCP port and open games:
+//!
+//! ```text
+//! > tttt discover
+//! < tttt host 7878 open 1 4
+//! ```
+//!
+//! The server's address is wherever the answer came from. On a single
+//! machine, probing `127.0.0.1` instead of broadcasting finds local servers.
+
+use super::protocol::GameId;
+use crate::engine::ParseMessageError;
+use std::{
+    fmt::Display,
+    io::{self, ErrorKind},
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// The default UDP port servers listen for probes on.
+pub const DISCOVERY_PORT: u16 = 7880;
+
+const PROBE: &str = "tttt discover";
+
+/// Where probes go by default: every machine on the local network.
+pub fn broadcast() -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::BROADCAST, DISCOVERY_PORT))
+}
+
+/// A server's answer to a probe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Announcement {
+    /// The TCP port games are played on.
+    pub port: u16,
+    pub open: Vec<GameId>,
+}
+
+impl Display for Announcement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "tttt host {} open", self.port)?;
+        for game in &self.open {
+            write!(f, " {}", game)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Announcement {
+    type Err = ParseMessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || ParseMessageError(format!("bad announcement: {}", s));
+        let rest = s.trim().strip_prefix("tttt host ").ok_or_else(bad)?;
+        let (port, open) = rest.split_once(" open").ok_or_else(bad)?;
+        Ok(Self {
+            port: port.parse().map_err(|_| bad())?,
+            open: open
+                .split_whitespace()
+                .map(|game| game.parse().map_err(|_| bad()))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// A server found on the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    /// Where to connect over TCP.
+    pub addr: SocketAddr,
+    pub open: Vec<GameId>,
+}
+
+/// Answers probes arriving on `socket` with whatever `announce` says at the
+/// time, until the socket fails.
+pub fn answer_probes(socket: UdpSocket, announce: impl Fn() -> Announcement) -> io::Result<()> {
+    let mut buf = [0; 64];
+    loop {
+        let (len, from) = socket.recv_from(&mut buf)?;
+        if buf[..len] == *PROBE.as_bytes() {
+            socket.send_to(announce().to_string().as_bytes(), from)?;
+        }
+    }
+}
+
+/// Probes each of `targets` and collects the servers that answer within
+/// `wait`, in the order they answered.
+pub fn discover(targets: &[SocketAddr], wait: Duration) -> io::Result<Vec<Host>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+    for target in targets {
+        socket.send_to(PROBE.as_bytes(), target)?;
+    }
+    let deadline = Instant::now() + wait;
+    let mut hosts: Vec<Host> = vec![];
+    let mut buf = [0; 1024];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(hosts);
+        }
+        socket.set_read_timeout(Some(left))?;
+        let (len, from) = match socket.recv_from(&mut buf) {
+            Ok(answer) => answer,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(hosts)
+            }
+            Err(e) => return Err(e),
+        };
+        let Ok(announcement) = String::from_utf8_lossy(&buf[..len]).parse::<Announcement>() else {
+            continue;
+        };
+        let addr = SocketAddr::new(from.ip(), announcement.port);
+        if hosts.iter().all(|host| host.addr != addr) {
+            hosts.push(Host {
+                addr,
+                open: announcement.open,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn announcements_round_trip_through_text() {
+        for open in [vec![], vec![1, 4]] {
+            let announcement = Announcement { port: 7878, open };
+            assert_eq!(announcement.to_string().parse(), Ok(announcement));
+        }
+        assert!("tttt host open".parse::<Announcement>().is_err());
+        assert!("hello".parse::<Announcement>().is_err());
+    }
+
+    #[test]
+    fn servers_answering_on_loopback_are_found() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            answer_probes(socket, || Announcement {
+                port: 9000,
+                open: vec![2],
+            })
+        });
+        let hosts = discover(&[target], Duration::from_millis(500)).unwrap();
+        assert_eq!(
+            hosts,
+            vec![Host {
+                addr: "127.0.0.1:9000".parse().unwrap(),
+                open: vec![2],
+            }]
+        );
+    }
+}
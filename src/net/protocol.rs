@@ -0,0 +1,666 @@
+//! The messages between game clients and the server. Over TCP each one is
+//! a line of text; over WebSockets each is a JSON text message with a
+//! `type` field (see the README for examples):
+//!
+//! ```text
+//! > register alice
+//! < registered alice 0b5e7c1a93d2f486
+//! > quickmatch
+//! < welcome 1 A 5f0c9e21d4a8b736
+//! < state ................/................/................/................ A
+//! > move b32 0
+//! < state ................/.........A....../................/................ B
+//! > move b32 1
+//! < error it isn't your turn
+//! ```
+//!
+//! The number after a move is optional: it's how many moves the client
+//! thinks have been played, so a move sent twice, or sent for a position
+//! that has moved on, is refused rather than played. The last word of
+//! `welcome` is a session token. A client that loses its connection can
+//! send `resume <token>` within the server's grace period to get its seat
+//! back, and is sent `history` with every move so far and then the `state`,
+//! as spectators are when they start watching.
+//!
+//! Players have to say who they are before taking a seat: `register <name>`
+//! the first time, which answers with a token to keep, and `login <token>`
+//! on every connection after that. Listing and watching games don't need
+//! it.
+//!
+//! Games can also be started with `create`, found with `list` (answered by
+//! `games open 1 4 running 2`) and joined with `join <id>`, or watched
+//! without playing with `watch <id>`. `quickmatch ai` lets the server bring
+//! in the computer when no one else turns up. The server answers a finished
+//! game with `gameover A`, `gameover B` or `gameover draw` after the final
+//! `state`: `gameover A` for a line, `gameover A resignation` or
+//! `gameover A timeout` when A won off the board, and `gameover draw
+//! agreement` for an agreed draw.
+//!
+//! A player can `resign` at any time, or `offer draw` or `offer takeback`
+//! to take back their last move. The server tells everyone in the game
+//! `offered draw A`, and the other player answers with `accept` or
+//! `decline`. A declined offer is passed on as `declined draw B`; an
+//! accepted takeback is followed by `history` and the new `state`. Playing
+//! a move withdraws any offer.
+//!
+//! On a server playing with a clock, every `state` is followed by
+//! `clock 180+2 175250 180000`: the time control, then A's and B's time
+//! left in milliseconds, with the time already spent on the current move
+//! taken off.
+//!
+//! Everyone in a game, players and spectators alike, can talk with
+//! `chat <text>`, which the server passes on as `chat <name> <text>` once
+//! they've logged in. It's also how the chat so far is replayed to anyone
+//! resuming or starting to watch.
+
+use crate::{
+    common::Location,
+    engine::ParseMessageError,
+    logic::{
+        clock::{Clock, TimeControl},
+        notation, DrawReason, GamePlayStatus, Offer, Player, TTTTState, WinReason,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+pub type GameId = u32;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Claims a name for this player, and logs in as them.
+    Register {
+        name: String,
+    },
+    /// Logs in with the token given when registering.
+    Login {
+        token: String,
+    },
+    /// Starts a new game and takes its first seat.
+    Create,
+    /// Asks which games are waiting for a player or being played.
+    List,
+    Join {
+        game: GameId,
+    },
+    /// Joins whichever game has been waiting longest, or starts one. With
+    /// `computer`, the computer takes the other seat if no one turns up.
+    QuickMatch {
+        computer: bool,
+    },
+    /// Follows a game without playing in it.
+    Watch {
+        game: GameId,
+    },
+    /// Takes the seat held for a client that lost its connection.
+    Resume {
+        token: String,
+    },
+    Move {
+        #[serde(flatten)]
+        at: Location,
+        /// How many moves had been played when this one was chosen.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<usize>,
+    },
+    /// Says something to everyone in the game.
+    Chat {
+        text: String,
+    },
+    /// Gives up the game.
+    Resign,
+    /// Asks the other player for something, which they accept or decline.
+    Offer {
+        offer: Offer,
+    },
+    /// Agrees to the other player's offer.
+    Accept,
+    Decline,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// The token to log in as `name` with from now on.
+    Registered {
+        name: String,
+        token: String,
+    },
+    LoggedIn {
+        name: String,
+    },
+    /// The game and seat this client was given, and the token to get the
+    /// seat back with after losing the connection.
+    Welcome {
+        game: GameId,
+        player: Player,
+        token: String,
+    },
+    /// Every move played so far, in order.
+    History {
+        moves: Vec<Location>,
+    },
+    /// The games waiting for a player, and those being played.
+    Games {
+        open: Vec<GameId>,
+        running: Vec<GameId>,
+    },
+    /// This client is now following the game as a spectator.
+    Watching {
+        game: GameId,
+    },
+    State(TTTTState),
+    /// Both players' time, as of now.
+    Clock(Clock),
+    Error {
+        message: String,
+    },
+    GameOver {
+        status: GamePlayStatus,
+    },
+    /// Something `from` said in the game.
+    Chat {
+        from: String,
+        text: String,
+    },
+    /// `by` asked the other player for `offer`.
+    Offered {
+        offer: Offer,
+        by: Player,
+    },
+    /// `by` turned down the other player's `offer`.
+    Declined {
+        offer: Offer,
+        by: Player,
+    },
+}
+
+pub(super) use notation::letter;
+
+fn parse_offer(word: Option<&str>) -> Result<Offer, ParseMessageError> {
+    let word = word.ok_or_else(|| ParseMessageError("missing offer".to_string()))?;
+    Offer::from_word(word).ok_or_else(|| ParseMessageError(format!("bad offer: {}", word)))
+}
+
+fn parse_player(word: &str) -> Result<Player, ParseMessageError> {
+    match word {
+        "A" | "a" => Ok(Player::A),
+        "B" | "b" => Ok(Player::B),
+        _ => Err(ParseMessageError(format!("bad player: {}", word))),
+    }
+}
+
+fn parse_location(word: &str) -> Result<Location, ParseMessageError> {
+    word.parse()
+        .map_err(|e| ParseMessageError(format!("{}", e)))
+}
+
+fn parse_word(word: Option<&str>, what: &str) -> Result<String, ParseMessageError> {
+    word.map(str::to_string)
+        .ok_or_else(|| ParseMessageError(format!("missing {}", what)))
+}
+
+fn parse_game(word: Option<&str>) -> Result<GameId, ParseMessageError> {
+    let word = word.ok_or_else(|| ParseMessageError("missing game".to_string()))?;
+    word.parse()
+        .map_err(|_| ParseMessageError(format!("bad game: {}", word)))
+}
+
+impl Display for ClientMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientMessage::Register { name } => write!(f, "register {}", name),
+            ClientMessage::Login { token } => write!(f, "login {}", token),
+            ClientMessage::Create => write!(f, "create"),
+            ClientMessage::List => write!(f, "list"),
+            ClientMessage::Join { game } => write!(f, "join {}", game),
+            ClientMessage::QuickMatch { computer: false } => write!(f, "quickmatch"),
+            ClientMessage::QuickMatch { computer: true } => write!(f, "quickmatch ai"),
+            ClientMessage::Watch { game } => write!(f, "watch {}", game),
+            ClientMessage::Resume { token } => write!(f, "resume {}", token),
+            ClientMessage::Move { at, seq: None } => write!(f, "move {}", at),
+            ClientMessage::Move { at, seq: Some(seq) } => write!(f, "move {} {}", at, seq),
+            ClientMessage::Chat { text } => write!(f, "chat {}", text),
+            ClientMessage::Resign => write!(f, "resign"),
+            ClientMessage::Offer { offer } => write!(f, "offer {}", offer.word()),
+            ClientMessage::Accept => write!(f, "accept"),
+            ClientMessage::Decline => write!(f, "decline"),
+        }
+    }
+}
+
+impl FromStr for ClientMessage {
+    type Err = ParseMessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Chat is the rest of the line, spaces and all.
+        if let Some(("chat", text)) = s.trim().split_once(' ') {
+            return Ok(ClientMessage::Chat {
+                text: text.trim().to_string(),
+            });
+        }
+        let mut words = s.split_whitespace().peekable();
+        let message = match words.next() {
+            Some("register") => ClientMessage::Register {
+                name: parse_word(words.next(), "name")?,
+            },
+            Some("login") => ClientMessage::Login {
+                token: parse_word(words.next(), "token")?,
+            },
+            Some("create") => ClientMessage::Create,
+            Some("list") => ClientMessage::List,
+            Some("join") => ClientMessage::Join {
+                game: parse_game(words.next())?,
+            },
+            Some("watch") => ClientMessage::Watch {
+                game: parse_game(words.next())?,
+            },
+            Some("quickmatch") => ClientMessage::QuickMatch {
+                computer: words.next_if_eq(&"ai").is_some(),
+            },
+            Some("move") => {
+                let loc = words
+                    .next()
+                    .ok_or_else(|| ParseMessageError("missing move".to_string()))?;
+                let at = parse_location(loc)?;
+                let seq = words
+                    .next()
+                    .map(|seq| {
+                        seq.parse()
+                            .map_err(|_| ParseMessageError(format!("bad move number: {}", seq)))
+                    })
+                    .transpose()?;
+                ClientMessage::Move { at, seq }
+            }
+            Some("resume") => ClientMessage::Resume {
+                token: parse_word(words.next(), "token")?,
+            },
+            Some("chat") => return Err(ParseMessageError("nothing to say".to_string())),
+            Some("resign") => ClientMessage::Resign,
+            Some("offer") => ClientMessage::Offer {
+                offer: parse_offer(words.next())?,
+            },
+            Some("accept") => ClientMessage::Accept,
+            Some("decline") => ClientMessage::Decline,
+            Some(word) => return Err(ParseMessageError(format!("unknown message {}", word))),
+            None => return Err(ParseMessageError("empty message".to_string())),
+        };
+        match words.next() {
+            Some(word) => Err(ParseMessageError(format!("unexpected {}", word))),
+            None => Ok(message),
+        }
+    }
+}
+
+impl Display for ServerMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerMessage::Registered { name, token } => {
+                write!(f, "registered {} {}", name, token)
+            }
+            ServerMessage::LoggedIn { name } => write!(f, "loggedin {}", name),
+            ServerMessage::Welcome {
+                game,
+                player,
+                token,
+            } => write!(f, "welcome {} {} {}", game, letter(*player), token),
+            ServerMessage::History { moves } => {
+                write!(f, "history")?;
+                for loc in moves {
+                    write!(f, " {}", loc)?;
+                }
+                Ok(())
+            }
+            ServerMessage::Games { open, running } => {
+                write!(f, "games open")?;
+                for game in open {
+                    write!(f, " {}", game)?;
+                }
+                write!(f, " running")?;
+                for game in running {
+                    write!(f, " {}", game)?;
+                }
+                Ok(())
+            }
+            ServerMessage::Watching { game } => write!(f, "watching {}", game),
+            ServerMessage::State(state) => write!(f, "state {}", state.to_notation()),
+            ServerMessage::Error { message } => write!(f, "error {}", message),
+            ServerMessage::Clock(clock) => write!(
+                f,
+                "clock {} {} {}",
+                clock.control,
+                clock.a.as_millis(),
+                clock.b.as_millis()
+            ),
+            ServerMessage::GameOver {
+                status:
+                    GamePlayStatus::Win {
+                        player,
+                        reason: WinReason::Line,
+                    },
+            } => write!(f, "gameover {}", letter(*player)),
+            ServerMessage::GameOver {
+                status: GamePlayStatus::Win { player, reason },
+            } => write!(f, "gameover {} {}", letter(*player), reason.word()),
+            ServerMessage::GameOver {
+                status:
+                    GamePlayStatus::Draw {
+                        reason: DrawReason::Agreement,
+                    },
+            } => write!(f, "gameover draw agreement"),
+            ServerMessage::GameOver { .. } => write!(f, "gameover draw"),
+            ServerMessage::Chat { from, text } => write!(f, "chat {} {}", from, text),
+            ServerMessage::Offered { offer, by } => {
+                write!(f, "offered {} {}", offer.word(), letter(*by))
+            }
+            ServerMessage::Declined { offer, by } => {
+                write!(f, "declined {} {}", offer.word(), letter(*by))
+            }
+        }
+    }
+}
+
+impl FromStr for ServerMessage {
+    type Err = ParseMessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, rest) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        match kind {
+            "welcome" => {
+                let mut words = rest.split_whitespace();
+                Ok(ServerMessage::Welcome {
+                    game: parse_game(words.next())?,
+                    player: parse_player(words.next().unwrap_or_default())?,
+                    token: parse_word(words.next(), "token")?,
+                })
+            }
+            "registered" => {
+                let mut words = rest.split_whitespace();
+                Ok(ServerMessage::Registered {
+                    name: parse_word(words.next(), "name")?,
+                    token: parse_word(words.next(), "token")?,
+                })
+            }
+            "loggedin" => Ok(ServerMessage::LoggedIn {
+                name: parse_word(rest.split_whitespace().next(), "name")?,
+            }),
+            "history" => Ok(ServerMessage::History {
+                moves: rest
+                    .split_whitespace()
+                    .map(parse_location)
+                    .collect::<Result<_, _>>()?,
+            }),
+            "games" => {
+                let ids = |list: &str| -> Result<Vec<GameId>, ParseMessageError> {
+                    list.split_whitespace()
+                        .map(|word| parse_game(Some(word)))
+                        .collect()
+                };
+                let (open, running) = rest
+                    .strip_prefix("open")
+                    .and_then(|rest| rest.split_once("running"))
+                    .ok_or_else(|| ParseMessageError(format!("bad game list: {}", rest)))?;
+                Ok(ServerMessage::Games {
+                    open: ids(open)?,
+                    running: ids(running)?,
+                })
+            }
+            "watching" => Ok(ServerMessage::Watching {
+                game: parse_game(Some(rest))?,
+            }),
+            "state" => TTTTState::from_notation(rest)
+                .map(ServerMessage::State)
+                .map_err(|e| ParseMessageError(e.to_string())),
+            "chat" => {
+                let (from, text) = rest
+                    .split_once(' ')
+                    .ok_or_else(|| ParseMessageError(format!("bad chat: {}", rest)))?;
+                Ok(ServerMessage::Chat {
+                    from: from.to_string(),
+                    text: text.trim().to_string(),
+                })
+            }
+            "clock" => {
+                let mut words = rest.split_whitespace();
+                let control: TimeControl = parse_word(words.next(), "time control")?.parse()?;
+                let mut left = || {
+                    let word = parse_word(words.next(), "time left")?;
+                    word.parse()
+                        .map(Duration::from_millis)
+                        .map_err(|_| ParseMessageError(format!("bad time left: {}", word)))
+                };
+                Ok(ServerMessage::Clock(Clock {
+                    control,
+                    a: left()?,
+                    b: left()?,
+                }))
+            }
+            "error" => Ok(ServerMessage::Error {
+                message: rest.to_string(),
+            }),
+            "gameover" => {
+                let mut words = rest.split_whitespace();
+                let status = match (words.next(), words.next()) {
+                    (Some("draw"), None) => GamePlayStatus::Draw {
+                        reason: DrawReason::FullBoard,
+                    },
+                    (Some("draw"), Some("agreement")) => GamePlayStatus::Draw {
+                        reason: DrawReason::Agreement,
+                    },
+                    (Some(player), reason) => GamePlayStatus::Win {
+                        player: parse_player(player)?,
+                        reason: match reason {
+                            None => WinReason::Line,
+                            Some(word) => WinReason::from_word(word).ok_or_else(|| {
+                                ParseMessageError(format!("bad reason: {}", word))
+                            })?,
+                        },
+                    },
+                    (None, _) => return Err(ParseMessageError("missing result".to_string())),
+                };
+                Ok(ServerMessage::GameOver { status })
+            }
+            "offered" | "declined" => {
+                let mut words = rest.split_whitespace();
+                let offer = parse_offer(words.next())?;
+                let by = parse_player(words.next().unwrap_or_default())?;
+                Ok(match kind {
+                    "offered" => ServerMessage::Offered { offer, by },
+                    _ => ServerMessage::Declined { offer, by },
+                })
+            }
+            _ => Err(ParseMessageError(format!("unknown message {}", s))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client_messages() -> [ClientMessage; 17] {
+        [
+            ClientMessage::Register {
+                name: "alice".to_string(),
+            },
+            ClientMessage::Login {
+                token: "0123abcd".to_string(),
+            },
+            ClientMessage::Create,
+            ClientMessage::List,
+            ClientMessage::Join { game: 12 },
+            ClientMessage::QuickMatch { computer: false },
+            ClientMessage::QuickMatch { computer: true },
+            ClientMessage::Watch { game: 2 },
+            ClientMessage::Resume {
+                token: "0123abcd".to_string(),
+            },
+            ClientMessage::Move {
+                at: "d41".parse().unwrap(),
+                seq: None,
+            },
+            ClientMessage::Move {
+                at: "a11".parse().unwrap(),
+                seq: Some(17),
+            },
+            ClientMessage::Chat {
+                text: "good game, well played".to_string(),
+            },
+            ClientMessage::Resign,
+            ClientMessage::Offer { offer: Offer::Draw },
+            ClientMessage::Offer {
+                offer: Offer::Takeback,
+            },
+            ClientMessage::Accept,
+            ClientMessage::Decline,
+        ]
+    }
+
+    fn server_messages(state: TTTTState) -> [ServerMessage; 18] {
+        [
+            ServerMessage::Registered {
+                name: "alice".to_string(),
+                token: "0123abcd".to_string(),
+            },
+            ServerMessage::LoggedIn {
+                name: "alice".to_string(),
+            },
+            ServerMessage::Welcome {
+                game: 3,
+                player: Player::B,
+                token: "0123abcd".to_string(),
+            },
+            ServerMessage::History { moves: vec![] },
+            ServerMessage::History {
+                moves: vec!["c23".parse().unwrap(), "a11".parse().unwrap()],
+            },
+            ServerMessage::Games {
+                open: vec![],
+                running: vec![],
+            },
+            ServerMessage::Games {
+                open: vec![1, 5],
+                running: vec![2],
+            },
+            ServerMessage::Watching { game: 2 },
+            ServerMessage::State(state),
+            ServerMessage::Error {
+                message: "it isn't your turn".to_string(),
+            },
+            ServerMessage::GameOver {
+                status: GamePlayStatus::line(Player::A),
+            },
+            ServerMessage::GameOver {
+                status: GamePlayStatus::Draw {
+                    reason: DrawReason::FullBoard,
+                },
+            },
+            ServerMessage::GameOver {
+                status: GamePlayStatus::Draw {
+                    reason: DrawReason::Agreement,
+                },
+            },
+            ServerMessage::GameOver {
+                status: GamePlayStatus::Win {
+                    player: Player::B,
+                    reason: WinReason::Timeout,
+                },
+            },
+            ServerMessage::Clock(Clock {
+                control: "180+2b".parse().unwrap(),
+                a: Duration::from_millis(175_250),
+                b: Duration::from_secs(180),
+            }),
+            ServerMessage::Chat {
+                from: "alice".to_string(),
+                text: "good game, well played".to_string(),
+            },
+            ServerMessage::Offered {
+                offer: Offer::Takeback,
+                by: Player::A,
+            },
+            ServerMessage::Declined {
+                offer: Offer::Draw,
+                by: Player::B,
+            },
+        ]
+    }
+
+    #[test]
+    fn messages_round_trip_through_text() {
+        for message in client_messages() {
+            assert_eq!(message.to_string().parse(), Ok(message));
+        }
+        let mut state = TTTTState::new();
+        state.play("c23".parse().unwrap()).unwrap();
+        for message in server_messages(state) {
+            assert_eq!(message.to_string().parse(), Ok(message));
+        }
+    }
+
+    #[test]
+    fn messages_round_trip_through_json() {
+        for message in client_messages() {
+            let json = serde_json::to_string(&message).unwrap();
+            assert_eq!(
+                serde_json::from_str::<ClientMessage>(&json).unwrap(),
+                message
+            );
+        }
+        for message in server_messages(TTTTState::new()) {
+            let json = serde_json::to_string(&message).unwrap();
+            assert_eq!(
+                serde_json::from_str::<ServerMessage>(&json).unwrap(),
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn json_messages_are_tagged_with_their_type() {
+        let json: ClientMessage =
+            serde_json::from_str(r#"{"type":"move","x":3,"y":0,"z":2}"#).unwrap();
+        assert_eq!(
+            json,
+            ClientMessage::Move {
+                at: Location::new(3, 0, 2),
+                seq: None
+            }
+        );
+        let json: ClientMessage =
+            serde_json::from_str(r#"{"type":"move","x":3,"y":0,"z":2,"seq":4}"#).unwrap();
+        assert_eq!(
+            json,
+            ClientMessage::Move {
+                at: Location::new(3, 0, 2),
+                seq: Some(4)
+            }
+        );
+        let welcome = ServerMessage::Welcome {
+            game: 7,
+            player: Player::A,
+            token: "0123abcd".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&welcome).unwrap(),
+            r#"{"type":"welcome","game":7,"player":"A","token":"0123abcd"}"#
+        );
+    }
+
+    #[test]
+    fn bad_messages_are_rejected() {
+        assert!("move".parse::<ClientMessage>().is_err());
+        assert!("move e11".parse::<ClientMessage>().is_err());
+        assert!("move a11 b22".parse::<ClientMessage>().is_err());
+        assert!("move a11 3 4".parse::<ClientMessage>().is_err());
+        assert!("resume".parse::<ClientMessage>().is_err());
+        assert!("register".parse::<ClientMessage>().is_err());
+        assert!("register bob smith".parse::<ClientMessage>().is_err());
+        assert!("join".parse::<ClientMessage>().is_err());
+        assert!("quickmatch now".parse::<ClientMessage>().is_err());
+        assert!("chat".parse::<ClientMessage>().is_err());
+        assert!("games 1 2".parse::<ServerMessage>().is_err());
+        assert!("chat alice".parse::<ServerMessage>().is_err());
+    }
+}
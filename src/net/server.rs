@@ -0,0 +1,1987 @@
+//! The authoritative game server. It runs any number of games at once;
+//! players log in, create or join games from the lobby, or ask for a quick
+//! match, and every accepted move is sent to everyone in that game, including
+//! anyone watching it. A player whose connection drops mid-game keeps their
+//! seat for a while, and can take it back with the token they were given.
+//! Everyone in a game can chat, a line at a time and not too often.
+//! Games can be played against the clock, which the server keeps: a player
+//! whose time runs out loses, whether or not they've sent a move. Players
+//! can resign, and offer a draw or ask to take back a move, which their
+//! opponent accepts or declines; the computer always gives a move back and
+//! never agrees a draw.
+//! With a `Store`, players and games are saved as they change, and a server
+//! started on the same database picks up the games that weren't over, holding
+//! their seats as if everyone had just lost their connection.
+//!
+//! The server itself doesn't care how messages travel: each connection gets
+//! a `ClientId` and a channel of messages to deliver, and hands what it
+//! reads to `Server::handle`. `serve_tcp` does that for TCP, and
+//! `serve_websocket` for WebSockets carrying JSON. `serve_discovery` lets
+//! clients on the local network find the server.
+
+use super::{
+    discovery::{self, Announcement},
+    protocol::{ClientMessage, GameId, ServerMessage},
+    store::{ChatLine, GameRecord, Store, StoreError},
+};
+use crate::{
+    common::Location,
+    logic::{
+        clock::{Clock, TimeControl},
+        computer_player::{self, search, CancelToken},
+        GamePlayStatus, Offer, PlaceErr, Player, TTTTState,
+    },
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt::Display,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tungstenite::Message;
+
+pub type ClientId = u64;
+
+/// The default TCP port.
+pub const PORT: u16 = 7878;
+
+/// The default WebSocket port.
+pub const WEBSOCKET_PORT: u16 = 7879;
+
+/// How often a WebSocket connection stops waiting for its client to check
+/// for messages to send it.
+const POLL: Duration = Duration::from_millis(20);
+
+/// The longest chat message, in characters.
+pub const CHAT_LENGTH: usize = 200;
+
+/// How many chat messages a player can send in `CHAT_WINDOW`.
+const CHAT_BURST: usize = 5;
+
+const CHAT_WINDOW: Duration = Duration::from_secs(10);
+
+/// Settings for a server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// How long a quick match waits for someone to join before bringing in
+    /// the computer, for players who asked for that.
+    pub match_timeout: Duration,
+    /// How many moves ahead the computer looks.
+    pub computer_depth: u8,
+    /// How long a player who loses their connection mid-game keeps their
+    /// seat.
+    pub reconnect_grace: Duration,
+    /// The clock every game is played with, if any.
+    pub clock: Option<TimeControl>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            match_timeout: Duration::from_secs(10),
+            computer_depth: 3,
+            reconnect_grace: Duration::from_secs(30),
+            clock: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Seat {
+    Human(ClientId),
+    Computer,
+}
+
+#[derive(Debug)]
+struct Game {
+    state: TTTTState,
+    seats: Vec<(Player, Seat)>,
+    /// Started by a quick match, so later quick matches can join it.
+    quick: bool,
+    spectators: Vec<ClientId>,
+    moves: Vec<Location>,
+    /// Who sits in each seat, which outlasts their connection.
+    names: Vec<(Player, String)>,
+    chat: Vec<ChatLine>,
+    /// When the player to move started thinking, or when both seats were
+    /// taken if no one's moved since.
+    turn_started: Instant,
+    /// An offer waiting for an answer, and who made it.
+    offer: Option<(Player, Offer)>,
+}
+
+impl Game {
+    fn new(quick: bool, clock: Option<TimeControl>) -> Self {
+        Self {
+            state: clock.map_or_else(TTTTState::new, TTTTState::with_clock),
+            seats: vec![],
+            quick,
+            spectators: vec![],
+            moves: vec![],
+            names: vec![],
+            chat: vec![],
+            turn_started: Instant::now(),
+            offer: None,
+        }
+    }
+
+    /// How long the player to move has been thinking. The clock only runs
+    /// once both seats are taken.
+    fn thought(&self) -> Duration {
+        if self.is_running() {
+            self.turn_started.elapsed()
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// When the player to move runs out of time, if the game's under way
+    /// with a clock.
+    fn flag_falls_at(&self) -> Option<Instant> {
+        let (Some(clock), GamePlayStatus::Playing(player)) = (self.state.clock, self.state.status)
+        else {
+            return None;
+        };
+        self.is_running()
+            .then(|| self.turn_started + clock.left(player))
+    }
+
+    /// Both players' time as of now, in a game with a clock.
+    fn clock(&self) -> Option<Clock> {
+        let clock = self.state.clock?;
+        Some(match self.state.status {
+            GamePlayStatus::Playing(player) => clock.ticked(player, self.thought()),
+            _ => clock,
+        })
+    }
+
+    fn free_seat(&self) -> Option<Player> {
+        let taken = |player: &Player| self.seats.iter().any(|(p, _)| p == player);
+        self.state.players.iter().copied().find(|p| !taken(p))
+    }
+
+    fn is_open(&self) -> bool {
+        self.free_seat().is_some() && matches!(self.state.status, GamePlayStatus::Playing(_))
+    }
+
+    /// Whether both seats are taken and the game isn't over.
+    fn is_running(&self) -> bool {
+        self.free_seat().is_none() && matches!(self.state.status, GamePlayStatus::Playing(_))
+    }
+
+    fn seat_of(&self, player: Player) -> Option<Seat> {
+        self.seats
+            .iter()
+            .find(|(p, _)| *p == player)
+            .map(|(_, seat)| *seat)
+    }
+
+    fn name_of(&self, player: Player) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|(p, _)| *p == player)
+            .map(|(_, name)| name.as_str())
+    }
+
+    fn has_player_named(&self, name: &str) -> bool {
+        self.names.iter().any(|(_, n)| n == name)
+    }
+
+    fn player_of(&self, client: ClientId) -> Option<Player> {
+        self.seats
+            .iter()
+            .find(|(_, seat)| *seat == Seat::Human(client))
+            .map(|(player, _)| *player)
+    }
+}
+
+/// Running totals, for the admin endpoint.
+#[derive(Debug, Default)]
+struct Metrics {
+    moves: u64,
+    /// When each move in the last minute was played, oldest first.
+    recent_moves: VecDeque<Instant>,
+    computer_moves: u64,
+    think_time: Duration,
+}
+
+impl Metrics {
+    const RECENT: Duration = Duration::from_secs(60);
+
+    fn record_move(&mut self) {
+        self.moves += 1;
+        self.recent_moves.push_back(Instant::now());
+        self.forget_old_moves();
+    }
+
+    fn forget_old_moves(&mut self) {
+        while self
+            .recent_moves
+            .front()
+            .is_some_and(|played| played.elapsed() > Self::RECENT)
+        {
+            self.recent_moves.pop_front();
+        }
+    }
+}
+
+/// How busy the server is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub open_games: usize,
+    pub running_games: usize,
+    /// Connections, whether playing, watching or neither.
+    pub clients: usize,
+    pub spectators: usize,
+    /// Moves played since the server started.
+    pub moves: u64,
+    pub moves_last_minute: usize,
+    pub computer_moves: u64,
+    /// How long the computer's spent choosing all its moves.
+    pub think_time: Duration,
+}
+
+/// A game as the server holds it, for looking into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameSummary {
+    pub id: GameId,
+    pub state: TTTTState,
+    pub moves: Vec<Location>,
+    /// The name in each seat that's taken.
+    pub players: Vec<(Player, String)>,
+    /// Seats held for players who lost their connection.
+    pub away: Vec<Player>,
+    pub spectators: usize,
+    /// Started by a quick match.
+    pub quick: bool,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    config: ServerConfig,
+    games: BTreeMap<GameId, Game>,
+    /// The game each client has a seat in.
+    playing: HashMap<ClientId, GameId>,
+    /// The game each spectator is following.
+    watching: HashMap<ClientId, GameId>,
+    /// The client holding the seat each session token was given for.
+    tokens: HashMap<String, ClientId>,
+    /// Clients that lost their connection but still hold their seat.
+    away: HashSet<ClientId>,
+    /// The name each login token is for.
+    accounts: HashMap<String, String>,
+    /// Who each connection has logged in as.
+    names: HashMap<ClientId, String>,
+    /// Seats taken over the bot API, with no connection to give them up
+    /// when their game's over.
+    detached: HashSet<ClientId>,
+    /// When each player's recent chat messages were sent, oldest first.
+    chatted: HashMap<String, VecDeque<Instant>>,
+    clients: HashMap<ClientId, Sender<ServerMessage>>,
+    next_client: ClientId,
+    next_game: GameId,
+    store: Option<Store>,
+    metrics: Metrics,
+    /// Whether a thread is watching the clocks of games under way.
+    timer_running: bool,
+}
+
+/// Whether `name` can be registered: a short word that can't be mistaken
+/// for the computer.
+fn is_valid_name(name: &str) -> bool {
+    (1..=20).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && name != COMPUTER
+}
+
+/// The name the computer plays under.
+const COMPUTER: &str = "computer";
+
+impl Shared {
+    fn send(&self, client: ClientId, message: ServerMessage) {
+        if let Some(sender) = self.clients.get(&client) {
+            // A client that's gone is cleaned up by its own connection.
+            sender.send(message).ok();
+        }
+    }
+
+    /// Sends `message` to everyone playing in or watching `game`.
+    fn broadcast(&self, game: GameId, message: ServerMessage) {
+        let game = &self.games[&game];
+        for (_, seat) in &game.seats {
+            if let Seat::Human(client) = seat {
+                self.send(*client, message.clone());
+            }
+        }
+        for spectator in &game.spectators {
+            self.send(*spectator, message.clone());
+        }
+    }
+
+    fn game_ids(&self, keep: fn(&Game) -> bool) -> Vec<GameId> {
+        self.games
+            .iter()
+            .filter(|(_, game)| keep(game))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Saves game `id` as it is now, if games are being kept.
+    fn save(&self, id: GameId) {
+        let Some(store) = &self.store else { return };
+        let game = &self.games[&id];
+        let tokens = self
+            .tokens
+            .iter()
+            .filter_map(|(token, client)| Some((game.player_of(*client)?, token.clone())))
+            .collect();
+        let record = GameRecord {
+            id,
+            quick: game.quick,
+            players: game.names.clone(),
+            tokens,
+            moves: game.moves.clone(),
+            state: game.state.clone(),
+            abandoned: false,
+            chat: vec![],
+            started_at: 0,
+            updated_at: 0,
+        };
+        // Losing a save only matters if the server restarts, so play goes on.
+        if let Err(e) = store.save(&record) {
+            eprintln!("couldn't save game {}: {}", id, e);
+        }
+    }
+
+    fn summary(&self, id: GameId) -> Option<GameSummary> {
+        let game = self.games.get(&id)?;
+        let away = game
+            .seats
+            .iter()
+            .filter(|(_, seat)| matches!(seat, Seat::Human(client) if self.away.contains(client)))
+            .map(|(player, _)| *player)
+            .collect();
+        Some(GameSummary {
+            id,
+            state: game.state.clone(),
+            moves: game.moves.clone(),
+            players: game.names.clone(),
+            away,
+            spectators: game.spectators.len(),
+            quick: game.quick,
+        })
+    }
+
+    fn create_game(&mut self, quick: bool) -> GameId {
+        self.next_game += 1;
+        let game = Game::new(quick, self.config.clock);
+        self.games.insert(self.next_game, game);
+        self.next_game
+    }
+
+    fn sit(&mut self, client: ClientId, id: GameId, player: Player) {
+        let game = self
+            .games
+            .get_mut(&id)
+            .expect("only existing games are joined");
+        let name = self.names[&client].clone();
+        game.seats.push((player, Seat::Human(client)));
+        game.names.push((player, name));
+        game.turn_started = Instant::now();
+        let token = new_token();
+        self.playing.insert(client, id);
+        self.tokens.insert(token.clone(), client);
+        self.send(
+            client,
+            ServerMessage::Welcome {
+                game: id,
+                player,
+                token,
+            },
+        );
+        let game = &self.games[&id];
+        self.send(client, ServerMessage::State(game.state.clone()));
+        // Filling the game starts the clock, which both players hear about.
+        if let Some(clock) = game.clock().filter(|_| game.is_running()) {
+            self.broadcast(id, ServerMessage::Clock(clock));
+        }
+        self.save(id);
+    }
+
+    /// Hands the seat `held` kept while it was away over to `client`, and
+    /// brings it up to date.
+    fn resume(&mut self, held: ClientId, client: ClientId, token: String) {
+        self.away.remove(&held);
+        let id = self
+            .playing
+            .remove(&held)
+            .expect("held seats are in a game");
+        self.playing.insert(client, id);
+        self.tokens.insert(token.clone(), client);
+        let game = self
+            .games
+            .get_mut(&id)
+            .expect("clients only play in existing games");
+        let player = game.player_of(held).expect("held seats are taken");
+        for (_, seat) in &mut game.seats {
+            if *seat == Seat::Human(held) {
+                *seat = Seat::Human(client);
+            }
+        }
+        let moves = game.moves.clone();
+        self.send(
+            client,
+            ServerMessage::Welcome {
+                game: id,
+                player,
+                token,
+            },
+        );
+        self.send(client, ServerMessage::History { moves });
+        self.replay_chat(client, id);
+        self.send_state(client, id);
+        let status = self.games[&id].state.status;
+        if !matches!(status, GamePlayStatus::Playing(_)) {
+            self.send(client, ServerMessage::GameOver { status });
+        }
+    }
+
+    /// The messages that bring someone up to date with game `id`: its
+    /// position, then its clock if it has one that's started.
+    fn state_messages(&self, id: GameId) -> Vec<ServerMessage> {
+        let game = &self.games[&id];
+        let clock = game
+            .clock()
+            .filter(|_| !game.is_open())
+            .map(ServerMessage::Clock);
+        [ServerMessage::State(game.state.clone())]
+            .into_iter()
+            .chain(clock)
+            .collect()
+    }
+
+    fn send_state(&self, client: ClientId, id: GameId) {
+        for message in self.state_messages(id) {
+            self.send(client, message);
+        }
+    }
+
+    /// Tells everyone in game `id` where it's got to, and how it ended if
+    /// it has.
+    fn announce(&self, id: GameId) {
+        for message in self.state_messages(id) {
+            self.broadcast(id, message);
+        }
+        let status = self.games[&id].state.status;
+        if !matches!(status, GamePlayStatus::Playing(_)) {
+            self.broadcast(id, ServerMessage::GameOver { status });
+        }
+    }
+
+    /// Sends `client` everything said in game `id` so far.
+    fn replay_chat(&self, client: ClientId, id: GameId) {
+        for line in &self.games[&id].chat {
+            let (from, text) = (line.from.clone(), line.text.clone());
+            self.send(client, ServerMessage::Chat { from, text });
+        }
+    }
+
+    /// Passes on what `client`, logged in as `name`, said in its game,
+    /// unless it's too long or comes too soon after the last few.
+    fn chat(&mut self, client: ClientId, name: &str, text: &str) -> Result<(), String> {
+        let id = self
+            .playing
+            .get(&client)
+            .or_else(|| self.watching.get(&client))
+            .copied()
+            .ok_or("you aren't in a game")?;
+        let text = text.trim();
+        if text.is_empty() || text.chars().count() > CHAT_LENGTH || text.contains(char::is_control)
+        {
+            return Err(format!(
+                "chat messages are one line of up to {} characters",
+                CHAT_LENGTH
+            ));
+        }
+        let sent = self.chatted.entry(name.to_string()).or_default();
+        while sent.front().is_some_and(|at| at.elapsed() > CHAT_WINDOW) {
+            sent.pop_front();
+        }
+        if sent.len() >= CHAT_BURST {
+            return Err(format!(
+                "slow down: up to {} messages every {} seconds",
+                CHAT_BURST,
+                CHAT_WINDOW.as_secs()
+            ));
+        }
+        sent.push_back(Instant::now());
+        let line = ChatLine::new(name, text);
+        if let Some(Err(e)) = self.store.as_ref().map(|store| store.add_chat(id, &line)) {
+            eprintln!("couldn't save chat in game {}: {}", id, e);
+        }
+        self.games
+            .get_mut(&id)
+            .expect("clients are only in existing games")
+            .chat
+            .push(line);
+        let (from, text) = (name.to_string(), text.to_string());
+        self.broadcast(id, ServerMessage::Chat { from, text });
+        Ok(())
+    }
+
+    /// The game `client` has a seat in and the player it's seated as, if
+    /// it may play for that seat now.
+    fn seated(&self, client: ClientId) -> Result<(GameId, Player), MoveError> {
+        if self.watching.contains_key(&client) {
+            return Err(MoveError::Watching);
+        }
+        let &id = self.playing.get(&client).ok_or(MoveError::NotJoined)?;
+        let game = &self.games[&id];
+        let player = game
+            .player_of(client)
+            .expect("clients in a game have a seat");
+        // The seat is taken by name, so only that player may act for it.
+        if game.name_of(player) != self.names.get(&client).map(String::as_str) {
+            return Err(MoveError::NotYourSeat);
+        }
+        if game.free_seat().is_some() {
+            return Err(MoveError::WaitingForOpponent);
+        }
+        Ok((id, player))
+    }
+
+    /// Whether `client` is in a game that isn't over yet.
+    fn is_busy(&self, client: ClientId) -> bool {
+        self.playing
+            .get(&client)
+            .is_some_and(|id| matches!(self.games[id].state.status, GamePlayStatus::Playing(_)))
+    }
+
+    /// Gives up `client`'s seat, or stops it watching. Games with no players
+    /// left in them are dropped, and their spectators told.
+    fn leave(&mut self, client: ClientId) {
+        self.away.remove(&client);
+        self.tokens.retain(|_, holder| *holder != client);
+        if let Some(id) = self.watching.remove(&client) {
+            let game = self
+                .games
+                .get_mut(&id)
+                .expect("clients only watch existing games");
+            game.spectators.retain(|spectator| *spectator != client);
+        }
+        let Some(id) = self.playing.remove(&client) else {
+            return;
+        };
+        let game = self
+            .games
+            .get_mut(&id)
+            .expect("clients only play in existing games");
+        let playing = matches!(game.state.status, GamePlayStatus::Playing(_));
+        // A finished game keeps its players' names, to be looked up by.
+        if let Some(player) = game.player_of(client).filter(|_| playing) {
+            game.names.retain(|(p, _)| *p != player);
+        }
+        game.seats.retain(|(_, seat)| *seat != Seat::Human(client));
+        if !game
+            .seats
+            .iter()
+            .any(|(_, seat)| matches!(seat, Seat::Human(_)))
+        {
+            self.close(id, &format!("everyone left game {}", id));
+        } else if playing {
+            self.save(id);
+        }
+    }
+
+    /// Gives up the seat `client` was away from for too long. A game still
+    /// under way is lost by forfeit, and kept, unless whoever it's against
+    /// is away too.
+    fn forfeit(&mut self, client: ClientId) {
+        if let Some(&id) = self.playing.get(&client) {
+            let game = self
+                .games
+                .get_mut(&id)
+                .expect("clients only play in existing games");
+            let against = game.seats.iter().any(|(_, seat)| match seat {
+                Seat::Human(other) => *other != client && !self.away.contains(other),
+                Seat::Computer => true,
+            });
+            let player = game.player_of(client).filter(|_| against);
+            if player.is_some_and(|player| game.state.forfeit(player).is_ok()) {
+                game.offer = None;
+                self.save(id);
+                self.announce(id);
+            }
+        }
+        self.leave(client);
+    }
+
+    /// Gives up the seats taken over the bot API in games that are over, or
+    /// gone.
+    fn reap_detached(&mut self) {
+        let done: Vec<ClientId> = self
+            .detached
+            .iter()
+            .filter(|client| !self.is_busy(**client))
+            .copied()
+            .collect();
+        for client in done {
+            self.detached.remove(&client);
+            self.names.remove(&client);
+            self.leave(client);
+        }
+    }
+
+    /// Drops game `id`, telling anyone still in it or watching it why. A
+    /// game that wasn't over is kept as abandoned.
+    fn close(&mut self, id: GameId, reason: &str) {
+        let game = self
+            .games
+            .remove(&id)
+            .expect("only existing games are closed");
+        for (_, seat) in &game.seats {
+            if let Seat::Human(client) = *seat {
+                self.playing.remove(&client);
+                self.away.remove(&client);
+                self.tokens.retain(|_, holder| *holder != client);
+                self.send(client, error(reason));
+            }
+        }
+        for spectator in &game.spectators {
+            self.watching.remove(spectator);
+            self.send(*spectator, error(reason));
+        }
+        let playing = matches!(game.state.status, GamePlayStatus::Playing(_));
+        if let Some(store) = self.store.as_ref().filter(|_| playing) {
+            if let Err(e) = store.abandon(id) {
+                eprintln!("couldn't save game {}: {}", id, e);
+            }
+        }
+    }
+}
+
+/// Why a client's move, or anything else it tried in its game, was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    NotJoined,
+    Watching,
+    /// The connection isn't logged in as the player in the seat.
+    NotYourSeat,
+    WaitingForOpponent,
+    /// The move numbered this has been played already.
+    AlreadyPlayed(usize),
+    /// The move is numbered past the end of the game.
+    Ahead {
+        played: usize,
+    },
+    NotYourTurn,
+    /// An offer was made while another was waiting for an answer.
+    OfferPending,
+    NoOffer,
+    /// A player tried to answer their own offer.
+    OwnOffer,
+    Place(PlaceErr),
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::NotJoined => write!(f, "you haven't joined a game"),
+            MoveError::Watching => write!(f, "you're only watching"),
+            MoveError::NotYourSeat => write!(f, "you aren't logged in as that player"),
+            MoveError::WaitingForOpponent => write!(f, "waiting for an opponent"),
+            MoveError::AlreadyPlayed(seq) => write!(f, "move {} has already been played", seq),
+            MoveError::Ahead { played } => write!(f, "only {} moves have been played", played),
+            MoveError::NotYourTurn => write!(f, "it isn't your turn"),
+            MoveError::OfferPending => write!(f, "there's already an offer waiting for an answer"),
+            MoveError::NoOffer => write!(f, "there's no offer to answer"),
+            MoveError::OwnOffer => write!(f, "you can't answer your own offer"),
+            MoveError::Place(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+impl From<PlaceErr> for MoveError {
+    fn from(e: PlaceErr) -> Self {
+        MoveError::Place(e)
+    }
+}
+
+/// What a player can do in their game besides moving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameAction {
+    Resign,
+    Offer(Offer),
+    /// Accepts or declines the other player's offer.
+    Answer {
+        accept: bool,
+    },
+}
+
+/// A handle to the server; clones share the same games.
+#[derive(Debug, Clone, Default)]
+pub struct Server {
+    shared: Arc<Mutex<Shared>>,
+    /// Wakes the thread watching the clocks when one of them changes.
+    clocks: Arc<Condvar>,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_config(config: ServerConfig) -> Self {
+        let server = Self::new();
+        server.lock().config = config;
+        server
+    }
+
+    /// Plays the games started from now on with `clock`, or without one.
+    pub fn set_clock(&self, clock: Option<TimeControl>) {
+        self.lock().config.clock = clock;
+    }
+
+    /// A server that keeps its players and games in `store`, starting with
+    /// the ones already there. Seats in unfinished games are held for the
+    /// grace period, for their players to resume with the tokens they had.
+    pub fn with_store(config: ServerConfig, store: Store) -> Result<Self, StoreError> {
+        let server = Self::with_config(config);
+        let mut shared = server.lock();
+        for (name, token) in store.accounts()? {
+            shared.accounts.insert(token, name);
+        }
+        shared.next_game = store.last_game_id()?;
+        let games = store.unfinished()?;
+        shared.store = Some(store);
+        for record in games {
+            let mut game = Game::new(record.quick, None);
+            game.state = record.state;
+            game.moves = record.moves;
+            game.names = record.players;
+            game.chat = record.chat;
+            let mut held = vec![];
+            for (player, name) in &game.names {
+                if name == COMPUTER {
+                    game.seats.push((*player, Seat::Computer));
+                    continue;
+                }
+                let client = shared.next_client;
+                shared.next_client += 1;
+                game.seats.push((*player, Seat::Human(client)));
+                shared.playing.insert(client, record.id);
+                if let Some((_, token)) = record.tokens.iter().find(|(p, _)| p == player) {
+                    shared.tokens.insert(token.clone(), client);
+                }
+                held.push(client);
+            }
+            shared.games.insert(record.id, game);
+            for client in held {
+                server.hold_seat(&mut shared, client);
+            }
+            server.start_clock(&mut shared, record.id);
+            server.start_computer(&mut shared, record.id);
+        }
+        drop(shared);
+        Ok(server)
+    }
+
+    /// The server's state, after tidying up what finished since it was
+    /// last looked at. Nothing's half done between locks, so games can be
+    /// dropped here safely.
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        let mut shared = self
+            .shared
+            .lock()
+            .expect("no thread panics while holding the lock");
+        shared.reap_detached();
+        shared
+    }
+
+    /// A game as the server sees it.
+    pub fn game(&self, id: GameId) -> Option<TTTTState> {
+        self.lock().games.get(&id).map(|game| game.state.clone())
+    }
+
+    /// Registers a new connection. Messages for it arrive on the receiver.
+    pub fn connect(&self) -> (ClientId, Receiver<ServerMessage>) {
+        let mut shared = self.lock();
+        let id = shared.next_client;
+        shared.next_client += 1;
+        let (sender, receiver) = mpsc::channel();
+        shared.clients.insert(id, sender);
+        (id, receiver)
+    }
+
+    /// Lets `client` keep its seat without a connection, as the bot API's
+    /// requests come and go. Its seat is given up once the game's over.
+    pub fn detach(&self, client: ClientId) {
+        let mut shared = self.lock();
+        shared.clients.remove(&client);
+        shared.detached.insert(client);
+    }
+
+    /// Forgets a connection. A seat in a game under way is held for the
+    /// grace period in case the player comes back; any other is given up.
+    pub fn disconnect(&self, client: ClientId) {
+        let mut shared = self.lock();
+        shared.clients.remove(&client);
+        shared.names.remove(&client);
+        let grace = shared.config.reconnect_grace;
+        let running = shared
+            .playing
+            .get(&client)
+            .is_some_and(|id| shared.games[id].is_running());
+        if !running || grace.is_zero() {
+            return shared.leave(client);
+        }
+        self.hold_seat(&mut shared, client);
+    }
+
+    /// Keeps `client`'s seat for the grace period, giving it up unless
+    /// someone's resumed it by then.
+    fn hold_seat(&self, shared: &mut Shared, client: ClientId) {
+        shared.away.insert(client);
+        let (server, grace) = (self.clone(), shared.config.reconnect_grace);
+        thread::spawn(move || {
+            thread::sleep(grace);
+            let mut shared = server.lock();
+            if shared.away.contains(&client) {
+                shared.forfeit(client);
+            }
+        });
+    }
+
+    pub fn handle(&self, client: ClientId, message: ClientMessage) {
+        let mut shared = self.lock();
+        if matches!(
+            message,
+            ClientMessage::Create
+                | ClientMessage::Join { .. }
+                | ClientMessage::QuickMatch { .. }
+                | ClientMessage::Watch { .. }
+                | ClientMessage::Resume { .. }
+                | ClientMessage::Register { .. }
+                | ClientMessage::Login { .. }
+        ) {
+            if shared.is_busy(client) {
+                return shared.send(client, error("you're already in a game"));
+            }
+            shared.leave(client);
+        }
+        let seating = matches!(
+            message,
+            ClientMessage::Create
+                | ClientMessage::Join { .. }
+                | ClientMessage::QuickMatch { .. }
+                | ClientMessage::Resume { .. }
+        );
+        let name = shared.names.get(&client).cloned();
+        if seating && name.is_none() {
+            let message = "log in first: register a name, or send the token you were given";
+            return shared.send(client, error(message));
+        }
+        let name = name.unwrap_or_default();
+        match message {
+            ClientMessage::Register { name } => {
+                if !is_valid_name(&name) {
+                    let message = "names are up to 20 letters, digits, - and _";
+                    return shared.send(client, error(message));
+                }
+                if shared.accounts.values().any(|taken| *taken == name) {
+                    return shared.send(client, error(&format!("{} is taken", name)));
+                }
+                let token = new_token();
+                if let Some(Err(e)) = shared.store.as_ref().map(|s| s.add_account(&name, &token)) {
+                    eprintln!("couldn't save {}: {}", name, e);
+                    return shared.send(client, error("couldn't register you, try again"));
+                }
+                shared.accounts.insert(token.clone(), name.clone());
+                shared.names.insert(client, name.clone());
+                shared.send(client, ServerMessage::Registered { name, token });
+            }
+            ClientMessage::Login { token } => match shared.accounts.get(&token).cloned() {
+                Some(name) => {
+                    shared.names.insert(client, name.clone());
+                    shared.send(client, ServerMessage::LoggedIn { name });
+                }
+                None => shared.send(client, error("that token isn't anyone's")),
+            },
+            ClientMessage::Create => {
+                let id = shared.create_game(false);
+                shared.sit(client, id, Player::A);
+            }
+            ClientMessage::List => {
+                let open = shared.game_ids(Game::is_open);
+                let running = shared.game_ids(Game::is_running);
+                shared.send(client, ServerMessage::Games { open, running });
+            }
+            ClientMessage::Join { game: id } => match shared.games.get(&id) {
+                None => shared.send(client, error(&format!("there's no game {}", id))),
+                Some(game) if !game.is_open() => {
+                    shared.send(client, error(&format!("game {} is full", id)))
+                }
+                Some(game) if game.has_player_named(&name) => shared.send(
+                    client,
+                    error(&format!("you're already playing in game {}", id)),
+                ),
+                Some(game) => {
+                    let player = game.free_seat().expect("open games have a free seat");
+                    shared.sit(client, id, player);
+                    self.start_clock(&mut shared, id);
+                }
+            },
+            ClientMessage::Watch { game: id } => {
+                let Some(game) = shared.games.get_mut(&id) else {
+                    return shared.send(client, error(&format!("there's no game {}", id)));
+                };
+                game.spectators.push(client);
+                let moves = game.moves.clone();
+                shared.watching.insert(client, id);
+                shared.send(client, ServerMessage::Watching { game: id });
+                shared.send(client, ServerMessage::History { moves });
+                shared.replay_chat(client, id);
+                shared.send_state(client, id);
+            }
+            ClientMessage::Resume { token } => match shared.tokens.get(&token) {
+                Some(&held) if shared.away.contains(&held) => {
+                    let game = &shared.games[&shared.playing[&held]];
+                    let player = game.player_of(held).expect("held seats are taken");
+                    match game.name_of(player) {
+                        Some(owner) if owner != name => {
+                            let message = format!("that seat belongs to {}", owner);
+                            shared.send(client, error(&message))
+                        }
+                        _ => shared.resume(held, client, token),
+                    }
+                }
+                _ => shared.send(client, error("there's no seat held for that token")),
+            },
+            ClientMessage::QuickMatch { computer } => {
+                let waiting = shared
+                    .games
+                    .iter()
+                    .find(|(_, game)| game.quick && game.is_open() && !game.has_player_named(&name))
+                    .map(|(id, game)| (*id, game.free_seat().unwrap()));
+                match waiting {
+                    Some((id, player)) => {
+                        shared.sit(client, id, player);
+                        self.start_clock(&mut shared, id);
+                    }
+                    None => {
+                        let id = shared.create_game(true);
+                        shared.sit(client, id, Player::A);
+                        if computer {
+                            let server = self.clone();
+                            let timeout = shared.config.match_timeout;
+                            thread::spawn(move || {
+                                thread::sleep(timeout);
+                                server.bring_in_computer(id);
+                            });
+                        }
+                    }
+                }
+            }
+            ClientMessage::Move { at, seq } => {
+                if let Err(e) = self.try_move(&mut shared, client, at, seq) {
+                    shared.send(client, error(&e.to_string()));
+                }
+            }
+            ClientMessage::Chat { .. } if !shared.names.contains_key(&client) => {
+                shared.send(client, error("log in to chat"))
+            }
+            ClientMessage::Chat { text } => {
+                if let Err(e) = shared.chat(client, &name, &text) {
+                    shared.send(client, error(&e));
+                }
+            }
+            ClientMessage::Resign
+            | ClientMessage::Offer { .. }
+            | ClientMessage::Accept
+            | ClientMessage::Decline => {
+                let action = match message {
+                    ClientMessage::Resign => GameAction::Resign,
+                    ClientMessage::Offer { offer } => GameAction::Offer(offer),
+                    ClientMessage::Accept => GameAction::Answer { accept: true },
+                    _ => GameAction::Answer { accept: false },
+                };
+                if let Err(e) = self.try_action(&mut shared, client, action) {
+                    shared.send(client, error(&e.to_string()));
+                }
+            }
+        }
+    }
+
+    /// Plays `at` for `client`, as if it had sent a move, and returns the
+    /// game afterwards and its moves. A game that's over may be gone by the
+    /// next look.
+    pub fn submit_move(
+        &self,
+        client: ClientId,
+        at: Location,
+        seq: Option<usize>,
+    ) -> Result<(TTTTState, Vec<Location>), MoveError> {
+        let mut shared = self.lock();
+        let id = self.try_move(&mut shared, client, at, seq)?;
+        let game = &shared.games[&id];
+        Ok((game.state.clone(), game.moves.clone()))
+    }
+
+    /// Does `action` for `client`, as if it had sent the message, and
+    /// returns the game afterwards and its moves.
+    pub fn submit_action(
+        &self,
+        client: ClientId,
+        action: GameAction,
+    ) -> Result<(TTTTState, Vec<Location>), MoveError> {
+        let mut shared = self.lock();
+        let id = self.try_action(&mut shared, client, action)?;
+        let game = &shared.games[&id];
+        Ok((game.state.clone(), game.moves.clone()))
+    }
+
+    /// The name a login token is for.
+    pub fn account(&self, token: &str) -> Option<String> {
+        self.lock().accounts.get(token).cloned()
+    }
+
+    /// The connection `name` holds a seat in game `id` through.
+    pub fn seat_of(&self, name: &str, id: GameId) -> Option<ClientId> {
+        let shared = self.lock();
+        shared
+            .playing
+            .iter()
+            .find(|(client, game)| {
+                **game == id && shared.names.get(client).is_some_and(|n| n == name)
+            })
+            .map(|(client, _)| *client)
+    }
+
+    /// Every move played so far in game `id`.
+    pub fn moves(&self, id: GameId) -> Option<Vec<Location>> {
+        self.lock().games.get(&id).map(|game| game.moves.clone())
+    }
+
+    /// Every kept game `name` has played in, oldest first, or `None` if
+    /// games aren't being kept.
+    pub fn games_of(&self, name: &str) -> Option<Result<Vec<GameRecord>, StoreError>> {
+        self.lock().store.as_ref().map(|store| store.games_of(name))
+    }
+
+    /// Game `id` as it was last saved, including games that are over.
+    pub fn saved_game(&self, id: GameId) -> Option<GameRecord> {
+        let shared = self.lock();
+        shared.store.as_ref()?.game(id).ok().flatten()
+    }
+
+    pub fn stats(&self) -> Stats {
+        let mut shared = self.lock();
+        shared.metrics.forget_old_moves();
+        Stats {
+            open_games: shared.game_ids(Game::is_open).len(),
+            running_games: shared.game_ids(Game::is_running).len(),
+            clients: shared.clients.len(),
+            spectators: shared.watching.len(),
+            moves: shared.metrics.moves,
+            moves_last_minute: shared.metrics.recent_moves.len(),
+            computer_moves: shared.metrics.computer_moves,
+            think_time: shared.metrics.think_time,
+        }
+    }
+
+    /// Every game the server holds, finished or not, by id.
+    pub fn summaries(&self) -> Vec<GameSummary> {
+        let shared = self.lock();
+        shared
+            .games
+            .keys()
+            .filter_map(|id| shared.summary(*id))
+            .collect()
+    }
+
+    pub fn summary(&self, id: GameId) -> Option<GameSummary> {
+        self.lock().summary(id)
+    }
+
+    /// Stops game `id` wherever it's got to, telling its players and
+    /// spectators. Returns whether there was such a game.
+    pub fn abort(&self, id: GameId) -> bool {
+        let mut shared = self.lock();
+        let found = shared.games.contains_key(&id);
+        if found {
+            shared.close(id, &format!("game {} was stopped by the server", id));
+        }
+        found
+    }
+
+    /// The games waiting for a player, and those being played.
+    pub fn games(&self) -> (Vec<GameId>, Vec<GameId>) {
+        let shared = self.lock();
+        (
+            shared.game_ids(Game::is_open),
+            shared.game_ids(Game::is_running),
+        )
+    }
+
+    /// Checks that `client` may play `at` now and plays it, returning the
+    /// game it was played in.
+    fn try_move(
+        &self,
+        shared: &mut Shared,
+        client: ClientId,
+        at: Location,
+        seq: Option<usize>,
+    ) -> Result<GameId, MoveError> {
+        let (id, player) = shared.seated(client)?;
+        let game = &shared.games[&id];
+        let played = game.state.turn();
+        match seq {
+            Some(seq) if seq < played => return Err(MoveError::AlreadyPlayed(seq)),
+            Some(seq) if seq > played => return Err(MoveError::Ahead { played }),
+            _ => (),
+        }
+        if matches!(game.state.status, GamePlayStatus::Playing(p) if p != player) {
+            return Err(MoveError::NotYourTurn);
+        }
+        if !at.is_on_board() {
+            return Err(PlaceErr::OffBoard.into());
+        }
+        self.play(shared, id, at)?;
+        Ok(id)
+    }
+
+    /// Checks that `client` may do `action` now and does it, returning the
+    /// game it was done in.
+    fn try_action(
+        &self,
+        shared: &mut Shared,
+        client: ClientId,
+        action: GameAction,
+    ) -> Result<GameId, MoveError> {
+        let (id, player) = shared.seated(client)?;
+        let game = shared
+            .games
+            .get_mut(&id)
+            .expect("clients only play in existing games");
+        if !matches!(game.state.status, GamePlayStatus::Playing(_)) {
+            return Err(PlaceErr::GameOver.into());
+        }
+        match action {
+            GameAction::Resign => {
+                game.state.resign(player)?;
+                game.offer = None;
+                shared.save(id);
+                shared.announce(id);
+            }
+            GameAction::Offer(offer) => {
+                if game.offer.is_some() {
+                    return Err(MoveError::OfferPending);
+                }
+                if offer == Offer::Takeback {
+                    // Only ask for what could be given.
+                    let mut moves = game.moves.clone();
+                    game.state.clone().take_back(player, &mut moves)?;
+                }
+                game.offer = Some((player, offer));
+                let computer = game.seat_of(player.other_player()) == Some(Seat::Computer);
+                shared.broadcast(id, ServerMessage::Offered { offer, by: player });
+                if computer {
+                    self.settle_offer(shared, id, computer_player::accepts(offer))?;
+                }
+            }
+            GameAction::Answer { accept } => match game.offer {
+                None => return Err(MoveError::NoOffer),
+                Some((by, _)) if by == player => return Err(MoveError::OwnOffer),
+                Some(_) => self.settle_offer(shared, id, accept)?,
+            },
+        }
+        Ok(id)
+    }
+
+    /// Settles the offer waiting in game `id`, telling everyone in it.
+    fn settle_offer(&self, shared: &mut Shared, id: GameId, accept: bool) -> Result<(), PlaceErr> {
+        let game = shared
+            .games
+            .get_mut(&id)
+            .expect("only existing games have offers");
+        let (by, offer) = game.offer.take().expect("only offers are answered");
+        if !accept {
+            let by = by.other_player();
+            shared.broadcast(id, ServerMessage::Declined { offer, by });
+            return Ok(());
+        }
+        match offer {
+            Offer::Draw => game.state.agree_draw()?,
+            Offer::Takeback => {
+                game.state.take_back(by, &mut game.moves)?;
+                game.turn_started = Instant::now();
+                let moves = game.moves.clone();
+                shared.broadcast(id, ServerMessage::History { moves });
+            }
+        }
+        shared.save(id);
+        shared.announce(id);
+        self.start_clock(shared, id);
+        self.start_computer(shared, id);
+        Ok(())
+    }
+
+    /// Plays `loc` in game `id` and tells its players. A move made after
+    /// the mover's time ran out ends the game instead.
+    fn play(&self, shared: &mut Shared, id: GameId, loc: Location) -> Result<(), PlaceErr> {
+        let game = shared
+            .games
+            .get_mut(&id)
+            .expect("only existing games are played");
+        if let Err(e) = game.state.play_timed(loc, game.thought()) {
+            if e == PlaceErr::OutOfTime {
+                shared.save(id);
+                shared.announce(id);
+            }
+            return Err(e);
+        }
+        game.moves.push(loc);
+        game.turn_started = Instant::now();
+        game.offer = None;
+        shared.metrics.record_move();
+        shared.save(id);
+        shared.announce(id);
+        self.start_clock(shared, id);
+        self.start_computer(shared, id);
+        Ok(())
+    }
+
+    /// Watches the clock of whoever is to move in game `id`, and ends the
+    /// game if their time runs out before they've moved. One thread watches
+    /// every game's clock, and is woken when one changes.
+    fn start_clock(&self, shared: &mut Shared, id: GameId) {
+        if shared.games[&id].flag_falls_at().is_none() {
+            return;
+        }
+        if shared.timer_running {
+            self.clocks.notify_one();
+            return;
+        }
+        shared.timer_running = true;
+        let server = self.clone();
+        thread::spawn(move || server.watch_clocks());
+    }
+
+    /// Ends the games where the player to move has run out of time, and
+    /// sleeps until the next one could. Stops once no clock is running.
+    fn watch_clocks(&self) {
+        let mut shared = self.lock();
+        loop {
+            let now = Instant::now();
+            let fallen: Vec<GameId> = shared
+                .games
+                .iter()
+                .filter(|(_, game)| game.flag_falls_at().is_some_and(|at| at <= now))
+                .map(|(id, _)| *id)
+                .collect();
+            for id in fallen {
+                let game = shared.games.get_mut(&id).expect("the game was just found");
+                let thought = game.thought();
+                if game.state.flag(thought) {
+                    shared.save(id);
+                    shared.announce(id);
+                }
+            }
+            let Some(next) = shared.games.values().filter_map(Game::flag_falls_at).min() else {
+                shared.timer_running = false;
+                return;
+            };
+            let wait = next.saturating_duration_since(Instant::now());
+            shared = self
+                .clocks
+                .wait_timeout(shared, wait)
+                .expect("no thread panics while holding the lock")
+                .0;
+        }
+    }
+
+    /// Gives the computer the free seat in game `id`, if it's still free.
+    fn bring_in_computer(&self, id: GameId) {
+        let mut shared = self.lock();
+        let Some(game) = shared.games.get_mut(&id) else {
+            return;
+        };
+        if let Some(player) = game.free_seat().filter(|_| game.is_open()) {
+            game.seats.push((player, Seat::Computer));
+            game.names.push((player, COMPUTER.to_string()));
+            game.turn_started = Instant::now();
+            if let Some(clock) = game.clock() {
+                shared.broadcast(id, ServerMessage::Clock(clock));
+            }
+            shared.save(id);
+            self.start_clock(&mut shared, id);
+            self.start_computer(&mut shared, id);
+        }
+    }
+
+    /// Starts the computer thinking if it's its turn in game `id`. The search
+    /// runs without the lock held, so other games carry on meanwhile.
+    fn start_computer(&self, shared: &mut Shared, id: GameId) {
+        let game = &shared.games[&id];
+        let GamePlayStatus::Playing(player) = game.state.status else {
+            return;
+        };
+        if game.seat_of(player) != Some(Seat::Computer) {
+            return;
+        }
+        let (server, state, depth) = (
+            self.clone(),
+            game.state.clone(),
+            shared.config.computer_depth,
+        );
+        thread::spawn(move || {
+            let started = Instant::now();
+            let found = search(&state, depth, &CancelToken::new());
+            let mut shared = server.lock();
+            shared.metrics.computer_moves += 1;
+            shared.metrics.think_time += started.elapsed();
+            let Some(loc) = found else { return };
+            // The game may have ended or been dropped while it was thinking.
+            if shared
+                .games
+                .get(&id)
+                .is_some_and(|game| game.state == state)
+            {
+                server.play(&mut shared, id, loc).ok();
+            }
+        });
+    }
+
+    /// Accepts TCP connections until the listener fails, serving each on its
+    /// own thread.
+    pub fn serve_tcp(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            thread::spawn(move || server.serve_tcp_client(stream));
+        }
+        Ok(())
+    }
+
+    fn serve_tcp_client(&self, stream: TcpStream) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        let (client, messages) = self.connect();
+        let writing = thread::spawn(move || {
+            for message in messages {
+                if writeln!(writer, "{}", message).is_err() {
+                    break;
+                }
+            }
+        });
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match line.parse() {
+                Ok(message) => self.handle(client, message),
+                Err(e) => self.lock().send(client, error(&e.to_string())),
+            }
+        }
+        // Dropping the client's sender ends the writing thread.
+        self.disconnect(client);
+        writing.join().ok();
+        Ok(())
+    }
+
+    /// Like `serve_tcp`, but for WebSocket clients, which send and receive
+    /// the messages as JSON text.
+    pub fn serve_websocket(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            thread::spawn(move || server.serve_websocket_client(stream));
+        }
+        Ok(())
+    }
+
+    /// Answers LAN discovery probes on `socket`, advertising the open games
+    /// and the TCP `port` to play them on.
+    pub fn serve_discovery(&self, socket: UdpSocket, port: u16) -> io::Result<()> {
+        discovery::answer_probes(socket, || Announcement {
+            port,
+            open: self.lock().game_ids(Game::is_open),
+        })
+    }
+
+    fn serve_websocket_client(&self, stream: TcpStream) {
+        let Ok(mut socket) = tungstenite::accept(stream) else {
+            return;
+        };
+        // A WebSocket can't be read and written from different threads, so
+        // reading gives up every so often to send what's waiting.
+        if socket.get_ref().set_read_timeout(Some(POLL)).is_err() {
+            return;
+        }
+        let (client, messages) = self.connect();
+        'connection: loop {
+            for message in messages.try_iter() {
+                let json = serde_json::to_string(&message).expect("messages always serialize");
+                if socket.send(Message::text(json)).is_err() {
+                    break 'connection;
+                }
+            }
+            match socket.read() {
+                Ok(Message::Text(text)) => match serde_json::from_str(&text) {
+                    Ok(message) => self.handle(client, message),
+                    Err(e) => self.lock().send(client, error(&e.to_string())),
+                },
+                Ok(_) => (),
+                Err(tungstenite::Error::Io(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(_) => break,
+            }
+        }
+        self.disconnect(client);
+    }
+}
+
+fn new_token() -> String {
+    format!(
+        "{:016x}{:016x}",
+        rand::random::<u64>(),
+        rand::random::<u64>()
+    )
+}
+
+fn error(message: &str) -> ServerMessage {
+    ServerMessage::Error {
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::{DrawReason, WinReason};
+
+    /// A new connection that's registered a name, and the token for it.
+    fn registered(server: &Server, name: &str) -> (ClientId, Receiver<ServerMessage>, String) {
+        let (client, messages) = server.connect();
+        let name = name.to_string();
+        server.handle(client, ClientMessage::Register { name });
+        let Ok(ServerMessage::Registered { token, .. }) = messages.try_recv() else {
+            panic!("couldn't register");
+        };
+        (client, messages, token)
+    }
+
+    /// A new player, with a name of their own, that's sent `message`.
+    fn connected(server: &Server, message: ClientMessage) -> (ClientId, Receiver<ServerMessage>) {
+        let name = format!("player{}", server.lock().next_client);
+        let (client, messages, _) = registered(server, &name);
+        server.handle(client, message);
+        (client, messages)
+    }
+
+    fn quick_match(server: &Server) -> (ClientId, Receiver<ServerMessage>) {
+        connected(server, ClientMessage::QuickMatch { computer: false })
+    }
+
+    /// The game and seat given in the next message, if it's a welcome.
+    fn seat(messages: &Receiver<ServerMessage>) -> Option<(GameId, Player)> {
+        match messages.try_recv() {
+            Ok(ServerMessage::Welcome { game, player, .. }) => Some((game, player)),
+            _ => None,
+        }
+    }
+
+    fn move_to(at: Location) -> ClientMessage {
+        ClientMessage::Move { at, seq: None }
+    }
+
+    fn last(messages: &Receiver<ServerMessage>) -> Option<ServerMessage> {
+        messages.try_iter().last()
+    }
+
+    #[test]
+    fn quick_matches_are_paired_in_order() {
+        let server = Server::new();
+        let (_, a) = quick_match(&server);
+        let (_, b) = quick_match(&server);
+        let (_, c) = quick_match(&server);
+        assert_eq!(seat(&a), Some((1, Player::A)));
+        assert_eq!(seat(&b), Some((1, Player::B)));
+        assert_eq!(seat(&c), Some((2, Player::A)));
+    }
+
+    #[test]
+    fn created_games_are_listed_until_they_fill_up() {
+        let server = Server::new();
+        let (_, host) = connected(&server, ClientMessage::Create);
+        assert_eq!(seat(&host), Some((1, Player::A)));
+        let (_, lobby) = connected(&server, ClientMessage::List);
+        assert_eq!(
+            lobby.try_recv(),
+            Ok(ServerMessage::Games {
+                open: vec![1],
+                running: vec![]
+            })
+        );
+
+        let (_, guest) = connected(&server, ClientMessage::Join { game: 1 });
+        assert_eq!(seat(&guest), Some((1, Player::B)));
+        let (_, late) = connected(&server, ClientMessage::Join { game: 1 });
+        assert_eq!(last(&late), Some(error("game 1 is full")));
+        let (_, lost) = connected(&server, ClientMessage::Join { game: 9 });
+        assert_eq!(last(&lost), Some(error("there's no game 9")));
+    }
+
+    #[test]
+    fn only_the_player_to_move_can_play() {
+        let server = Server::new();
+        let (a, a_messages) = quick_match(&server);
+        let (b, b_messages) = quick_match(&server);
+        let loc = Location::new(1, 1, 1);
+
+        server.handle(b, move_to(loc));
+        assert_eq!(last(&b_messages), Some(error("it isn't your turn")));
+        server.handle(a, move_to(loc));
+        let after = ServerMessage::State(server.game(1).unwrap());
+        assert_eq!(last(&a_messages), Some(after.clone()));
+        assert_eq!(last(&b_messages), Some(after));
+        server.handle(b, move_to(loc));
+        assert_eq!(last(&b_messages), Some(error("that spot is taken")));
+    }
+
+    #[test]
+    fn games_are_independent() {
+        let server = Server::new();
+        let (a, _) = quick_match(&server);
+        quick_match(&server);
+        quick_match(&server);
+        quick_match(&server);
+        server.handle(a, move_to(Location::new(0, 0, 0)));
+        assert_eq!(server.game(1).unwrap().turn(), 1);
+        assert_eq!(server.game(2).unwrap().turn(), 0);
+    }
+
+    #[test]
+    fn a_seat_is_freed_when_its_player_leaves() {
+        let server = Server::with_config(ServerConfig {
+            reconnect_grace: Duration::ZERO,
+            ..ServerConfig::default()
+        });
+        let (a, _) = connected(&server, ClientMessage::Create);
+        let (_, b) = connected(&server, ClientMessage::Join { game: 1 });
+        server.disconnect(a);
+        let (_, c) = connected(&server, ClientMessage::Join { game: 1 });
+        assert_eq!(seat(&b), Some((1, Player::B)));
+        assert_eq!(seat(&c), Some((1, Player::A)));
+    }
+
+    #[test]
+    fn a_dropped_player_can_take_their_seat_back() {
+        let server = Server::new();
+        let (a, a_messages, account) = registered(&server, "alice");
+        server.handle(a, ClientMessage::QuickMatch { computer: false });
+        let (b, _) = quick_match(&server);
+        let Ok(ServerMessage::Welcome { token, .. }) = a_messages.try_recv() else {
+            panic!("A wasn't welcomed");
+        };
+        server.handle(a, move_to(Location::new(0, 0, 0)));
+        server.disconnect(a);
+        server.handle(b, move_to(Location::new(1, 1, 1)));
+
+        let resume = ClientMessage::Resume { token };
+        let (_, thief) = connected(&server, resume.clone());
+        assert_eq!(last(&thief), Some(error("that seat belongs to alice")));
+        let (a, messages) = server.connect();
+        server.handle(a, ClientMessage::Login { token: account });
+        server.handle(a, resume);
+        let name = "alice".to_string();
+        assert_eq!(messages.try_recv(), Ok(ServerMessage::LoggedIn { name }));
+        assert_eq!(seat(&messages), Some((1, Player::A)));
+        let moves = vec![Location::new(0, 0, 0), Location::new(1, 1, 1)];
+        assert_eq!(messages.try_recv(), Ok(ServerMessage::History { moves }));
+        let state = server.game(1).unwrap();
+        assert_eq!(messages.try_recv(), Ok(ServerMessage::State(state)));
+
+        let numbered = |seq| ClientMessage::Move {
+            at: Location::new(2, 2, 2),
+            seq: Some(seq),
+        };
+        server.handle(a, numbered(0));
+        assert_eq!(
+            last(&messages),
+            Some(error("move 0 has already been played"))
+        );
+        server.handle(a, numbered(3));
+        assert_eq!(
+            last(&messages),
+            Some(error("only 2 moves have been played"))
+        );
+        server.handle(a, numbered(2));
+        assert_eq!(server.game(1).unwrap().turn(), 3);
+    }
+
+    #[test]
+    fn held_seats_are_given_up_after_the_grace_period() {
+        let server = Server::with_config(ServerConfig {
+            reconnect_grace: Duration::from_millis(10),
+            ..ServerConfig::default()
+        });
+        let (a, a_messages) = quick_match(&server);
+        let (_, b_messages) = quick_match(&server);
+        let Ok(ServerMessage::Welcome { token, .. }) = a_messages.try_recv() else {
+            panic!("A wasn't welcomed");
+        };
+        server.disconnect(a);
+        let (_, late) = connected(&server, ClientMessage::Join { game: 1 });
+        assert_eq!(last(&late), Some(error("game 1 is full")));
+
+        thread::sleep(Duration::from_millis(100));
+        let (_, lost) = connected(&server, ClientMessage::Resume { token });
+        assert_eq!(
+            last(&lost),
+            Some(error("there's no seat held for that token"))
+        );
+        // A doesn't come back, so B wins, and the seat isn't anyone else's.
+        let status = GamePlayStatus::Win {
+            player: Player::B,
+            reason: WinReason::Forfeit,
+        };
+        assert_eq!(last(&b_messages), Some(ServerMessage::GameOver { status }));
+        assert_eq!(server.games(), (vec![], vec![]));
+        let (_, c) = connected(&server, ClientMessage::Join { game: 1 });
+        assert_eq!(last(&c), Some(error("game 1 is full")));
+    }
+
+    #[test]
+    fn only_logged_in_players_take_seats() {
+        let server = Server::new();
+        let (client, messages) = server.connect();
+        server.handle(client, ClientMessage::Create);
+        assert_eq!(
+            last(&messages),
+            Some(error(
+                "log in first: register a name, or send the token you were given"
+            ))
+        );
+        let login = ClientMessage::Login {
+            token: "guess".to_string(),
+        };
+        server.handle(client, login);
+        assert_eq!(last(&messages), Some(error("that token isn't anyone's")));
+        let (_, _, _) = registered(&server, "bob");
+        let name = "bob".to_string();
+        server.handle(client, ClientMessage::Register { name });
+        assert_eq!(last(&messages), Some(error("bob is taken")));
+        let name = "computer".to_string();
+        server.handle(client, ClientMessage::Register { name });
+        assert_eq!(
+            last(&messages),
+            Some(error("names are up to 20 letters, digits, - and _"))
+        );
+    }
+
+    #[test]
+    fn players_are_not_paired_with_themselves() {
+        let server = Server::new();
+        let (a, a_messages, account) = registered(&server, "alice");
+        server.handle(a, ClientMessage::QuickMatch { computer: false });
+        let (again, messages) = server.connect();
+        server.handle(again, ClientMessage::Login { token: account });
+        server.handle(again, ClientMessage::QuickMatch { computer: false });
+        assert_eq!(seat(&a_messages), Some((1, Player::A)));
+        messages.try_recv().unwrap();
+        assert_eq!(seat(&messages), Some((2, Player::A)));
+    }
+
+    #[test]
+    fn spectators_see_the_game_but_cannot_play() {
+        let server = Server::new();
+        let (a, _) = quick_match(&server);
+        let (b, _) = quick_match(&server);
+        server.handle(a, move_to(Location::new(0, 0, 0)));
+        let (_, lobby) = connected(&server, ClientMessage::List);
+        assert_eq!(
+            lobby.try_recv(),
+            Ok(ServerMessage::Games {
+                open: vec![],
+                running: vec![1]
+            })
+        );
+
+        let (watcher, messages) = connected(&server, ClientMessage::Watch { game: 1 });
+        assert_eq!(messages.try_recv(), Ok(ServerMessage::Watching { game: 1 }));
+        let moves = vec![Location::new(0, 0, 0)];
+        assert_eq!(messages.try_recv(), Ok(ServerMessage::History { moves }));
+        let joined = server.game(1).unwrap();
+        assert_eq!(messages.try_recv(), Ok(ServerMessage::State(joined)));
+        server.handle(watcher, move_to(Location::new(1, 1, 1)));
+        assert_eq!(last(&messages), Some(error("you're only watching")));
+        assert_eq!(server.game(1).unwrap().turn(), 1);
+
+        server.handle(b, move_to(Location::new(2, 2, 2)));
+        let after = ServerMessage::State(server.game(1).unwrap());
+        assert_eq!(last(&messages), Some(after));
+        let (_, lost) = connected(&server, ClientMessage::Watch { game: 9 });
+        assert_eq!(last(&lost), Some(error("there's no game 9")));
+    }
+
+    #[test]
+    fn spectators_are_told_when_everyone_leaves() {
+        let server = Server::new();
+        let (a, _) = connected(&server, ClientMessage::Create);
+        let (_, messages) = connected(&server, ClientMessage::Watch { game: 1 });
+        server.disconnect(a);
+        assert_eq!(last(&messages), Some(error("everyone left game 1")));
+        assert_eq!(server.game(1), None);
+    }
+
+    #[test]
+    fn the_computer_steps_in_when_no_one_turns_up() {
+        let server = Server::with_config(ServerConfig {
+            match_timeout: Duration::from_millis(10),
+            computer_depth: 1,
+            ..ServerConfig::default()
+        });
+        let (a, messages) = connected(&server, ClientMessage::QuickMatch { computer: true });
+        thread::sleep(Duration::from_millis(100));
+        server.handle(a, move_to(Location::new(0, 0, 0)));
+        let replied = std::iter::from_fn(|| messages.recv_timeout(Duration::from_secs(5)).ok())
+            .filter_map(|message| match message {
+                ServerMessage::State(state) => Some(state),
+                _ => None,
+            })
+            .any(|state| state.turn() == 2);
+        assert!(replied);
+    }
+
+    #[test]
+    fn a_player_whose_time_runs_out_loses() {
+        let server = Server::with_config(ServerConfig {
+            clock: Some("0.2+1".parse().unwrap()),
+            ..ServerConfig::default()
+        });
+        let (a, a_messages) = quick_match(&server);
+        let (b, b_messages) = quick_match(&server);
+        let clock = |messages: &Receiver<ServerMessage>| {
+            messages.try_iter().find_map(|message| match message {
+                ServerMessage::Clock(clock) => Some(clock),
+                _ => None,
+            })
+        };
+        let started = clock(&b_messages).expect("B wasn't sent the clock");
+        assert_eq!(started.left(Player::B), Duration::from_millis(200));
+        clock(&a_messages).expect("A wasn't told the clock started");
+
+        server.handle(a, move_to(Location::new(0, 0, 0)));
+        let after = clock(&b_messages).expect("the clock wasn't sent with the move");
+        assert!(after.left(Player::A) > Duration::from_secs(1));
+
+        thread::sleep(Duration::from_millis(400));
+        let status = GamePlayStatus::Win {
+            player: Player::A,
+            reason: WinReason::Timeout,
+        };
+        assert_eq!(last(&a_messages), Some(ServerMessage::GameOver { status }));
+        assert_eq!(server.game(1).unwrap().status, status);
+        server.handle(b, move_to(Location::new(1, 0, 0)));
+        assert_eq!(last(&b_messages), Some(error("the game is over")));
+    }
+
+    #[test]
+    fn offers_are_answered_by_the_other_player() {
+        let server = Server::new();
+        let (a, a_messages) = quick_match(&server);
+        let (b, b_messages) = quick_match(&server);
+        let offer = |offer| ClientMessage::Offer { offer };
+        server.handle(a, offer(Offer::Takeback));
+        let refused = PlaceErr::NothingToTakeBack.to_string();
+        assert_eq!(last(&a_messages), Some(error(&refused)));
+
+        server.handle(a, move_to(Location::new(0, 0, 0)));
+        server.handle(a, offer(Offer::Takeback));
+        let offered = ServerMessage::Offered {
+            offer: Offer::Takeback,
+            by: Player::A,
+        };
+        assert_eq!(last(&b_messages), Some(offered));
+        server.handle(a, offer(Offer::Draw));
+        let pending = MoveError::OfferPending.to_string();
+        assert_eq!(last(&a_messages), Some(error(&pending)));
+        server.handle(a, ClientMessage::Accept);
+        assert_eq!(
+            last(&a_messages),
+            Some(error("you can't answer your own offer"))
+        );
+        server.handle(b, ClientMessage::Accept);
+        assert_eq!(server.game(1), Some(TTTTState::new()));
+        assert_eq!(server.moves(1), Some(vec![]));
+
+        server.handle(a, offer(Offer::Draw));
+        server.handle(b, ClientMessage::Decline);
+        let declined = ServerMessage::Declined {
+            offer: Offer::Draw,
+            by: Player::B,
+        };
+        assert_eq!(last(&a_messages), Some(declined));
+        server.handle(b, ClientMessage::Accept);
+        assert_eq!(last(&b_messages), Some(error("there's no offer to answer")));
+
+        server.handle(b, offer(Offer::Draw));
+        server.handle(a, ClientMessage::Accept);
+        let agreed = GamePlayStatus::Draw {
+            reason: DrawReason::Agreement,
+        };
+        assert_eq!(
+            last(&b_messages),
+            Some(ServerMessage::GameOver { status: agreed })
+        );
+    }
+
+    #[test]
+    fn resigning_ends_the_game_and_the_computer_gives_moves_back() {
+        let server = Server::with_config(ServerConfig {
+            match_timeout: Duration::ZERO,
+            computer_depth: 1,
+            ..ServerConfig::default()
+        });
+        let (a, messages) = connected(&server, ClientMessage::QuickMatch { computer: true });
+        thread::sleep(Duration::from_millis(100));
+        server.handle(a, move_to(Location::new(0, 0, 0)));
+        let replied = || {
+            std::iter::from_fn(|| messages.recv_timeout(Duration::from_secs(5)).ok())
+                .any(|message| matches!(message, ServerMessage::State(state) if state.turn() == 2))
+        };
+        assert!(replied());
+        server.handle(
+            a,
+            ClientMessage::Offer {
+                offer: Offer::Takeback,
+            },
+        );
+        assert_eq!(server.game(1), Some(TTTTState::new()));
+        server.handle(a, ClientMessage::Offer { offer: Offer::Draw });
+        assert_eq!(
+            last(&messages),
+            Some(ServerMessage::Declined {
+                offer: Offer::Draw,
+                by: Player::B
+            })
+        );
+
+        server.handle(a, ClientMessage::Resign);
+        let resigned = GamePlayStatus::Win {
+            player: Player::B,
+            reason: WinReason::Resignation,
+        };
+        assert_eq!(
+            last(&messages),
+            Some(ServerMessage::GameOver { status: resigned })
+        );
+        server.handle(a, ClientMessage::Resign);
+        assert_eq!(last(&messages), Some(error("the game is over")));
+    }
+
+    #[test]
+    fn everyone_in_a_game_can_chat() {
+        let server = Server::new();
+        let (a, a_messages) = quick_match(&server);
+        let (_, b_messages) = quick_match(&server);
+        let (watcher, watching) = connected(&server, ClientMessage::Watch { game: 1 });
+        let say = |text: &str| ClientMessage::Chat {
+            text: text.to_string(),
+        };
+        let said = |from: &str, text: &str| ServerMessage::Chat {
+            from: from.to_string(),
+            text: text.to_string(),
+        };
+
+        server.handle(a, say("  good luck "));
+        let from_a = said("player0", "good luck");
+        assert_eq!(last(&b_messages), Some(from_a.clone()));
+        assert_eq!(last(&watching), Some(from_a.clone()));
+        server.handle(watcher, say("go B!"));
+        assert_eq!(last(&a_messages), Some(said("player2", "go B!")));
+        let (_, late) = connected(&server, ClientMessage::Watch { game: 1 });
+        let replayed: Vec<_> = late
+            .try_iter()
+            .filter(|message| matches!(message, ServerMessage::Chat { .. }))
+            .collect();
+        assert_eq!(replayed, vec![from_a, said("player2", "go B!")]);
+
+        let (outsider, refused) = connected(&server, ClientMessage::List);
+        server.handle(outsider, say("hello?"));
+        assert_eq!(last(&refused), Some(error("you aren't in a game")));
+        let (anonymous, refused) = server.connect();
+        server.handle(anonymous, say("hello?"));
+        assert_eq!(last(&refused), Some(error("log in to chat")));
+        let too_long = "a".repeat(CHAT_LENGTH + 1);
+        for text in [too_long.as_str(), " ", "one\ntwo"] {
+            server.handle(a, say(text));
+            assert_eq!(
+                last(&a_messages),
+                Some(error("chat messages are one line of up to 200 characters"))
+            );
+        }
+        for _ in 1..CHAT_BURST {
+            server.handle(a, say("spam"));
+        }
+        server.handle(a, say("spam"));
+        assert_eq!(
+            last(&a_messages),
+            Some(error("slow down: up to 5 messages every 10 seconds"))
+        );
+    }
+
+    #[test]
+    fn games_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("tttt-test-{}.db", new_token()));
+        let start = || Server::with_store(ServerConfig::default(), Store::open(&path).unwrap());
+        let server = start().unwrap();
+        let (a, a_messages, account) = registered(&server, "alice");
+        server.handle(a, ClientMessage::QuickMatch { computer: false });
+        let Ok(ServerMessage::Welcome { token, .. }) = a_messages.try_recv() else {
+            panic!("A wasn't welcomed");
+        };
+        let (b, _, _) = registered(&server, "bob");
+        server.handle(b, ClientMessage::QuickMatch { computer: false });
+        server.handle(a, move_to(Location::new(0, 0, 0)));
+        let text = "see you after the restart".to_string();
+        server.handle(b, ClientMessage::Chat { text: text.clone() });
+        connected(&server, ClientMessage::Create);
+        drop(server);
+
+        let server = start().unwrap();
+        let (again, refused) = server.connect();
+        let name = "alice".to_string();
+        server.handle(again, ClientMessage::Register { name });
+        assert_eq!(last(&refused), Some(error("alice is taken")));
+        let (a, messages) = server.connect();
+        server.handle(a, ClientMessage::Login { token: account });
+        server.handle(a, ClientMessage::Resume { token });
+        messages.try_recv().unwrap();
+        assert_eq!(seat(&messages), Some((1, Player::A)));
+        let moves = vec![Location::new(0, 0, 0)];
+        assert_eq!(messages.try_recv(), Ok(ServerMessage::History { moves }));
+        let from = "bob".to_string();
+        assert_eq!(messages.try_recv(), Ok(ServerMessage::Chat { from, text }));
+        let (c, _) = connected(&server, ClientMessage::Create);
+        assert_eq!(server.lock().playing[&c], 3);
+
+        let games = server.games_of("bob").unwrap().unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].player(Player::A), Some("alice"));
+        assert_eq!(games[0].moves, vec![Location::new(0, 0, 0)]);
+        assert!(Server::new().games_of("bob").is_none());
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,419 @@
+//! Keeping the server's players and games in an SQLite database, so they
+//! outlive the server. A game's moves are kept in coordinate notation, as
+//! `history` sends them, and its position as the JSON the WebSocket sends:
+//!
+//! ```text
+//! id | quick | player_a | player_b | moves   | state         | result | abandoned | started_at | updated_at
+//!  3 |     1 | alice    | bob      | a11 b32 | {"board":...} |        |         0 | 1760000000 | 1760000042
+//! ```
+//!
+//! `result` is `A`, `B` or `draw` once the game is over. What was said in
+//! each game is kept line by line in the `chat` table. Times are seconds
+//! since the Unix epoch.
+
+use super::protocol::GameId;
+use crate::{
+    common::Location,
+    logic::{GamePlayStatus, Player, TTTTState},
+};
+use rusqlite::{params, types::Type, Connection, OptionalExtension, Row};
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub use rusqlite::Error as StoreError;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS players (
+    name TEXT PRIMARY KEY,
+    token TEXT NOT NULL UNIQUE,
+    registered_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS games (
+    id INTEGER PRIMARY KEY,
+    quick INTEGER NOT NULL,
+    player_a TEXT,
+    player_b TEXT,
+    token_a TEXT,
+    token_b TEXT,
+    moves TEXT NOT NULL,
+    state TEXT NOT NULL,
+    result TEXT,
+    abandoned INTEGER NOT NULL DEFAULT 0,
+    started_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS games_by_player_a ON games (player_a);
+CREATE INDEX IF NOT EXISTS games_by_player_b ON games (player_b);
+CREATE TABLE IF NOT EXISTS chat (
+    game INTEGER NOT NULL,
+    sender TEXT NOT NULL,
+    text TEXT NOT NULL,
+    sent_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS chat_by_game ON chat (game);
+";
+
+const COLUMNS: &str = "id, quick, player_a, player_b, token_a, token_b, moves, state, \
+abandoned, started_at, updated_at";
+
+/// Something said in a game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatLine {
+    pub from: String,
+    pub text: String,
+    pub sent_at: u64,
+}
+
+impl ChatLine {
+    /// A line said just now.
+    pub fn new(from: &str, text: &str) -> Self {
+        Self {
+            from: from.to_string(),
+            text: text.to_string(),
+            sent_at: now(),
+        }
+    }
+}
+
+/// A game as it's kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRecord {
+    pub id: GameId,
+    /// Started by a quick match.
+    pub quick: bool,
+    /// The name in each seat that's taken.
+    pub players: Vec<(Player, String)>,
+    /// The session token each human seat can be resumed with.
+    pub tokens: Vec<(Player, String)>,
+    pub moves: Vec<Location>,
+    pub state: TTTTState,
+    /// Everyone left before it was over.
+    pub abandoned: bool,
+    /// What was said, in order. `save` leaves it alone; lines are added
+    /// with `add_chat`.
+    pub chat: Vec<ChatLine>,
+    /// Set when the game is first saved.
+    pub started_at: u64,
+    /// Set whenever the game is saved.
+    pub updated_at: u64,
+}
+
+impl GameRecord {
+    pub fn player(&self, player: Player) -> Option<&str> {
+        seat(&self.players, player)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(self.state.status, GamePlayStatus::Playing(_))
+    }
+}
+
+fn seat(seats: &[(Player, String)], player: Player) -> Option<&str> {
+    seats
+        .iter()
+        .find(|(p, _)| *p == player)
+        .map(|(_, name)| name.as_str())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// The `result` column for a game in `status`.
+fn result(status: GamePlayStatus) -> Option<&'static str> {
+    match status {
+        GamePlayStatus::Playing(_) => None,
+        GamePlayStatus::Draw { .. } => Some("draw"),
+        _ => status.winner().map(|player| match player {
+            Player::A => "A",
+            Player::B => "B",
+        }),
+    }
+}
+
+fn bad_column(column: usize, e: impl std::error::Error + Send + Sync + 'static) -> StoreError {
+    StoreError::FromSqlConversionFailure(column, Type::Text, Box::new(e))
+}
+
+fn read_game(row: &Row) -> rusqlite::Result<GameRecord> {
+    let mut players = vec![];
+    let mut tokens = vec![];
+    for (player, name_column, token_column) in [(Player::A, 2, 4), (Player::B, 3, 5)] {
+        if let Some(name) = row.get::<_, Option<String>>(name_column)? {
+            players.push((player, name));
+        }
+        if let Some(token) = row.get::<_, Option<String>>(token_column)? {
+            tokens.push((player, token));
+        }
+    }
+    let moves: String = row.get(6)?;
+    let state: String = row.get(7)?;
+    Ok(GameRecord {
+        id: row.get(0)?,
+        quick: row.get(1)?,
+        players,
+        tokens,
+        moves: moves
+            .split_whitespace()
+            .map(|word| word.parse().map_err(|e| bad_column(6, e)))
+            .collect::<Result<_, _>>()?,
+        state: serde_json::from_str(&state).map_err(|e| bad_column(7, e))?,
+        abandoned: row.get(8)?,
+        chat: vec![],
+        started_at: row.get(9)?,
+        updated_at: row.get(10)?,
+    })
+}
+
+/// An open database.
+#[derive(Debug)]
+pub struct Store {
+    connection: Connection,
+}
+
+impl Store {
+    /// Opens the database at `path`, creating it if need be.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// A database that's forgotten when it's dropped.
+    pub fn in_memory() -> Result<Self, StoreError> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, StoreError> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    pub fn add_account(&self, name: &str, token: &str) -> Result<(), StoreError> {
+        self.connection.execute(
+            "INSERT INTO players (name, token, registered_at) VALUES (?1, ?2, ?3)",
+            params![name, token, now()],
+        )?;
+        Ok(())
+    }
+
+    /// Every registered name, with its login token.
+    pub fn accounts(&self) -> Result<Vec<(String, String)>, StoreError> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT name, token FROM players ORDER BY registered_at")?;
+        let accounts = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        accounts.collect()
+    }
+
+    /// Saves `game` as it is now. Its start and update times are filled in
+    /// here, so the ones passed are ignored.
+    pub fn save(&self, game: &GameRecord) -> Result<(), StoreError> {
+        let moves: Vec<String> = game.moves.iter().map(Location::to_string).collect();
+        let state = serde_json::to_string(&game.state).expect("states always serialize");
+        let now = now();
+        self.connection.execute(
+            "INSERT INTO games (id, quick, player_a, player_b, token_a, token_b, moves, state, \
+             result, abandoned, started_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?11)
+             ON CONFLICT (id) DO UPDATE SET
+                 player_a = ?3, player_b = ?4, token_a = ?5, token_b = ?6, moves = ?7,
+                 state = ?8, result = ?9, abandoned = ?10, updated_at = ?11",
+            params![
+                game.id,
+                game.quick,
+                game.player(Player::A),
+                game.player(Player::B),
+                seat(&game.tokens, Player::A),
+                seat(&game.tokens, Player::B),
+                moves.join(" "),
+                state,
+                result(game.state.status),
+                game.abandoned,
+                now,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Marks game `id` as left by everyone before it was over.
+    pub fn abandon(&self, id: GameId) -> Result<(), StoreError> {
+        self.connection.execute(
+            "UPDATE games SET abandoned = 1, token_a = NULL, token_b = NULL, updated_at = ?2
+             WHERE id = ?1",
+            params![id, now()],
+        )?;
+        Ok(())
+    }
+
+    pub fn add_chat(&self, id: GameId, line: &ChatLine) -> Result<(), StoreError> {
+        self.connection.execute(
+            "INSERT INTO chat (game, sender, text, sent_at) VALUES (?1, ?2, ?3, ?4)",
+            params![id, line.from, line.text, line.sent_at],
+        )?;
+        Ok(())
+    }
+
+    fn chat(&self, id: GameId) -> Result<Vec<ChatLine>, StoreError> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT sender, text, sent_at FROM chat WHERE game = ?1 ORDER BY rowid")?;
+        let lines = statement.query_map([id], |row| {
+            Ok(ChatLine {
+                from: row.get(0)?,
+                text: row.get(1)?,
+                sent_at: row.get(2)?,
+            })
+        })?;
+        lines.collect()
+    }
+
+    fn with_chat(&self, mut game: GameRecord) -> Result<GameRecord, StoreError> {
+        game.chat = self.chat(game.id)?;
+        Ok(game)
+    }
+
+    pub fn game(&self, id: GameId) -> Result<Option<GameRecord>, StoreError> {
+        self.connection
+            .query_row(
+                &format!("SELECT {} FROM games WHERE id = ?1", COLUMNS),
+                [id],
+                read_game,
+            )
+            .optional()?
+            .map(|game| self.with_chat(game))
+            .transpose()
+    }
+
+    /// Every game `name` has played in, oldest first.
+    pub fn games_of(&self, name: &str) -> Result<Vec<GameRecord>, StoreError> {
+        self.games_where("player_a = ?1 OR player_b = ?1", [name])
+    }
+
+    /// The games that were still being played, or waiting for a player,
+    /// when they were last saved.
+    pub fn unfinished(&self) -> Result<Vec<GameRecord>, StoreError> {
+        self.games_where("result IS NULL AND abandoned = 0", [])
+    }
+
+    fn games_where(
+        &self,
+        condition: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<GameRecord>, StoreError> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT {} FROM games WHERE {} ORDER BY id",
+            COLUMNS, condition
+        ))?;
+        let games = statement.query_map(params, read_game)?;
+        games.map(|game| self.with_chat(game?)).collect()
+    }
+
+    /// The highest game id handed out so far.
+    pub fn last_game_id(&self) -> Result<GameId, StoreError> {
+        self.connection
+            .query_row("SELECT COALESCE(MAX(id), 0) FROM games", [], |row| {
+                row.get(0)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: GameId, players: &[(Player, &str)]) -> GameRecord {
+        GameRecord {
+            id,
+            quick: false,
+            players: players
+                .iter()
+                .map(|(player, name)| (*player, name.to_string()))
+                .collect(),
+            tokens: vec![(Player::A, format!("token{}", id))],
+            moves: vec![],
+            state: TTTTState::new(),
+            abandoned: false,
+            chat: vec![],
+            started_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn games_are_saved_and_found_by_player() {
+        let store = Store::in_memory().unwrap();
+        assert_eq!(store.last_game_id().unwrap(), 0);
+        let mut first = record(1, &[(Player::A, "alice"), (Player::B, "bob")]);
+        store.save(&first).unwrap();
+        store
+            .save(&record(2, &[(Player::A, "carol"), (Player::B, "bob")]))
+            .unwrap();
+        store.save(&record(3, &[(Player::A, "carol")])).unwrap();
+
+        for loc in [Location::new(0, 0, 0), Location::new(1, 2, 0)] {
+            first.state.play(loc).unwrap();
+            first.moves.push(loc);
+        }
+        store.save(&first).unwrap();
+        let saved = store.game(1).unwrap().unwrap();
+        assert_eq!(saved.moves, first.moves);
+        assert_eq!(saved.state, first.state);
+        assert_eq!(saved.player(Player::B), Some("bob"));
+        assert_eq!(saved.tokens, first.tokens);
+        assert!(saved.started_at <= saved.updated_at && saved.started_at > 0);
+
+        let ids = |games: Vec<GameRecord>| games.iter().map(|g| g.id).collect::<Vec<_>>();
+        assert_eq!(ids(store.games_of("bob").unwrap()), vec![1, 2]);
+        assert_eq!(ids(store.games_of("carol").unwrap()), vec![2, 3]);
+        assert!(store.games_of("dave").unwrap().is_empty());
+        assert_eq!(store.last_game_id().unwrap(), 3);
+        assert_eq!(store.game(4).unwrap(), None);
+    }
+
+    #[test]
+    fn finished_and_abandoned_games_are_not_unfinished() {
+        let store = Store::in_memory().unwrap();
+        let mut won = record(1, &[(Player::A, "alice"), (Player::B, "bob")]);
+        won.state.status = GamePlayStatus::line(Player::B);
+        store.save(&won).unwrap();
+        store.save(&record(2, &[(Player::A, "alice")])).unwrap();
+        store.save(&record(3, &[(Player::A, "bob")])).unwrap();
+        store.abandon(3).unwrap();
+
+        let unfinished = store.unfinished().unwrap();
+        assert_eq!(unfinished.len(), 1);
+        assert_eq!(unfinished[0].id, 2);
+        let abandoned = store.game(3).unwrap().unwrap();
+        assert!(abandoned.abandoned && abandoned.tokens.is_empty());
+        assert!(store.game(1).unwrap().unwrap().is_finished());
+    }
+
+    #[test]
+    fn chat_is_kept_with_its_game() {
+        let store = Store::in_memory().unwrap();
+        store.save(&record(1, &[(Player::A, "alice")])).unwrap();
+        store.save(&record(2, &[(Player::A, "bob")])).unwrap();
+        let lines = [ChatLine::new("alice", "hi"), ChatLine::new("bob", "hello")];
+        for line in &lines {
+            store.add_chat(1, line).unwrap();
+        }
+        store.save(&record(1, &[(Player::A, "alice")])).unwrap();
+        assert_eq!(store.game(1).unwrap().unwrap().chat, lines);
+        assert_eq!(store.games_of("alice").unwrap()[0].chat, lines);
+        assert!(store.game(2).unwrap().unwrap().chat.is_empty());
+    }
+
+    #[test]
+    fn names_are_only_registered_once() {
+        let store = Store::in_memory().unwrap();
+        store.add_account("alice", "1234").unwrap();
+        assert!(store.add_account("alice", "5678").is_err());
+        assert_eq!(
+            store.accounts().unwrap(),
+            vec![("alice".to_string(), "1234".to_string())]
+        );
+    }
+}
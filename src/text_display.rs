@@ -0,0 +1,68 @@
+//! Drawing the board as text, for the terminal frontends.
+
+use crate::{
+    common::Location,
+    logic::{Board, Player},
+};
+
+const GAP: &str = "   ";
+
+pub fn symbol(spot: Option<Player>) -> char {
+    match spot {
+        Some(Player::A) => 'A',
+        Some(Player::B) => 'B',
+        None => '.',
+    }
+}
+
+/// The four layers side by side, bottom layer first, with the highest row
+/// at the top so that `I` (up) matches the 3D view.
+pub fn render_board(board: &Board) -> String {
+    let mut lines = vec![
+        (0..4)
+            .map(|z| format!("{:<9}", format!(" layer {}", z + 1)))
+            .collect::<Vec<_>>()
+            .join(GAP),
+        ["  a b c d"; 4].join(GAP),
+    ];
+    for y in (0..4).rev() {
+        let layers: Vec<String> = (0..4)
+            .map(|z| {
+                let row: Vec<String> = (0..4)
+                    .map(|x| symbol(board.at(Location::new(x, y, z))).to_string())
+                    .collect();
+                format!("{} {}", y + 1, row.join(" "))
+            })
+            .collect();
+        lines.push(layers.join(GAP));
+    }
+    lines
+        .iter()
+        .map(|l| l.trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+        + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_are_drawn_side_by_side() {
+        let mut board = Board::new();
+        board.place(Player::A, "a11".parse().unwrap()).unwrap();
+        board.place(Player::B, "d42".parse().unwrap()).unwrap();
+        board.place(Player::A, "b34".parse().unwrap()).unwrap();
+
+        let expected = [
+            " layer 1     layer 2     layer 3     layer 4",
+            "  a b c d     a b c d     a b c d     a b c d",
+            "4 . . . .   4 . . . B   4 . . . .   4 . . . .",
+            "3 . . . .   3 . . . .   3 . . . .   3 . A . .",
+            "2 . . . .   2 . . . .   2 . . . .   2 . . . .",
+            "1 A . . .   1 . . . .   1 . . . .   1 . . . .",
+        ];
+        assert_eq!(render_board(&board), expected.join("\n") + "\n");
+    }
+}
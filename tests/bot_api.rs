@@ -0,0 +1,132 @@
+//! Bots playing over plain HTTP against a server on localhost.
+
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+use tic_tac_toc_toe::net::{
+    server::{Server, ServerConfig},
+    store::Store,
+};
+
+/// A server keeping its games in memory, and its bot API's address.
+fn start_server() -> (Server, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let server = Server::with_store(ServerConfig::default(), Store::in_memory().unwrap()).unwrap();
+    let serving = server.clone();
+    thread::spawn(move || serving.serve_bot_api(listener));
+    (server, addr)
+}
+
+fn send(addr: &str, method: &str, path: &str, token: Option<&str>, body: &str) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let auth = token
+        .map(|token| format!("Authorization: Bearer {}\r\n", token))
+        .unwrap_or_default();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        method,
+        path,
+        addr,
+        auth,
+        body.len(),
+        body
+    )
+    .unwrap();
+    stream
+}
+
+/// The status and body of the answer to a request.
+fn request(addr: &str, method: &str, path: &str, token: Option<&str>, body: &str) -> (u16, String) {
+    let mut answer = String::new();
+    send(addr, method, path, token, body)
+        .read_to_string(&mut answer)
+        .unwrap();
+    let status = answer[9..12].parse().unwrap();
+    let (_, body) = answer.split_once("\r\n\r\n").unwrap();
+    (status, body.to_string())
+}
+
+fn json(answer: (u16, String)) -> (u16, Value) {
+    (answer.0, serde_json::from_str(&answer.1).unwrap())
+}
+
+#[test]
+fn two_bots_play_a_game_over_http() {
+    let (server, addr) = start_server();
+    let register = |name| {
+        let (status, account) = json(request(&addr, "POST", "/register", None, name));
+        assert_eq!(status, 201);
+        account["token"].as_str().unwrap().to_string()
+    };
+    let tokens = [register("first"), register("second")];
+    for token in &tokens {
+        let (status, _) = json(request(&addr, "POST", "/quickmatch", Some(token), ""));
+        assert_eq!(status, 200);
+    }
+
+    let stream = send(&addr, "GET", "/games/1/stream", None, "");
+    let mut updates = BufReader::new(stream).lines().map(Result::unwrap);
+    assert_eq!(updates.next().unwrap(), "HTTP/1.1 200 OK");
+    let mut updates = updates.skip_while(|line| !line.is_empty()).skip(1);
+    assert_eq!(
+        serde_json::from_str::<Value>(&updates.next().unwrap()).unwrap(),
+        json!({ "type": "watching", "game": 1 })
+    );
+
+    // Seats taken over HTTP hold no connection open.
+    assert_eq!(server.stats().clients, 1);
+
+    let (status, refused) = json(request(
+        &addr,
+        "POST",
+        "/games/1/move",
+        Some(&tokens[0]),
+        r#"{"x": 9, "y": 0, "z": 0}"#,
+    ));
+    assert_eq!(status, 400, "{}", refused);
+
+    let moves = ["a11", "a12", "b11", "b12", "c11", "c12", "d11"];
+    for (i, loc) in moves.iter().enumerate() {
+        let body = format!("{} {}", loc, i);
+        let (status, game) = json(request(
+            &addr,
+            "POST",
+            "/games/1/move",
+            Some(&tokens[i % 2]),
+            &body,
+        ));
+        assert_eq!(status, 200, "{}", game);
+        assert_eq!(game["moves"].as_array().unwrap().len(), i + 1);
+    }
+    let (status, body) = request(&addr, "GET", "/games/1?format=notation", None, "");
+    assert_eq!(status, 200);
+    assert_eq!(
+        body,
+        "AAAA............/BBB............./................/................ -"
+    );
+    let (status, refused) = json(request(
+        &addr,
+        "POST",
+        "/games/1/move",
+        Some(&tokens[1]),
+        "a44",
+    ));
+    assert_eq!(
+        (status, refused),
+        (409, json!({ "error": "the game is over" }))
+    );
+
+    let last = updates.last().unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(&last).unwrap(),
+        json!({ "type": "game_over", "status": { "Win": { "player": "A", "reason": "Line" } } })
+    );
+    // Once it's over, the bots' seats are given up and the game's only kept.
+    assert_eq!(server.games(), (vec![], vec![]));
+    assert_eq!(server.stats().clients, 0);
+}
@@ -0,0 +1,101 @@
+//! Drives the `engine` binary the way a controller would.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Command, Stdio},
+    thread,
+};
+use tic_tac_toc_toe::{
+    engine::{EngineWorker, ExternalEngine, Response},
+    logic::{
+        computer_player::Eval,
+        search::Limits,
+        tournament::{play_game, Agent, SearchAgent},
+        GamePlayStatus, TTTTState,
+    },
+};
+
+const ENGINE: &str = env!("CARGO_BIN_EXE_engine");
+
+#[test]
+fn the_engine_introduces_itself_and_finds_the_winning_move() {
+    let mut engine = ExternalEngine::start(ENGINE, Limits::depth(2)).unwrap();
+    assert_eq!(engine.name(), "tttt-engine");
+    engine.sync().unwrap();
+
+    let mut state = TTTTState::new();
+    for loc in ["a11", "a12", "b11", "b12", "c11", "c12"] {
+        state.play(loc.parse().unwrap()).unwrap();
+    }
+    assert_eq!(
+        engine.best_move(&state).unwrap(),
+        Some("d11".parse().unwrap())
+    );
+}
+
+#[test]
+fn a_worker_answers_in_the_background_or_says_why_not() {
+    let mut state = TTTTState::new();
+    for loc in ["a11", "a12", "b11", "b12", "c11", "c12"] {
+        state.play(loc.parse().unwrap()).unwrap();
+    }
+    let answer = |worker: EngineWorker| {
+        worker.ask(&state);
+        loop {
+            if let Some(answer) = worker.answer() {
+                return answer;
+            }
+            thread::yield_now();
+        }
+    };
+    let worker = EngineWorker::start(ENGINE.to_string(), Limits::depth(2));
+    assert_eq!(
+        answer(worker),
+        (state.clone(), Ok(Some("d11".parse().unwrap())))
+    );
+
+    let worker = EngineWorker::start("no-such-engine".to_string(), Limits::depth(2));
+    let (_, failed) = answer(worker);
+    assert!(failed
+        .unwrap_err()
+        .starts_with("couldn't start no-such-engine"));
+}
+
+#[test]
+fn an_external_engine_can_play_a_whole_game() {
+    let mut engine = ExternalEngine::start(ENGINE, Limits::depth(1)).unwrap();
+    let mut searcher = SearchAgent {
+        name: "heuristic".to_string(),
+        eval: Eval,
+        limits: Limits::depth(1),
+    };
+    let opening = ["b22".parse().unwrap(), "c33".parse().unwrap()];
+    let status = play_game(&mut engine, &mut searcher, &opening);
+    assert!(!matches!(status, GamePlayStatus::Playing(_)));
+}
+
+#[test]
+fn stop_still_answers_with_a_move() {
+    let mut child = Command::new(ENGINE)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+
+    writeln!(stdin, "position startpos moves a11").unwrap();
+    writeln!(stdin, "go movetime 60000").unwrap();
+    writeln!(stdin, "stop").unwrap();
+    let best = stdout
+        .lines()
+        .map(|line| line.unwrap().parse::<Response>())
+        .find_map(|response| match response {
+            Ok(Response::BestMove(best)) => Some(best),
+            _ => None,
+        });
+    assert!(matches!(best, Some(Some(loc)) if loc != "a11".parse().unwrap()));
+
+    writeln!(stdin, "quit").unwrap();
+    assert!(child.wait().unwrap().success());
+}
@@ -0,0 +1,91 @@
+//! Finds a `server` process on loopback the way a client on the LAN would.
+
+use std::{
+    net::{TcpListener, UdpSocket},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+use tic_tac_toc_toe::net::{
+    client::Connection,
+    discovery::{discover, Host},
+    protocol::{ClientMessage, ServerMessage},
+};
+
+const SERVER: &str = env!("CARGO_BIN_EXE_server");
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Kills the server when the test ends, however it ends.
+struct Running(Child);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.kill().ok();
+        self.0.wait().ok();
+    }
+}
+
+fn free_tcp_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn free_udp_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[test]
+fn clients_find_the_server_and_its_open_games() {
+    let (port, ws_port, discovery_port) = (free_tcp_port(), free_tcp_port(), free_udp_port());
+    let _server = Running(
+        Command::new(SERVER)
+            .args(["--host", "127.0.0.1"])
+            .args(["--port", &port.to_string()])
+            .args(["--ws-port", &ws_port.to_string()])
+            .args(["--discovery-port", &discovery_port.to_string()])
+            .args(["--bot-api-port", "0"])
+            .args(["--admin-port", "0"])
+            .args(["--database", ":memory:"])
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap(),
+    );
+
+    let started = Instant::now();
+    let mut host = loop {
+        if let Ok(connection) = Connection::connect(("127.0.0.1", port)) {
+            break connection;
+        }
+        assert!(started.elapsed() < TIMEOUT, "the server never started");
+        thread::sleep(Duration::from_millis(20));
+    };
+    let name = "host".to_string();
+    host.send(&ClientMessage::Register { name }).unwrap();
+    host.send(&ClientMessage::Create).unwrap();
+    assert!(matches!(
+        host.recv_timeout(TIMEOUT).unwrap(),
+        ServerMessage::Registered { .. }
+    ));
+    assert!(matches!(
+        host.recv_timeout(TIMEOUT).unwrap(),
+        ServerMessage::Welcome { game: 1, .. }
+    ));
+
+    let target = format!("127.0.0.1:{}", discovery_port).parse().unwrap();
+    let hosts = discover(&[target], Duration::from_millis(500)).unwrap();
+    assert_eq!(
+        hosts,
+        vec![Host {
+            addr: format!("127.0.0.1:{}", port).parse().unwrap(),
+            open: vec![1],
+        }]
+    );
+}
@@ -0,0 +1,161 @@
+//! Two clients playing a whole game through a server on localhost.
+
+use std::{
+    net::TcpListener,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+use tic_tac_toc_toe::{
+    logic::{GamePlayStatus, Player, TTTTState},
+    net::{
+        client::Connection,
+        protocol::{ClientMessage, ServerMessage},
+        server::Server,
+    },
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+static NEXT_NAME: AtomicUsize = AtomicUsize::new(0);
+
+fn start_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || Server::new().serve_tcp(listener));
+    addr
+}
+
+/// Registers a new name, returning the token to log in with.
+fn register(connection: &mut Connection) -> String {
+    let name = format!("player{}", NEXT_NAME.fetch_add(1, Ordering::Relaxed));
+    connection.send(&ClientMessage::Register { name }).unwrap();
+    let ServerMessage::Registered { token, .. } = connection.recv_timeout(TIMEOUT).unwrap() else {
+        panic!("couldn't register");
+    };
+    token
+}
+
+/// Takes a seat, returning it and the token to resume with.
+fn quick_match(connection: &mut Connection) -> (Player, String) {
+    connection
+        .send(&ClientMessage::QuickMatch { computer: false })
+        .unwrap();
+    let ServerMessage::Welcome { player, token, .. } = connection.recv_timeout(TIMEOUT).unwrap()
+    else {
+        panic!("expected a welcome");
+    };
+    next_state(connection);
+    (player, token)
+}
+
+fn join(addr: &str) -> (Connection, Player) {
+    let mut connection = Connection::connect(addr).unwrap();
+    register(&mut connection);
+    let (player, _) = quick_match(&mut connection);
+    (connection, player)
+}
+
+fn play(connection: &mut Connection, loc: &str) {
+    connection
+        .send(&ClientMessage::Move {
+            at: loc.parse().unwrap(),
+            seq: None,
+        })
+        .unwrap();
+}
+
+/// Waits for the next state, which both players get after every move.
+fn next_state(connection: &Connection) -> TTTTState {
+    match connection.recv_timeout(TIMEOUT).unwrap() {
+        ServerMessage::State(state) => state,
+        message => panic!("expected the state, got {:?}", message),
+    }
+}
+
+#[test]
+fn two_clients_play_a_game_to_the_end() {
+    let addr = start_server();
+    let (mut a, player_a) = join(&addr);
+    let (mut b, player_b) = join(&addr);
+    assert_eq!((player_a, player_b), (Player::A, Player::B));
+
+    let moves = ["a11", "a12", "b11", "b12", "c11", "c12", "d11"];
+    for (i, loc) in moves.iter().enumerate() {
+        let mover = if i % 2 == 0 { &mut a } else { &mut b };
+        play(mover, loc);
+        let (seen_by_a, seen_by_b) = (next_state(&a), next_state(&b));
+        assert_eq!(seen_by_a, seen_by_b);
+        assert_eq!(seen_by_a.turn(), i + 1);
+    }
+    for connection in [&a, &b] {
+        assert_eq!(
+            connection.recv_timeout(TIMEOUT).unwrap(),
+            ServerMessage::GameOver {
+                status: GamePlayStatus::line(Player::A)
+            }
+        );
+    }
+}
+
+#[test]
+fn moves_out_of_turn_are_refused() {
+    let addr = start_server();
+    let (mut a, _) = join(&addr);
+    let (mut b, _) = join(&addr);
+
+    play(&mut b, "a11");
+    assert_eq!(
+        b.recv_timeout(TIMEOUT).unwrap(),
+        ServerMessage::Error {
+            message: "it isn't your turn".to_string()
+        }
+    );
+    play(&mut a, "a11");
+    let state = next_state(&b);
+    assert_eq!(state.board.at("a11".parse().unwrap()), Some(Player::A));
+    assert_eq!(state.status, GamePlayStatus::Playing(Player::B));
+}
+
+#[test]
+fn a_dropped_client_resumes_its_game() {
+    let addr = start_server();
+    let mut a = Connection::connect(addr.as_str()).unwrap();
+    let account = register(&mut a);
+    let (_, token) = quick_match(&mut a);
+    let (mut b, _) = join(&addr);
+    play(&mut a, "a11");
+    next_state(&a);
+    next_state(&b);
+    drop(a);
+    play(&mut b, "a12");
+    next_state(&b);
+
+    // The server may not have noticed the old connection close yet.
+    let a = loop {
+        let mut a = Connection::connect(addr.as_str()).unwrap();
+        let token = token.clone();
+        a.send(&ClientMessage::Login {
+            token: account.clone(),
+        })
+        .unwrap();
+        a.send(&ClientMessage::Resume { token }).unwrap();
+        let ServerMessage::LoggedIn { .. } = a.recv_timeout(TIMEOUT).unwrap() else {
+            panic!("couldn't log in");
+        };
+        match a.recv_timeout(TIMEOUT).unwrap() {
+            ServerMessage::Welcome { player, .. } => {
+                assert_eq!(player, Player::A);
+                break a;
+            }
+            _ => thread::sleep(Duration::from_millis(20)),
+        }
+    };
+    assert_eq!(
+        a.recv_timeout(TIMEOUT).unwrap(),
+        ServerMessage::History {
+            moves: vec!["a11".parse().unwrap(), "a12".parse().unwrap()]
+        }
+    );
+    assert_eq!(next_state(&a).turn(), 2);
+}
@@ -0,0 +1,110 @@
+//! The JSON protocol over WebSockets, as a browser client would use it.
+
+use serde_json::{json, Value};
+use std::{
+    net::{TcpListener, TcpStream},
+    thread,
+};
+use tic_tac_toc_toe::net::server::Server;
+use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+fn start_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || Server::new().serve_websocket(listener));
+    format!("ws://{}", addr)
+}
+
+fn send(socket: &mut Socket, message: Value) {
+    socket.send(Message::text(message.to_string())).unwrap();
+}
+
+fn receive(socket: &mut Socket) -> Value {
+    loop {
+        if let Message::Text(text) = socket.read().unwrap() {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+fn join(url: &str, name: &str) -> (Socket, Value) {
+    let (mut socket, _) = tungstenite::connect(url).unwrap();
+    send(&mut socket, json!({"type": "register", "name": name}));
+    let registered = receive(&mut socket);
+    assert_eq!(registered["type"], "registered");
+    assert!(registered["token"].is_string());
+    send(
+        &mut socket,
+        json!({"type": "quick_match", "computer": false}),
+    );
+    let welcome = receive(&mut socket);
+    assert_eq!(receive(&mut socket)["type"], "state");
+    (socket, welcome)
+}
+
+#[test]
+fn players_join_and_see_each_others_moves() {
+    let url = start_server();
+    let (mut a, welcome_a) = join(&url, "alice");
+    let (mut b, welcome_b) = join(&url, "bob");
+    for (welcome, player) in [(welcome_a, "A"), (welcome_b, "B")] {
+        assert_eq!(welcome["type"], "welcome");
+        assert_eq!(welcome["game"], 1);
+        assert_eq!(welcome["player"], player);
+        assert!(welcome["token"].is_string());
+    }
+
+    send(&mut a, json!({"type": "move", "x": 1, "y": 2, "z": 3}));
+    for socket in [&mut a, &mut b] {
+        let state = receive(socket);
+        assert_eq!(state["type"], "state");
+        assert_eq!(state["board"]["spots"][3][2][1], "A");
+        assert_eq!(state["status"], json!({"Playing": "B"}));
+    }
+}
+
+#[test]
+fn bad_messages_get_an_error() {
+    let url = start_server();
+    let (mut a, _) = join(&url, "carol");
+    let (mut b, _) = join(&url, "dave");
+
+    send(&mut b, json!({"type": "move", "x": 0, "y": 0, "z": 0}));
+    assert_eq!(
+        receive(&mut b),
+        json!({"type": "error", "message": "it isn't your turn"})
+    );
+    send(&mut a, json!({"type": "dance"}));
+    assert_eq!(receive(&mut a)["type"], "error");
+    // Spots off the board are turned away without hurting the server.
+    send(&mut a, json!({"type": "move", "x": 9, "y": 0, "z": 0}));
+    assert_eq!(receive(&mut a)["type"], "error");
+    send(&mut a, json!({"type": "move", "x": 0, "y": 0, "z": 0}));
+    assert_eq!(receive(&mut a)["type"], "state");
+}
+
+#[test]
+fn a_finished_game_is_announced() {
+    let url = start_server();
+    let (mut a, _) = join(&url, "erin");
+    let (mut b, _) = join(&url, "frank");
+
+    for x in 0..4 {
+        send(&mut a, json!({"type": "move", "x": x, "y": 0, "z": 0}));
+        receive(&mut a);
+        receive(&mut b);
+        if x < 3 {
+            send(&mut b, json!({"type": "move", "x": x, "y": 1, "z": 0}));
+            receive(&mut a);
+            receive(&mut b);
+        }
+    }
+    for socket in [&mut a, &mut b] {
+        assert_eq!(
+            receive(socket),
+            json!({"type": "game_over", "status": {"Win": {"player": "A", "reason": "Line"}}})
+        );
+    }
+}